@@ -0,0 +1,66 @@
+use tracing::Level;
+use std::env;
+use std::fs;
+use anyhow::{anyhow, Result};
+
+use m365::AuthToken;
+use m365::btsnoop::{read_btsnoop, AttExtractor, SessionDecoder};
+use m365::consts::Registers;
+
+/**
+ * Decode btsnoop_hci.log of Xiaomi app talking to scooter:
+ *
+ * cargo run --example btsnoop -- btsnoop_hci.log [.mi-token] [handle=register...]
+ *
+ * With token, login is followed and uart frames are decrypted. When log was captured without service
+ * discovery, give characteristic handles by hand, for example 0x000e=TX 0x0010=RX.
+ */
+fn parse_handle(arg: &str) -> Result<(u16, Registers)> {
+  let (handle, register) = arg.split_once('=').ok_or_else(|| anyhow!("Expected handle=register, got {}", arg))?;
+  let handle = u16::from_str_radix(handle.trim_start_matches("0x"), 16)?;
+  let register = match register.to_uppercase().as_str() {
+    "TX" => Registers::TX,
+    "RX" => Registers::RX,
+    "UPNP" => Registers::UPNP,
+    "AVDTP" => Registers::AVDTP,
+    _ => return Err(anyhow!("Unknown register {}, use TX, RX, UPNP or AVDTP", register))
+  };
+
+  Ok((handle, register))
+}
+
+fn main() -> Result<()> {
+  tracing_subscriber::fmt()
+    .with_max_level(Level::WARN)
+    .init();
+
+  let args: Vec<String> = env::args().skip(1).collect();
+  let Some(log_path) = args.first() else {
+    panic!("First argument is path to btsnoop_hci.log");
+  };
+
+  let mut token : Option<AuthToken> = None;
+  let mut extractor = AttExtractor::new();
+  for arg in &args[1..] {
+    if arg.contains('=') {
+      let (handle, register) = parse_handle(arg)?;
+      extractor = extractor.with_handle(handle, register);
+    } else {
+      let bytes = fs::read(arg)?;
+      token = Some(bytes.get(..12).ok_or_else(|| anyhow!("Token file {} is too short", arg))?.try_into()?);
+    }
+  }
+
+  let records = read_btsnoop(&fs::read(log_path)?)?;
+  let frames = extractor.extract(&records);
+  println!("{} hci packets, {} frames on mi registers", records.len(), frames.len());
+
+  let mut decoder = SessionDecoder::new(token);
+  for frame in &frames {
+    if let Some(decoded) = decoder.push(frame) {
+      println!("{}", decoded);
+    }
+  }
+
+  Ok(())
+}
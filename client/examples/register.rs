@@ -7,10 +7,10 @@ use tokio::io::{BufWriter, AsyncWriteExt};
 use tokio::fs::File;
 use pretty_hex::*;
 use std::env;
-use tracing_subscriber;
 use std::path::Path;
 use anyhow::Result;
 
+use m365::error::M365Error;
 use m365::{
   ScooterScanner, ScannerEvent,
   RegistrationRequest, RegistrationError,
@@ -23,26 +23,26 @@ async fn save_token(token : &AuthToken) -> Result<()> {
   let f = File::create(path).await?;
   {
     let mut writer = BufWriter::new(f);
-    writer.write(token).await?;
+    writer.write_all(token).await?;
     writer.flush().await?;
   }
   Ok(())
 }
 
 async fn register(device: &Peripheral) -> Result<()> {
-  let connection = ConnectionHelper::new(&device);
+  let connection = ConnectionHelper::new(device);
 
   loop {
     tracing::info!(">>> Press power button up to 5 seconds after beep!");
     connection.reconnect().await?;
-    let mut request = RegistrationRequest::new(&device).await?;
+    let mut request = RegistrationRequest::new(device).await?;
 
     match request.start().await {
       Ok(token) => {
         save_token(&token).await?;
         break;
       },
-      Err(RegistrationError::RestartNeeded) => {
+      Err(M365Error::Registration(RegistrationError::RestartNeeded)) => {
         tracing::debug!("Restarting...");
         continue;
       },
@@ -74,16 +74,14 @@ async fn main() -> Result<()> {
   let mut rx = scanner.start().await?;
 
   while let Some(event) = rx.recv().await {
-    match event {
-      ScannerEvent::DiscoveredScooter(scooter) => {
-        if scooter.addr == mac {
-          tracing::info!("Found your scooter, starting registration");
-          let device = scanner.peripheral(&scooter).await?;
-          register(&device).await?;
-          break;
-        } else {
-          tracing::info!("Found scooter nearby: {} with mac: {}", scooter.name.unwrap(), scooter.addr);
-        }
+    if let ScannerEvent::DiscoveredScooter(scooter) = event {
+      if scooter.addr == mac {
+        tracing::info!("Found your scooter, starting registration");
+        let device = scanner.peripheral(&scooter).await?;
+        register(&device).await?;
+        break;
+      } else {
+        tracing::info!("Found scooter nearby: {:?} with mac: {}", scooter.name, scooter.addr);
       }
     }
   }
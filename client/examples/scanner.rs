@@ -1,4 +1,3 @@
-use tracing_subscriber;
 use tracing::Level;
 use tracing_subscriber::fmt::format::FmtSpan;
 use anyhow::Result;
@@ -12,13 +11,20 @@ async fn main() -> Result<()> {
     .init();
 
   let scanner = ScooterScanner::new().await?;
+  tracing::info!("Scanning with adapter: {}", scanner.adapter());
   let mut rx = scanner.clone().start().await?;
 
   while let Some(event) = rx.recv().await {
     match event {
       ScannerEvent::DiscoveredScooter(scooter) => {
-        tracing::info!("Found scooter nearby: {} with mac: {}", scooter.name.unwrap(), scooter.addr);
+        tracing::info!("Found scooter nearby: {:?} ({:?}) with mac: {}", scooter.name, scooter.model(), scooter.addr);
         tracing::debug!("All devices: {:?}", scanner.devices().await);
+      },
+      ScannerEvent::Updated(scooter) => {
+        tracing::info!("Scooter {} rssi: {:?} dBm", scooter.addr, scooter.rssi);
+      },
+      ScannerEvent::Lost(scooter) => {
+        tracing::info!("Lost scooter: {}", scooter.addr);
       }
     }
   }
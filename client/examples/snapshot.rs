@@ -0,0 +1,42 @@
+use tracing::Level;
+use tracing_subscriber::fmt::format::FmtSpan;
+
+use btleplug::api::BDAddr;
+use std::env;
+use anyhow::Result;
+
+use m365::supervisor::{Backoff, BleConnector, Supervisor};
+use m365::{AuthToken, ScooterModel};
+
+/**
+ * Dump every ESC and BMS register of the scooter into json file:
+ * cargo run --example snapshot -- 00:1A:2B:3C:4D:5E snapshot.json [.mi-token]
+ */
+#[tokio::main(flavor = "multi_thread")]
+async fn main() -> Result<()> {
+  tracing_subscriber::fmt()
+    .with_max_level(Level::INFO)
+    .with_span_events(FmtSpan::CLOSE)
+    .init();
+
+  let args: Vec<String> = env::args().collect();
+  if args.len() < 3 {
+    panic!("Usage: snapshot <scooter mac> <output.json> [token file]");
+  }
+
+  let mac = BDAddr::from_str_delim(&args[1]).expect("Invalid mac address");
+  let token_path = args.get(3).map(String::as_str).unwrap_or(".mi-token");
+  let token : AuthToken = std::fs::read(token_path)?
+    .try_into()
+    .expect("Token file should have 12 bytes");
+
+  let mut supervisor = Supervisor::new(BleConnector::new(mac, token, ScooterModel::default()), Backoff::default());
+  let mut session = supervisor.establish().await;
+
+  tracing::info!("Reading registers, it takes a while");
+  let snapshot = session.register_snapshot().await?;
+  snapshot.save(&args[2])?;
+  tracing::info!("Saved {} ESC and {} BMS registers to {}", snapshot.esc.len(), snapshot.bms.len(), args[2]);
+
+  Ok(())
+}
@@ -0,0 +1,154 @@
+use crate::scanner::ScannerError;
+
+use btleplug::api::{BDAddr, Central, Manager as _};
+use btleplug::platform::{Adapter, Manager};
+#[cfg(target_os = "linux")]
+use bluez_async::BluetoothSession;
+use serde::Deserialize;
+use std::fmt;
+
+/**
+ * Host bluetooth adapter, as reported by bluez. Other platforms have no bluez: adapters are named
+ * hci0, hci1... in the order btleplug lists them, name is btleplug description and address is unknown.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct AdapterInfo {
+  /**
+   * Interface name like hci0
+   */
+  pub id: String,
+  pub address: BDAddr,
+  /**
+   * Friendly name (alias), defaults to host name
+   */
+  pub name: String,
+  pub powered: bool,
+}
+
+impl AdapterInfo {
+  /**
+   * Number after hci in interface name
+   */
+  pub fn index(&self) -> Option<u32> {
+    self.id.strip_prefix("hci").and_then(|index| index.parse().ok())
+  }
+}
+
+impl fmt::Display for AdapterInfo {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let state = if self.powered { "on" } else { "off" };
+    write!(f, "{} {} \"{}\" ({})", self.id, self.address, self.name, state)
+  }
+}
+
+/**
+ * Which adapter to scan with. In config it is written as index (adapter = 1), interface name
+ * (adapter = "hci1") or friendly name (adapter = "long-range"). First adapter is used when missing.
+ */
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum AdapterSelector {
+  #[default]
+  First,
+  Index(u32),
+  Name(String),
+}
+
+impl AdapterSelector {
+  /**
+   * Pick adapter from the list returned by list_adapters
+   */
+  pub fn select<'a>(&self, adapters: &'a [AdapterInfo]) -> Option<&'a AdapterInfo> {
+    match self {
+      AdapterSelector::First => adapters.first(),
+      AdapterSelector::Index(index) => adapters.iter().find(|adapter| adapter.index() == Some(*index)),
+      AdapterSelector::Name(name) => adapters.iter()
+        .find(|adapter| adapter.id == *name)
+        .or_else(|| adapters.iter().find(|adapter| adapter.name == *name))
+    }
+  }
+}
+
+impl fmt::Display for AdapterSelector {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      AdapterSelector::First => write!(f, "first adapter"),
+      AdapterSelector::Index(index) => write!(f, "hci{}", index),
+      AdapterSelector::Name(name) => write!(f, "{}", name),
+    }
+  }
+}
+
+/**
+ * List bluetooth adapters of this host
+ */
+#[cfg(target_os = "linux")]
+pub async fn list_adapters() -> Result<Vec<AdapterInfo>, ScannerError> {
+  let (_, session) = BluetoothSession::new().await?;
+
+  let mut adapters : Vec<AdapterInfo> = session.get_adapters().await?
+    .into_iter()
+    .map(|adapter| AdapterInfo {
+      id: adapter.id.to_string(),
+      address: BDAddr::from(<[u8; 6]>::from(adapter.mac_address)),
+      name: adapter.alias,
+      powered: adapter.powered,
+    })
+    .collect();
+
+  adapters.sort_by_key(|adapter| (adapter.index(), adapter.id.clone()));
+  Ok(adapters)
+}
+
+/**
+ * List bluetooth adapters of this host
+ */
+#[cfg(not(target_os = "linux"))]
+pub async fn list_adapters() -> Result<Vec<AdapterInfo>, ScannerError> {
+  let manager = Manager::new().await?;
+
+  let mut adapters = Vec::new();
+  for (index, adapter) in manager.adapters().await?.into_iter().enumerate() {
+    adapters.push(AdapterInfo {
+      id: format!("hci{}", index),
+      address: BDAddr::default(),
+      name: adapter.adapter_info().await?,
+      powered: true,
+    });
+  }
+
+  Ok(adapters)
+}
+
+/**
+ * Find btleplug adapter matching selector. When it is missing, error lists the adapters that exist.
+ */
+pub async fn find_adapter(manager: &Manager, selector: &AdapterSelector) -> Result<(Adapter, AdapterInfo), ScannerError> {
+  let adapters = list_adapters().await?;
+  if adapters.is_empty() {
+    return Err(ScannerError::MissingCentral)
+  }
+
+  let info = selector.select(&adapters)
+    .ok_or_else(|| ScannerError::AdapterNotFound { requested: selector.clone(), available: adapters.clone() })?;
+
+  for (index, adapter) in manager.adapters().await?.into_iter().enumerate() {
+    if is_same_adapter(&adapter, index, info).await? {
+      return Ok((adapter, info.clone()))
+    }
+  }
+
+  Err(ScannerError::AdapterNotFound { requested: selector.clone(), available: adapters })
+}
+
+#[cfg(target_os = "linux")]
+async fn is_same_adapter(adapter: &Adapter, _index: usize, info: &AdapterInfo) -> Result<bool, ScannerError> {
+  // btleplug describes adapter as "hci0 (modalias)"
+  let description = adapter.adapter_info().await?;
+  Ok(description.split_whitespace().next() == Some(info.id.as_str()))
+}
+
+#[cfg(not(target_os = "linux"))]
+async fn is_same_adapter(_adapter: &Adapter, index: usize, info: &AdapterInfo) -> Result<bool, ScannerError> {
+  Ok(info.index() == Some(index as u32))
+}
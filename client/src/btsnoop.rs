@@ -0,0 +1,576 @@
+use crate::consts::{MiCommands, Registers};
+use crate::error::{M365Error, Result};
+use crate::frame::{FrameDecoder, FrameKind};
+use crate::mi_crypto::{self, AuthToken, EncryptionKey, LoginKeychain, MiCryptoError, RandKey, UartCounters};
+use crate::ninebot;
+use crate::session::commands::{Attribute, Direction, ReadWrite};
+use crate::transport::hex_bytes::encode as hex;
+use crate::transport::{RecordedFrame, TrafficDirection};
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use uuid::Uuid;
+
+/*
+ * Offline decoder for btsnoop_hci.log captured by Android (Developer options > Enable Bluetooth HCI snoop log)
+ * while the Xiaomi app talks to scooter. Works in three steps:
+ *
+ * let records = read_btsnoop(&std::fs::read("btsnoop_hci.log")?)?;
+ * let frames = AttExtractor::new().extract(&records);   // ATT writes and notifications of mi registers
+ * let mut decoder = SessionDecoder::new(Some(token));
+ * for frame in &frames {
+ *   if let Some(decoded) = decoder.push(frame) { println!("{}", decoded); }
+ * }
+ *
+ * Extracted frames have the same format as Recorder files, so they can be replayed with ReplayTransport too.
+ */
+
+const BTSNOOP_MAGIC : &[u8; 8] = b"btsnoop\0";
+const BTSNOOP_HEADER_SIZE : usize = 16;
+const RECORD_HEADER_SIZE : usize = 24;
+
+/**
+ * Datalink types, H4 records start with packet type byte
+ */
+const DATALINK_HCI : u32 = 1001;
+const DATALINK_H4 : u32 = 1002;
+
+const H4_ACL : u8 = 0x02;
+const L2CAP_ATT : u16 = 0x0004;
+
+const ATT_READ_BY_TYPE_REQ : u8 = 0x08;
+const ATT_READ_BY_TYPE_RSP : u8 = 0x09;
+const ATT_WRITE_REQ : u8 = 0x12;
+const ATT_WRITE_CMD : u8 = 0x52;
+const ATT_NOTIFICATION : u8 = 0x1b;
+const ATT_INDICATION : u8 = 0x1d;
+
+/**
+ * Types of mi parcels sent by scooter during login
+ */
+const PARCEL_LOGIN_INFO : u8 = 0x0c;
+const PARCEL_LOGIN_KEY : u8 = 0x0d;
+
+/**
+ * GATT characteristic declaration, read during service discovery
+ */
+const GATT_CHARACTERISTIC : u16 = 0x2803;
+
+/**
+ * One HCI packet from the log
+ */
+#[derive(Debug, Clone)]
+pub struct BtsnoopRecord {
+  /**
+   * Microseconds since midnight, January 1st, 0 AD
+   */
+  pub timestamp_us: i64,
+  /**
+   * Sent by controller to host, for phone log it means sent by scooter
+   */
+  pub received: bool,
+  /**
+   * ACL data without H4 packet type, None for commands and events
+   */
+  pub acl: Option<Vec<u8>>,
+}
+
+/**
+ * Parse whole btsnoop file
+ */
+pub fn read_btsnoop(bytes: &[u8]) -> Result<Vec<BtsnoopRecord>> {
+  if bytes.len() < BTSNOOP_HEADER_SIZE || &bytes[0..8] != BTSNOOP_MAGIC {
+    return Err(M365Error::parse("btsnoop header", format!("{} is not btsnoop file", hex(&bytes[..bytes.len().min(BTSNOOP_HEADER_SIZE)]))))
+  }
+
+  let datalink = be_u32(&bytes[12..16]);
+  if datalink != DATALINK_H4 && datalink != DATALINK_HCI {
+    return Err(M365Error::parse("btsnoop datalink", format!("{} is not HCI", datalink)))
+  }
+
+  let mut records = Vec::new();
+  let mut offset = BTSNOOP_HEADER_SIZE;
+
+  while offset + RECORD_HEADER_SIZE <= bytes.len() {
+    let header = &bytes[offset..offset + RECORD_HEADER_SIZE];
+    let included = be_u32(&header[4..8]) as usize;
+    let flags = be_u32(&header[8..12]);
+    let timestamp_us = i64::from_be_bytes(header[16..24].try_into().unwrap());
+
+    let start = offset + RECORD_HEADER_SIZE;
+    let packet = bytes.get(start..start + included)
+      .ok_or_else(|| M365Error::parse("btsnoop record", format!("{} bytes at offset {}, file is truncated", included, offset)))?;
+    offset = start + included;
+
+    let acl = match datalink {
+      DATALINK_H4 => packet.split_first()
+        .filter(|(packet_type, _)| **packet_type == H4_ACL)
+        .map(|(_, acl)| acl.to_vec()),
+      _ => (flags & 0x02 == 0).then(|| packet.to_vec()) // Bit 1 is set for commands and events
+    };
+
+    records.push(BtsnoopRecord { timestamp_us, received: flags & 0x01 != 0, acl });
+  }
+
+  Ok(records)
+}
+
+/**
+ * Pulls ATT writes and notifications of mi registers out of HCI packets. Attribute handles are mapped to
+ * register uuids by watching GATT characteristic discovery. Phones cache discovery, when log doesn't
+ * contain it (no bond was removed before capture), handles can be given with with_handle.
+ */
+#[derive(Default)]
+pub struct AttExtractor {
+  /**
+   * Characteristic uuid by connection and value handle
+   */
+  handles: HashMap<(u16, u16), Uuid>,
+  /**
+   * Given by user, valid for all connections
+   */
+  known_handles: HashMap<u16, Uuid>,
+  /**
+   * Connections waiting for Read By Type response with characteristic declarations
+   */
+  discovering: HashSet<u16>,
+  /**
+   * L2CAP frames being reassembled, by connection and side
+   */
+  fragments: HashMap<(u16, bool), Vec<u8>>,
+  first_timestamp: Option<i64>,
+}
+
+impl AttExtractor {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /**
+   * Map attribute value handle to register, when log starts after service discovery
+   */
+  pub fn with_handle(mut self, handle: u16, reg: Registers) -> Self {
+    self.known_handles.insert(handle, reg.to_uuid());
+    self
+  }
+
+  pub fn extract(mut self, records: &[BtsnoopRecord]) -> Vec<RecordedFrame> {
+    records.iter().filter_map(|record| self.push(record)).collect()
+  }
+
+  /**
+   * Process next record, returns write or notification of mi register
+   */
+  pub fn push(&mut self, record: &BtsnoopRecord) -> Option<RecordedFrame> {
+    let acl = record.acl.as_ref()?;
+    if acl.len() < 4 {
+      return None
+    }
+
+    let connection = u16::from_le_bytes([acl[0], acl[1]]) & 0x0fff;
+    let continuation = (acl[1] >> 4) & 0x03 == 0x01;
+    let data = &acl[4..];
+
+    let key = (connection, record.received);
+    if continuation {
+      self.fragments.get_mut(&key)?.extend_from_slice(data);
+    } else {
+      self.fragments.insert(key, data.to_vec());
+    }
+
+    // Whole L2CAP frame: length(2) cid(2) payload(length)
+    let l2cap = self.fragments.get(&key)?;
+    if l2cap.len() < 4 || l2cap.len() < 4 + u16::from_le_bytes([l2cap[0], l2cap[1]]) as usize {
+      return None
+    }
+    let l2cap = self.fragments.remove(&key)?;
+
+    let length = u16::from_le_bytes([l2cap[0], l2cap[1]]) as usize;
+    if u16::from_le_bytes([l2cap[2], l2cap[3]]) != L2CAP_ATT {
+      return None
+    }
+
+    let at_ms = (record.timestamp_us - *self.first_timestamp.get_or_insert(record.timestamp_us)) / 1000;
+    self.on_att(connection, at_ms.max(0) as u64, &l2cap[4..4 + length])
+  }
+
+  fn on_att(&mut self, connection: u16, at_ms: u64, pdu: &[u8]) -> Option<RecordedFrame> {
+    let (opcode, params) = pdu.split_first()?;
+
+    let direction = match *opcode {
+      ATT_READ_BY_TYPE_REQ => {
+        let uuid_type = params.get(4..6).map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]));
+        if params.len() == 6 && uuid_type == Some(GATT_CHARACTERISTIC) {
+          self.discovering.insert(connection);
+        } else {
+          self.discovering.remove(&connection);
+        }
+        return None
+      },
+      ATT_READ_BY_TYPE_RSP => {
+        if self.discovering.remove(&connection) {
+          self.on_characteristics(connection, params);
+        }
+        return None
+      },
+      ATT_WRITE_REQ | ATT_WRITE_CMD => TrafficDirection::Write,
+      ATT_NOTIFICATION | ATT_INDICATION => TrafficDirection::Notification,
+      _ => return None
+    };
+
+    if params.len() < 2 {
+      return None
+    }
+
+    let handle = u16::from_le_bytes([params[0], params[1]]);
+    let uuid = self.handles.get(&(connection, handle)).or_else(|| self.known_handles.get(&handle))?;
+    Registers::from_uuid(uuid)?; // Other characteristics (battery service...) are not interesting
+
+    Some(RecordedFrame { at_ms, direction, uuid: *uuid, data: params[2..].to_vec() })
+  }
+
+  /**
+   * Entries of characteristic declarations: handle(2) properties(1) value handle(2) uuid(2 or 16)
+   */
+  fn on_characteristics(&mut self, connection: u16, params: &[u8]) {
+    let Some((entry_size, entries)) = params.split_first() else { return };
+    let entry_size = *entry_size as usize;
+    if entry_size != 7 && entry_size != 21 {
+      return
+    }
+
+    for entry in entries.chunks_exact(entry_size) {
+      let value_handle = u16::from_le_bytes([entry[3], entry[4]]);
+      let uuid = match entry_size {
+        7 => Uuid::from_u128(bluetooth_base_uuid(u16::from_le_bytes([entry[5], entry[6]]))),
+        _ => {
+          let mut bytes : [u8; 16] = entry[5..21].try_into().unwrap();
+          bytes.reverse(); // ATT sends uuid little endian
+          Uuid::from_bytes(bytes)
+        }
+      };
+
+      tracing::debug!("Characteristic {} has handle 0x{:04x}", uuid, value_handle);
+      self.handles.insert((connection, value_handle), uuid);
+    }
+  }
+}
+
+/**
+ * Uart message decoded from frame: direction, read/write, attribute and data
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct UartMessage {
+  pub direction: u8,
+  pub read_write: u8,
+  pub attribute: u8,
+  pub data: Vec<u8>,
+}
+
+impl UartMessage {
+  fn from_bytes(bytes: &[u8]) -> Option<Self> {
+    let (header, data) = bytes.split_at_checked(3)?;
+    Some(Self { direction: header[0], read_write: header[1], attribute: header[2], data: data.to_vec() })
+  }
+}
+
+impl fmt::Display for UartMessage {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match Direction::from_value(self.direction) {
+      Some(direction) => write!(f, "{:?}", direction)?,
+      None => write!(f, "0x{:02x}", self.direction)?
+    }
+    match ReadWrite::from_value(self.read_write) {
+      Some(read_write) => write!(f, " {:?}", read_write)?,
+      None => write!(f, " 0x{:02x}", self.read_write)?
+    }
+    match Attribute::from_value(self.attribute) {
+      Some(attribute) => write!(f, " {:?} (0x{:02x})", attribute, self.attribute)?,
+      None => write!(f, " 0x{:02x}", self.attribute)?
+    }
+
+    write!(f, " [{}]", hex(&self.data))
+  }
+}
+
+/**
+ * One frame of the log with whatever could be decoded from it
+ */
+#[derive(Debug, Clone)]
+pub struct DecodedFrame {
+  pub at_ms: u64,
+  pub direction: TrafficDirection,
+  pub register: Registers,
+  pub raw: Vec<u8>,
+  /**
+   * Uart command or reply, when frame was complete and could be decrypted
+   */
+  pub message: Option<UartMessage>,
+  /**
+   * Login steps, mi commands and decryption problems
+   */
+  pub note: Option<String>,
+}
+
+impl fmt::Display for DecodedFrame {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let arrow = match self.direction {
+      TrafficDirection::Write => "->",
+      TrafficDirection::Notification => "<-",
+    };
+
+    write!(f, "{:>9.3}s {} {:<5} {}", self.at_ms as f64 / 1000.0, arrow, format!("{:?}", self.register), hex(&self.raw))?;
+    if let Some(message) = &self.message {
+      write!(f, "\n{:>19}{}", "", message)?;
+    }
+    if let Some(note) = &self.note {
+      write!(f, "\n{:>19}{}", "", note)?;
+    }
+
+    Ok(())
+  }
+}
+
+/**
+ * Mi parcel being received (chunks of 18 bytes prefixed by chunk index)
+ */
+#[derive(Default)]
+struct Parcel {
+  parcel_type: u8,
+  expected_chunks: u16,
+  data: Vec<u8>,
+  chunks: u16,
+}
+
+/**
+ * Follows login on AVDTP to recover session keys, then decrypts uart frames written to TX and notified on RX.
+ * Without token (or before login is seen) only plain 55AA and ninebot 5AA5 frames are decoded.
+ */
+pub struct SessionDecoder {
+  token: Option<AuthToken>,
+  keys: Option<LoginKeychain>,
+  rand_key: Option<RandKey>,
+  remote_key: Option<Vec<u8>>,
+  sending_key: bool,
+  app_parcel: Vec<u8>,
+  scooter_parcel: Option<Parcel>,
+  tx: FrameDecoder,
+  rx: FrameDecoder,
+  tx_counters: UartCounters,
+  rx_counters: UartCounters,
+}
+
+impl SessionDecoder {
+  pub fn new(token: Option<AuthToken>) -> Self {
+    Self {
+      token,
+      keys: None,
+      rand_key: None,
+      remote_key: None,
+      sending_key: false,
+      app_parcel: Vec::new(),
+      scooter_parcel: None,
+      tx: FrameDecoder::new(),
+      rx: FrameDecoder::new(),
+      tx_counters: UartCounters::default(),
+      rx_counters: UartCounters::default(),
+    }
+  }
+
+  /**
+   * Keys recovered from login, None until whole login was seen
+   */
+  pub fn keys(&self) -> Option<&LoginKeychain> {
+    self.keys.as_ref()
+  }
+
+  /**
+   * Decode next extracted frame. Uart frame split into more writes/notifications is returned with the last one.
+   */
+  pub fn push(&mut self, frame: &RecordedFrame) -> Option<DecodedFrame> {
+    let register = Registers::from_uuid(&frame.uuid)?;
+
+    let mut decoded = DecodedFrame {
+      at_ms: frame.at_ms,
+      direction: frame.direction,
+      register,
+      raw: frame.data.clone(),
+      message: None,
+      note: None,
+    };
+
+    match register {
+      Registers::AVDTP | Registers::UPNP => decoded.note = self.on_auth(frame),
+      Registers::TX | Registers::RX => {
+        let (message, note) = self.on_uart(frame);
+        decoded.message = message;
+        decoded.note = note;
+      },
+      _ => {}
+    }
+
+    Some(decoded)
+  }
+
+  fn on_auth(&mut self, frame: &RecordedFrame) -> Option<String> {
+    let data = frame.data.as_slice();
+
+    if let Some(command) = mi_command(data) {
+      if frame.direction == TrafficDirection::Write && data == MiCommands::CMD_SEND_KEY.to_bytes().as_slice() {
+        // New login, keys of previous one are gone
+        self.sending_key = true;
+        self.app_parcel.clear();
+        self.scooter_parcel = None;
+        self.rand_key = None;
+        self.remote_key = None;
+        self.keys = None;
+      }
+      return Some(command)
+    }
+
+    match frame.direction {
+      TrafficDirection::Write => self.on_app_parcel(data),
+      TrafficDirection::Notification => self.on_scooter_parcel(data),
+    }
+  }
+
+  fn on_app_parcel(&mut self, data: &[u8]) -> Option<String> {
+    if !self.sending_key || !is_parcel_chunk(data) {
+      return None
+    }
+
+    self.app_parcel.extend_from_slice(&data[2..]);
+    if self.app_parcel.len() < 16 {
+      return None
+    }
+
+    self.sending_key = false;
+    self.rand_key = self.app_parcel[..16].try_into().ok();
+    Some(format!("app random key: {}", hex(&self.app_parcel[..16])))
+  }
+
+  fn on_scooter_parcel(&mut self, data: &[u8]) -> Option<String> {
+    // Parcel header: 00 00 00 type frames(2)
+    if data.len() == 6 && data[..3] == [0, 0, 0] {
+      let expected_chunks = u16::from_le_bytes([data[4], data[5]]);
+      self.scooter_parcel = Some(Parcel { parcel_type: data[3], expected_chunks, ..Parcel::default() });
+      return Some(format!("scooter parcel 0x{:02x} of {} chunks", data[3], expected_chunks))
+    }
+
+    if !is_parcel_chunk(data) {
+      return None
+    }
+
+    let parcel = self.scooter_parcel.as_mut()?;
+    parcel.data.extend_from_slice(&data[2..]);
+    parcel.chunks += 1;
+    if parcel.chunks < parcel.expected_chunks {
+      return None
+    }
+
+    let parcel = self.scooter_parcel.take()?;
+    match parcel.parcel_type {
+      PARCEL_LOGIN_KEY => {
+        let note = format!("scooter random key: {}", hex(&parcel.data));
+        self.remote_key = Some(parcel.data);
+        Some(note)
+      },
+      PARCEL_LOGIN_INFO => Some(format!("scooter info: {}, {}", hex(&parcel.data), self.derive_keys(&parcel.data))),
+      _ => Some(format!("scooter parcel: {}", hex(&parcel.data)))
+    }
+  }
+
+  fn derive_keys(&mut self, remote_info: &[u8]) -> String {
+    let (Some(token), Some(rand_key), Some(remote_key)) = (self.token, self.rand_key, self.remote_key.clone()) else {
+      return "no token or login not complete, uart stays encrypted".to_owned()
+    };
+
+    let mut rand_key = rand_key;
+    let mut remote_key = remote_key;
+    let (_, expected_remote_info, keys) = mi_crypto::calc_login_did(&mut rand_key, &mut remote_key, &token);
+    let verified = expected_remote_info.as_slice() == remote_info;
+    self.keys = Some(keys);
+    self.tx.clear();
+    self.rx.clear();
+    self.tx_counters = UartCounters::default();
+    self.rx_counters = UartCounters::default();
+
+    match verified {
+      true => "token matches, session keys recovered".to_owned(),
+      false => "scooter info doesn't match token, decryption will likely fail".to_owned()
+    }
+  }
+
+  fn on_uart(&mut self, frame: &RecordedFrame) -> (Option<UartMessage>, Option<String>) {
+    let (decoder, counters, key) = match frame.direction {
+      TrafficDirection::Write => (&mut self.tx, &mut self.tx_counters, self.keys.as_ref().map(|keys| &keys.app)),
+      TrafficDirection::Notification => (&mut self.rx, &mut self.rx_counters, self.keys.as_ref().map(|keys| &keys.dev)),
+    };
+
+    decoder.push(&frame.data);
+    let Some(uart) = decoder.next_frame() else {
+      return (None, None)
+    };
+
+    let decoded = match FrameKind::from_header(&uart) {
+      Some(FrameKind::Encrypted) => match key {
+        Some(key) => decrypt_counted(key, counters, &uart)
+          .map(|mut message| { message.truncate(message.len().saturating_sub(4)); message }) // Skip random bytes
+          .map_err(|e| e.to_string()),
+        None => Err("encrypted, keys unknown".to_owned())
+      },
+      Some(FrameKind::Legacy) => mi_crypto::decode_uart(&uart).map_err(|e| e.to_string()),
+      Some(FrameKind::Ninebot) => ninebot::decode_frame(&uart).map_err(|e| e.to_string())
+        .and_then(|frame| frame.to_uart_message().map_err(|e| e.to_string())),
+      None => Err("unknown frame".to_owned())
+    };
+
+    match decoded {
+      Ok(message) => (UartMessage::from_bytes(&message), None),
+      Err(error) => (None, Some(format!("frame {}: {}", hex(&uart), error)))
+    }
+  }
+}
+
+/**
+ * Frames carry only lower 16 bits of counter, full one is followed per direction so captures longer
+ * than 65535 frames keep decrypting
+ */
+fn decrypt_counted(key: &EncryptionKey, counters: &mut UartCounters, uart: &[u8]) -> std::result::Result<Vec<u8>, MiCryptoError> {
+  let it = match mi_crypto::uart_counter(uart) {
+    Some(received) => counters.expected_rx(received)?,
+    None => 0 // Too short, decrypt tells why
+  };
+
+  let message = mi_crypto::decrypt_uart_at(key, uart, it)?;
+  counters.accept_rx(it);
+  Ok(message)
+}
+
+/**
+ * Short mi control commands exchanged on AVDTP and UPNP
+ */
+fn mi_command(data: &[u8]) -> Option<String> {
+  let commands = [
+    MiCommands::CMD_GET_INFO, MiCommands::CMD_SET_KEY, MiCommands::CMD_AUTH, MiCommands::CMD_LOGIN,
+    MiCommands::CMD_SEND_DATA, MiCommands::CMD_SEND_DID, MiCommands::CMD_SEND_KEY, MiCommands::CMD_SEND_INFO,
+    MiCommands::RCV_RDY, MiCommands::RCV_OK, MiCommands::RCV_AUTH_OK, MiCommands::RCV_AUTH_ERR,
+    MiCommands::RCV_LOGIN_OK, MiCommands::RCV_LOGIN_ERR
+  ];
+
+  commands.into_iter()
+    .find(|command| command.to_bytes() == data)
+    .map(|command| format!("{:?}", command).split_whitespace().next().unwrap_or_default().to_owned())
+}
+
+fn is_parcel_chunk(data: &[u8]) -> bool {
+  data.len() > 2 && data[0] > 0 && data[1] == 0
+}
+
+fn bluetooth_base_uuid(short: u16) -> u128 {
+  0x0000_0000_0000_1000_8000_0080_5f9b_34fb | ((short as u128) << 96)
+}
+
+fn be_u32(bytes: &[u8]) -> u32 {
+  u32::from_be_bytes(bytes[0..4].try_into().unwrap())
+}
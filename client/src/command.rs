@@ -0,0 +1,31 @@
+use crate::error::Result;
+use crate::session::{SessionHandle, TailLight};
+use crate::transport::Transport;
+use serde::Deserialize;
+
+/**
+Setting changed remotely, received as json on the command topic of the scooter:
+{"tail_light": "always"} (off, on_brake or always) or {"cruise": true}
+ */
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Command {
+    TailLight(TailLight),
+    Cruise(bool),
+}
+
+impl Command {
+    pub fn parse(payload: &[u8]) -> serde_json::Result<Self> {
+        serde_json::from_slice(payload)
+    }
+
+    /**
+    Sent with high priority, ahead of telemetry polls waiting for the session
+     */
+    pub async fn send<T: Transport + 'static>(self, session: &SessionHandle<T>) -> Result<()> {
+        match self {
+            Command::TailLight(mode) => session.set_tail_light(mode).await,
+            Command::Cruise(on) => session.set_cruise(on).await,
+        }
+    }
+}
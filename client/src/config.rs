@@ -1,8 +1,13 @@
-use anyhow::Result;
+use crate::adapter::AdapterSelector;
+use crate::fleet::AdapterShare;
+use crate::model::ScooterModel;
+use crate::supervisor::Backoff;
+use anyhow::{anyhow, Result};
 use lazy_static::lazy_static;
 use serde::Deserialize;
 use std::fs;
 use std::path::Path;
+use std::time::Duration;
 use toml;
 
 /**
@@ -18,12 +23,93 @@ pub struct Mqtt {
     pub reconnect_min: u64, //The minimum retry interval. Doubled on each failed retry. This has a resolution in seconds.
     pub reconnect_max: u64, //The maximum retry interval. Doubling stops here on failed retries. This has a resolution in seconds.
     pub send_interval: u64, // Frecuency for sending data to broker (seconds). More frecuency == More data consumption
+    pub bms_interval: Option<u64>, // Seconds between battery identity and lifetime counter readings, 600 when missing
+}
+
+impl Mqtt {
+    pub fn bms_interval(&self) -> Duration {
+        Duration::from_secs(self.bms_interval.unwrap_or(600))
+    }
 }
 
 #[derive(Debug, Deserialize)]
 pub struct Scooter {
     pub mac: String,
     pub token_file_path: String,
+    #[serde(default)]
+    pub model: ScooterModel, // Decides protocol family, m365 when missing
+    pub name: Option<String>, // Identity published with telemetry, mac address when missing
+    pub topic: Option<String>, // Telemetry topic, mqtt.topic when missing
+    pub device_topic: Option<String>, // Retained serial and firmware versions, telemetry topic + "/device/<name>" when missing
+    pub events_topic: Option<String>, // Errors and warnings appearing or clearing, telemetry topic + "/events/<name>" when missing
+    pub command_topic: Option<String>, // Remote settings (tail light, cruise) sent to the scooter, telemetry topic + "/command/<name>" when missing
+    pub poll_interval: Option<u64>, // Seconds between polls, mqtt.send_interval when missing
+    pub record: Option<String>, // Append raw bluetooth traffic to this file, to replay it when debugging firmware issues
+}
+
+impl Scooter {
+    pub fn name(&self) -> &str {
+        self.name.as_deref().unwrap_or(self.mac.trim())
+    }
+
+    pub fn topic<'a>(&'a self, mqtt: &'a Mqtt) -> &'a str {
+        self.topic.as_deref().unwrap_or(&mqtt.topic)
+    }
+
+    /**
+     Scooters of the fleet may share one topic, so the default keeps one retained message per scooter
+     */
+    pub fn device_topic(&self, mqtt: &Mqtt) -> String {
+        match &self.device_topic {
+            Some(topic) => topic.clone(),
+            None => format!("{}/device/{}", self.topic(mqtt), self.name()),
+        }
+    }
+
+    pub fn events_topic(&self, mqtt: &Mqtt) -> String {
+        match &self.events_topic {
+            Some(topic) => topic.clone(),
+            None => format!("{}/events/{}", self.topic(mqtt), self.name()),
+        }
+    }
+
+    pub fn command_topic(&self, mqtt: &Mqtt) -> String {
+        match &self.command_topic {
+            Some(topic) => topic.clone(),
+            None => format!("{}/command/{}", self.topic(mqtt), self.name()),
+        }
+    }
+
+    pub fn poll_interval(&self, mqtt: &Mqtt) -> Duration {
+        Duration::from_secs(self.poll_interval.unwrap_or(mqtt.send_interval))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct Bluetooth {
+    pub adapter: AdapterSelector, // hci index (1), interface name ("hci1") or adapter alias. First adapter when missing
+    pub max_connections: Option<usize>, // Scooters connected at the same time, others wait for their turn. Unlimited when missing
+    pub share_slot: u64, // Seconds one scooter keeps the adapter when connections are limited
+}
+
+impl Default for Bluetooth {
+    fn default() -> Self {
+        Self {
+            adapter: AdapterSelector::First,
+            max_connections: None,
+            share_slot: 60,
+        }
+    }
+}
+
+impl Bluetooth {
+    pub fn share(&self) -> AdapterShare {
+        match self.max_connections {
+            Some(max_connections) => AdapterShare::new(max_connections, Duration::from_secs(self.share_slot)),
+            None => AdapterShare::unlimited(),
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -32,11 +118,83 @@ pub struct Serial {
     pub baudrate: u32,
 }
 
+/**
+Retry policy used when the scooter can't be reached. Times have a resolution in seconds.
+ */
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct Reconnect {
+    pub initial_delay: f64, // Delay after first failed attempt. Multiplied by multiplier on each failed attempt.
+    pub max_delay: f64,
+    pub multiplier: f64,
+    pub jitter: f64, // Between 0 and 1. Randomizes each delay by this fraction.
+    pub park_after: u32, // Failed attempts in a row before assuming the scooter is switched off
+    pub park_for: f64, // How long to wait before scanning again when scooter is switched off
+}
+
+impl Default for Reconnect {
+    fn default() -> Self {
+        let backoff = Backoff::default();
+        Self {
+            initial_delay: backoff.initial.as_secs_f64(),
+            max_delay: backoff.max.as_secs_f64(),
+            multiplier: backoff.multiplier,
+            jitter: backoff.jitter,
+            park_after: backoff.park_after,
+            park_for: backoff.park_for.as_secs_f64(),
+        }
+    }
+}
+
+impl Reconnect {
+    /**
+    Durations must fit in Duration, so negative, nan and inf values are refused here instead of panicking later
+     */
+    fn validate(&self) -> Result<()> {
+        for (name, seconds) in [
+            ("initial_delay", self.initial_delay),
+            ("max_delay", self.max_delay),
+            ("park_for", self.park_for),
+        ] {
+            if Duration::try_from_secs_f64(seconds).is_err() {
+                return Err(anyhow!("Invalid reconnect.{}: {} is not a duration in seconds", name, seconds));
+            }
+        }
+
+        if !self.multiplier.is_finite() || self.multiplier < 1.0 {
+            return Err(anyhow!("Invalid reconnect.multiplier: {} must be a number of at least 1", self.multiplier));
+        }
+
+        if !(0.0..=1.0).contains(&self.jitter) {
+            return Err(anyhow!("Invalid reconnect.jitter: {} must be between 0 and 1", self.jitter));
+        }
+
+        Ok(())
+    }
+
+    pub fn backoff(&self) -> Backoff {
+        Backoff {
+            initial: Duration::from_secs_f64(self.initial_delay),
+            max: Duration::from_secs_f64(self.max_delay),
+            multiplier: self.multiplier,
+            jitter: self.jitter,
+            park_after: self.park_after,
+            park_for: Duration::from_secs_f64(self.park_for),
+        }
+    }
+}
+
 #[derive(Debug, Deserialize)]
 pub struct Config {
     pub mqtt: Mqtt,
-    pub scooter: Scooter,
+    pub scooter: Option<Scooter>, // Single scooter, same as a fleet of one
+    #[serde(default)]
+    pub scooters: Vec<Scooter>,
     pub serial: Serial,
+    #[serde(default)]
+    pub bluetooth: Bluetooth,
+    #[serde(default)]
+    pub reconnect: Reconnect,
 }
 
 //While LazyLock is in alpha, I will use the deprecated but functional lazy_static crate https://github.com/rust-lang-nursery/lazy-static.rs
@@ -51,10 +209,27 @@ impl Config {
             )
         });
 
-        let config: Config = toml::from_str(&config_string)?;
+        Self::parse(&config_string)
+    }
+
+    pub fn parse(config_string: &str) -> Result<Self> {
+        let config: Config = toml::from_str(config_string)?;
+
+        if config.fleet().is_empty() {
+            return Err(anyhow!("No scooter configured: add [scooter] or [[scooters]] section"));
+        }
+
+        config.reconnect.validate()?;
 
         Ok(config)
     }
+
+    /**
+    All monitored scooters, [scooter] first followed by [[scooters]]
+     */
+    pub fn fleet(&self) -> Vec<&Scooter> {
+        self.scooter.iter().chain(self.scooters.iter()).collect()
+    }
 }
 
 lazy_static! {
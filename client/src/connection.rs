@@ -1,4 +1,4 @@
-use anyhow::Result;
+use crate::error::{BluetoothContext, Result};
 use btleplug::api::Peripheral as _;
 use btleplug::platform::Peripheral;
 use std::time::Duration;
@@ -20,12 +20,17 @@ impl ConnectionHelper {
     /**
         Due to BlueZ issues the reconnection does not work well since the device appears to be busy.
     */
-    pub async fn connect(&self) -> Result<bool, btleplug::Error> {
+    pub async fn connect(&self) -> Result<bool> {
         // Connect to Bluetooth device
         tracing::debug!("Connecting to device.");
         let mut retries = NUM_RETRIES;
         while retries >= 0 {
-            if self.device.is_connected().await? {
+            if self
+                .device
+                .is_connected()
+                .await
+                .with_context(|| "checking connection")?
+            {
                 tracing::debug!("Connected to device");
                 //Return false to know there is an active connection
                 return Ok(false);
@@ -42,7 +47,7 @@ impl ConnectionHelper {
                     time::sleep(Duration::from_secs(1)).await;
                 }
 
-                Err(err) => return Err(err),
+                Err(err) => return Err(err).with_context(|| "connecting"),
             }
         }
 
@@ -50,7 +55,12 @@ impl ConnectionHelper {
     }
 
     pub async fn disconnect(&self) -> Result<bool> {
-        if !self.device.is_connected().await? {
+        if !self
+            .device
+            .is_connected()
+            .await
+            .with_context(|| "checking connection")?
+        {
             tracing::debug!("Already disconnected.");
             return Ok(true);
         }
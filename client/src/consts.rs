@@ -6,7 +6,7 @@ use pretty_hex::*;
 //Check protocol documentation to learn more.
 
 #[allow(non_camel_case_types)]
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Registers {
   /**
    * Universal Asynchronous Receiver and Transmitter
@@ -39,6 +39,11 @@ impl Registers {
 
     Uuid::parse_str(uuid).expect("Invalid uuid for register")
   }
+
+  pub fn from_uuid(uuid: &Uuid) -> Option<Self> {
+    [Self::UART, Self::TX, Self::RX, Self::AUTH, Self::UPNP, Self::AVDTP].into_iter()
+      .find(|reg| reg.to_uuid() == *uuid)
+  }
 }
 
 #[allow(non_camel_case_types)]
@@ -101,7 +106,7 @@ impl Debug for MiCommands {
       Self::RCV_LOGIN_ERR => write!(fmt, "RCV_LOGIN_ERR {}", self.to_bytes().hex_dump()),
       Self::CMD_LOGIN => write!(fmt, "CMD_LOGIN {}", self.to_bytes().hex_dump()),
       Self::CMD_SEND_KEY => write!(fmt, "CMD_SEND_KEY {}", self.to_bytes().hex_dump()),
-      Self::CMD_SEND_INFO => write!(fmt, "CMD_SEND_KEY {}", self.to_bytes().hex_dump()),
+      Self::CMD_SEND_INFO => write!(fmt, "CMD_SEND_INFO {}", self.to_bytes().hex_dump()),
     }
   }
 }
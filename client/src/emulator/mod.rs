@@ -0,0 +1,447 @@
+mod state;
+
+pub use state::ScooterState;
+
+use crate::consts::{MiCommands, Registers};
+use crate::mi_crypto::{self, AuthToken, LoginKeychain, RandKey, UartCounters, encrypt_uart, decrypt_uart_at, encode_uart, decode_uart, uart_counter};
+use crate::frame::{FrameDecoder, FrameKind};
+use crate::transport::ChannelPeer;
+use crate::ninebot::{self, NinebotFrame};
+
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use pretty_hex::*;
+use p256::EncodedPoint;
+use anyhow::{Result, anyhow};
+
+const MI_CHUNK_SIZE : usize = 18;
+const NOTIFICATION_SIZE : usize = 20;
+
+/**
+ * Parcel headers sent by scooter before mi parcels: [0, 0, 0, type, frames_lo, frames_hi]
+ */
+const PARCEL_DEVICE_INFO : u8 = 0x00;
+const PARCEL_PUBLIC_KEY : u8 = 0x03;
+const PARCEL_LOGIN_INFO : u8 = 0x0c;
+const PARCEL_LOGIN_KEY : u8 = 0x0d;
+
+/**
+ * Device id announced during registration (remote info without the first 4 bytes)
+ */
+const DEVICE_ID : &[u8; 20] = b"blt.3.16394t3g4lc00\0";
+
+/**
+ * Software M365 scooter. Plays the scooter side of ChannelTransport: registration (ECDH),
+ * login (HKDF) and encrypted 55AB uart replies served from ScooterState registers.
+ * With legacy firmware it skips all of that and answers plain 55AA frames instead,
+ * and as a ninebot scooter it answers plain 5AA5 frames.
+ *
+ * let (transport, peer) = ChannelTransport::pair();
+ * let emulator = ScooterEmulator::new(peer, ScooterState::default());
+ * let state = emulator.state(); // change it while the client is talking to the scooter
+ * tokio::spawn(emulator.run());
+ */
+pub struct ScooterEmulator {
+  peer: ChannelPeer,
+  state: Arc<Mutex<ScooterState>>,
+  token: Option<AuthToken>,
+  keys: Option<LoginKeychain>,
+  did_ct: Option<Vec<u8>>,
+  uart: FrameDecoder,
+  counters: UartCounters,
+  notification_size: usize,
+  realtime: Option<Instant>,
+  firmware: FrameKind, // Which uart framing emulated scooter speaks
+}
+
+impl ScooterEmulator {
+  pub fn new(peer: ChannelPeer, state: ScooterState) -> Self {
+    Self {
+      peer,
+      state: Arc::new(Mutex::new(state)),
+      token: None,
+      keys: None,
+      did_ct: None,
+      uart: FrameDecoder::new(),
+      counters: UartCounters::default(),
+      notification_size: NOTIFICATION_SIZE,
+      realtime: None,
+      firmware: FrameKind::Encrypted,
+    }
+  }
+
+  /**
+   * Behave like scooter with firmware older than 1.5: no login, plain 55AA uart frames
+   */
+  pub fn with_legacy_firmware(mut self) -> Self {
+    self.firmware = FrameKind::Legacy;
+    self
+  }
+
+  /**
+   * Behave like Ninebot/Segway scooter with plain firmware: no login, unencrypted 5AA5 uart frames
+   */
+  pub fn with_ninebot_firmware(mut self) -> Self {
+    self.firmware = FrameKind::Ninebot;
+    self
+  }
+
+  /**
+   * Scooter that is already registered with token, so client can login straight away
+   */
+  pub fn with_token(mut self, token: &AuthToken) -> Self {
+    self.token = Some(*token);
+    self
+  }
+
+  /**
+   * Max bytes per uart notification, real scooter uses 20
+   */
+  pub fn with_notification_size(mut self, size: usize) -> Self {
+    self.notification_size = size.max(1);
+    self
+  }
+
+  /**
+   * Advance the ride with wall clock time before every uart reply
+   */
+  pub fn with_realtime_ride(mut self) -> Self {
+    self.realtime = Some(Instant::now());
+    self
+  }
+
+  /**
+   * Client connected again over new transport. Like real scooter after short bluetooth drop,
+   * emulator keeps login keys and counters, so client can resume its session.
+   */
+  pub fn reconnect(mut self, peer: ChannelPeer) -> Self {
+    self.peer = peer;
+    self.uart.clear();
+    self
+  }
+
+  /**
+   * Shared handle to scooter state, it can be changed while emulator is running
+   */
+  pub fn state(&self) -> Arc<Mutex<ScooterState>> {
+    self.state.clone()
+  }
+
+  /**
+   * Token registered by last successful registration
+   */
+  pub fn token(&self) -> Option<AuthToken> {
+    self.token
+  }
+
+  /**
+   * Serve client until transport is dropped
+   */
+  pub async fn run(mut self) -> Result<Self> {
+    while let Some(write) = self.peer.recv().await {
+      let value = write.value;
+
+      if write.uuid == Registers::UPNP.to_uuid() {
+        self.on_upnp(&value).await?;
+      } else if write.uuid == Registers::AVDTP.to_uuid() {
+        self.on_avdtp(&value).await?;
+      } else if write.uuid == Registers::TX.to_uuid() {
+        self.on_uart(&value)?;
+      } else {
+        tracing::debug!("Emulator ignoring write to {}: {:?}", write.uuid, value.hex_dump());
+      }
+    }
+
+    Ok(self)
+  }
+
+  async fn on_upnp(&mut self, value: &[u8]) -> Result<()> {
+    if value == MiCommands::CMD_GET_INFO.to_bytes() {
+      let mut remote_info = vec![0x01, 0x00, 0x00, 0x00];
+      remote_info.extend_from_slice(DEVICE_ID);
+      self.send_mi_parcel(PARCEL_DEVICE_INFO, &remote_info).await?;
+    } else if value == MiCommands::CMD_AUTH.to_bytes() {
+      self.confirm_registration()?;
+    } else if value == MiCommands::CMD_LOGIN.to_bytes() || value == MiCommands::CMD_SET_KEY.to_bytes() {
+      tracing::debug!("Emulator: {:?}", value.hex_dump());
+    } else {
+      tracing::debug!("Emulator: unknown UPNP command {:?}", value.hex_dump());
+    }
+
+    Ok(())
+  }
+
+  async fn on_avdtp(&mut self, value: &[u8]) -> Result<()> {
+    if value == MiCommands::CMD_SEND_DATA.to_bytes() {
+      let client_key = self.receive_mi_parcel(value[4]).await?;
+      self.exchange_public_keys(&client_key).await?;
+    } else if value == MiCommands::CMD_SEND_DID.to_bytes() {
+      let did_ct = self.receive_mi_parcel(value[4]).await?;
+      if self.did_ct.as_ref() != Some(&did_ct) {
+        tracing::error!("Emulator: client sent invalid did");
+        self.did_ct = None;
+      }
+    } else if value == MiCommands::CMD_SEND_KEY.to_bytes() {
+      let client_rand_key = self.receive_mi_parcel(value[4]).await?;
+      self.login(client_rand_key).await?;
+    } else {
+      tracing::debug!("Emulator: unknown AVDTP command {:?}", value.hex_dump());
+    }
+
+    Ok(())
+  }
+
+  /**
+   * Registration, scooter side: read client public key, answer with ours and precompute did
+   */
+  async fn exchange_public_keys(&mut self, client_key: &[u8]) -> Result<()> {
+    let (secret_key, public_key) = mi_crypto::gen_key_pair();
+    let public_key = EncodedPoint::from(public_key);
+
+    let mut remote_info = vec![0x01, 0x00, 0x00, 0x00];
+    remote_info.extend_from_slice(DEVICE_ID);
+    let client_key = [&[0x04], client_key].concat();
+
+    let (did_ct, token) = mi_crypto::calc_did(&secret_key, &client_key, &remote_info);
+    self.did_ct = Some(did_ct);
+    self.token = Some(token);
+
+    self.send_mi_parcel(PARCEL_PUBLIC_KEY, &public_key.as_bytes()[1..]).await
+  }
+
+  fn confirm_registration(&mut self) -> Result<()> {
+    match self.did_ct.take() {
+      Some(_) => {
+        tracing::info!("Emulator: registered token {:?}", self.token.unwrap().hex_dump());
+        self.notify(&Registers::UPNP, &MiCommands::RCV_AUTH_OK.to_bytes())
+      },
+      None => {
+        self.token = None;
+        self.notify(&Registers::UPNP, &MiCommands::RCV_AUTH_ERR.to_bytes())
+      }
+    }
+  }
+
+  /**
+   * Login, scooter side: answer with our rand key and remote info, then validate client info
+   */
+  async fn login(&mut self, client_rand_key: Vec<u8>) -> Result<()> {
+    let token = match self.token {
+      Some(token) => token,
+      None => {
+        tracing::error!("Emulator: login without registration");
+        return self.notify(&Registers::UPNP, &MiCommands::RCV_LOGIN_ERR.to_bytes())
+      }
+    };
+
+    let mut client_rand_key = client_rand_key;
+    let mut rand_key : RandKey = mi_crypto::gen_rand_key();
+    let (info, expected_remote_info, keys) = mi_crypto::calc_login_did(&mut client_rand_key, &mut rand_key, &token);
+
+    self.send_mi_parcel(PARCEL_LOGIN_KEY, &rand_key).await?;
+    self.send_mi_parcel(PARCEL_LOGIN_INFO, &expected_remote_info).await?;
+
+    let command = self.next_write().await?;
+    if command != MiCommands::CMD_SEND_INFO.to_bytes() {
+      return Err(anyhow!("Emulator expected CMD_SEND_INFO, received: {:?}", command.hex_dump()))
+    }
+
+    let client_info = self.receive_mi_parcel(command[4]).await?;
+    if client_info != info {
+      tracing::error!("Emulator: client sent invalid login info");
+      return self.notify(&Registers::UPNP, &MiCommands::RCV_LOGIN_ERR.to_bytes())
+    }
+
+    self.keys = Some(keys);
+    self.uart.clear();
+    self.counters = UartCounters::default();
+    self.notify(&Registers::UPNP, &MiCommands::RCV_LOGIN_OK.to_bytes())
+  }
+
+  /**
+   * Collect uart chunks until whole 55AB frame is received, then answer it
+   */
+  fn on_uart(&mut self, value: &[u8]) -> Result<()> {
+    self.uart.push(value);
+
+    while let Some(frame) = self.uart.next_frame() {
+      if !frame.starts_with(&self.firmware.header()) {
+        tracing::debug!("Emulator: ignoring frame for other firmware {:?}", frame.hex_dump());
+        continue;
+      }
+
+      self.on_uart_frame(&frame)?;
+    }
+
+    Ok(())
+  }
+
+  fn on_uart_frame(&mut self, frame: &[u8]) -> Result<()> {
+    if self.firmware == FrameKind::Legacy {
+      let command = decode_uart(frame)?;
+
+      if let Some(reply) = self.handle_command(&command)? {
+        let bytes = encode_uart(&reply);
+        self.notify_uart(&bytes)?;
+      }
+
+      return Ok(())
+    }
+
+    if self.firmware == FrameKind::Ninebot {
+      let request = ninebot::decode_frame(frame)?;
+
+      if let Some(reply) = self.handle_command(&request.to_uart_message()?)? {
+        let frame = NinebotFrame {
+          src: request.dst,
+          dst: request.src,
+          cmd: ninebot::CMD_READ_REPLY,
+          arg: request.arg,
+          payload: reply[4..].to_vec() // skip xiaomi header
+        };
+
+        self.notify_uart(&frame.as_bytes())?;
+      }
+
+      return Ok(())
+    }
+
+    let keys = match &self.keys {
+      Some(keys) => keys.clone(),
+      None => {
+        tracing::error!("Emulator: uart frame before login, ignoring");
+        return Ok(())
+      }
+    };
+
+    let it = match self.counters.expected_rx(uart_counter(frame).unwrap_or(0)) {
+      Ok(it) => it,
+      Err(err) => {
+        tracing::error!("Emulator: {}", err);
+        return Ok(())
+      }
+    };
+
+    let command = decrypt_uart_at(&keys.app, frame, it)?;
+    self.counters.accept_rx(it);
+
+    if command.len() < 4 {
+      return Err(anyhow!("Emulator received too short uart command: {:?}", command.hex_dump()))
+    }
+
+    if let Some(reply) = self.handle_command(&command[..command.len() - 4])? { // skip rand
+      let bytes = encrypt_uart(&keys.dev, &reply, self.counters.next_tx()?, None);
+      self.notify_uart(&bytes)?;
+    }
+
+    Ok(())
+  }
+
+  /**
+   * Serve [direction, read_write, attribute, payload..] from state, returns reply message for reads
+   */
+  fn handle_command(&mut self, command: &[u8]) -> Result<Option<Vec<u8>>> {
+    if command.len() < 3 {
+      return Err(anyhow!("Emulator received too short uart command: {:?}", command.hex_dump()))
+    }
+
+    let direction = command[0];
+    let read_write = command[1];
+    let attribute = command[2];
+    let payload = &command[3..];
+
+    if let Some(started) = self.realtime {
+      let now = Instant::now();
+      self.state.lock().unwrap().ride(now - started);
+      self.realtime = Some(now);
+    }
+
+    match read_write {
+      0x01 => {
+        let len = *payload.first().unwrap_or(&0x02) as usize;
+        let data = self.state.lock().unwrap().read(direction, attribute, len);
+        let reply_direction = direction + 3; // 0x20 -> 0x23, 0x22 -> 0x25
+
+        let mut reply = vec![len as u8 + 2, reply_direction, 0x01, attribute];
+        reply.extend_from_slice(&data);
+
+        Ok(Some(reply))
+      },
+      0x03 => {
+        self.state.lock().unwrap().write(direction, attribute, payload);
+        Ok(None)
+      },
+      other => {
+        tracing::error!("Emulator: unknown uart operation {:#04x}", other);
+        Ok(None)
+      }
+    }
+  }
+
+  /**
+   * Split uart reply into notifications on RX
+   */
+  fn notify_uart(&self, bytes: &[u8]) -> Result<()> {
+    for chunk in bytes.chunks(self.notification_size) {
+      self.notify(&Registers::RX, chunk)?;
+    }
+
+    Ok(())
+  }
+
+  async fn send_mi_parcel(&mut self, parcel_type: u8, data: &[u8]) -> Result<()> {
+    let frames = data.chunks(MI_CHUNK_SIZE).count() as u16;
+    let frames_bytes = frames.to_le_bytes();
+
+    self.notify(&Registers::AVDTP, &[0x00, 0x00, 0x00, parcel_type, frames_bytes[0], frames_bytes[1]])?;
+    self.expect_write(MiCommands::RCV_RDY).await?;
+
+    for (index, chunk) in data.chunks(MI_CHUNK_SIZE).enumerate() {
+      let index = (index as u16 + 1).to_le_bytes();
+      let mut frame = vec![index[0], index[1]];
+      frame.extend_from_slice(chunk);
+      self.notify(&Registers::AVDTP, &frame)?;
+    }
+
+    self.expect_write(MiCommands::RCV_OK).await
+  }
+
+  /**
+   * Receive data from client using mi parcel flow: RCV_RDY, frames, RCV_OK
+   */
+  async fn receive_mi_parcel(&mut self, frames: u8) -> Result<Vec<u8>> {
+    let mut data : Vec<u8> = Vec::new();
+    self.notify(&Registers::AVDTP, &MiCommands::RCV_RDY.to_bytes())?;
+
+    for _ in 0..frames {
+      let frame = self.next_write().await?;
+      let chunk = frame.get(2..)
+        .ok_or_else(|| anyhow!("Emulator expected mi parcel frame, received: {:?}", frame.hex_dump()))?;
+      data.extend_from_slice(chunk);
+    }
+
+    self.notify(&Registers::AVDTP, &MiCommands::RCV_OK.to_bytes())?;
+    Ok(data)
+  }
+
+  async fn expect_write(&mut self, command: MiCommands) -> Result<()> {
+    let value = self.next_write().await?;
+
+    if value != command.to_bytes() {
+      return Err(anyhow!("Emulator expected {:?}, received: {:?}", command, value.hex_dump()))
+    }
+
+    Ok(())
+  }
+
+  async fn next_write(&mut self) -> Result<Vec<u8>> {
+    match self.peer.recv().await {
+      Some(write) => Ok(write.value),
+      None => Err(anyhow!("Client disconnected from emulator"))
+    }
+  }
+
+  fn notify(&self, reg: &Registers, data: &[u8]) -> Result<()> {
+    Ok(self.peer.notify(reg, data)?)
+  }
+}
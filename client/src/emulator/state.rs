@@ -0,0 +1,275 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+const ESC : u8 = 0x20;
+const BMS : u8 = 0x22;
+
+/**
+ * Everything the emulated scooter knows about itself. Values are kept in human units and encoded
+ * into the ESC (0x20) and BMS (0x22) word registers only when the client reads them.
+ * Check protocol documentation on docs folder to get more info on the registers.
+ */
+#[derive(Debug, Clone)]
+pub struct ScooterState {
+  pub serial: String,
+  pub pin: String,
+  /**
+   * ESC firmware as nibbles, 0x0134 => 1.3.4
+   */
+  pub esc_version: u16,
+  pub bms_version: u16,
+  pub ble_version: u16,
+
+  pub error_code: u16,
+  pub warning_code: u16,
+  pub esc_flags: u16,
+  pub work_mode: u16,
+  pub speed_kmh: f32,
+  pub total_distance_m: f64,
+  pub trip_distance_m: f64,
+  pub trip_time: Duration,
+  pub uptime: Duration,
+  /**
+   * Temperature in celsius
+   */
+  pub frame_temperature: f32,
+
+  pub kers: u16,
+  pub cruise: bool,
+  pub tail_light: u16,
+
+  pub battery_serial: String,
+  pub design_capacity_mah: u16,
+  pub capacity_mah: f64,
+  /**
+   * In Ampers, current going out of battery
+   */
+  pub current: f32,
+  /**
+   * Voltage for each of the 10 cells, in Volts
+   */
+  pub cells: [f32; 10],
+  pub battery_temperatures: [u8; 2],
+  pub cycles: u16,
+  pub charges: u16,
+  /**
+   * Charge put into the battery over its life
+   */
+  pub recharged_mah: u32,
+  /**
+   * Packed like BMS register 0x1e: bits 15-9 year - 2000, bits 8-5 month, bits 4-0 day
+   */
+  pub manufacture_date: u16,
+
+  /**
+   * Raw words written by client to registers not modelled above, by (direction, address)
+   */
+  registers: HashMap<(u8, u8), u16>,
+}
+
+impl Default for ScooterState {
+  fn default() -> Self {
+    let mut state = Self {
+      serial: "26354/00467353".to_owned(),
+      pin: "000000".to_owned(),
+      esc_version: 0x0134,
+      bms_version: 0x0115,
+      ble_version: 0x0071,
+      error_code: 0,
+      warning_code: 0,
+      esc_flags: 0,
+      work_mode: 0,
+      speed_kmh: 0.0,
+      total_distance_m: 1306083.0,
+      trip_distance_m: 0.0,
+      trip_time: Duration::ZERO,
+      uptime: Duration::ZERO,
+      frame_temperature: 25.0,
+      kers: 0,
+      cruise: false,
+      tail_light: 0,
+      battery_serial: "3LABATTDECAMIL".to_owned(),
+      design_capacity_mah: 7800,
+      capacity_mah: 6240.0,
+      current: 0.0,
+      cells: [0.0; 10],
+      battery_temperatures: [45, 45],
+      cycles: 12,
+      charges: 34,
+      recharged_mah: 8866,
+      manufacture_date: (21 << 9) | (5 << 5) | 17, // 2021-05-17
+      registers: HashMap::new(),
+    };
+
+    state.update_cells();
+    state
+  }
+}
+
+impl ScooterState {
+  pub fn battery_percent(&self) -> u16 {
+    (self.capacity_mah / self.design_capacity_mah as f64 * 100.0).round() as u16
+  }
+
+  /**
+   * Battery pack voltage, sum of all cells
+   */
+  pub fn voltage(&self) -> f32 {
+    self.cells.iter().sum()
+  }
+
+  /**
+   * Estimated range in kilometers, a full battery gives 30km
+   */
+  pub fn distance_left_km(&self) -> f32 {
+    self.capacity_mah as f32 / self.design_capacity_mah as f32 * 30.0
+  }
+
+  pub fn average_speed_kmh(&self) -> f32 {
+    if self.trip_time.is_zero() {
+      return 0.0
+    }
+
+    (self.trip_distance_m / self.trip_time.as_secs_f64() * 3.6) as f32
+  }
+
+  /**
+   * Move simulated ride forward. Distance grows with current speed and battery drains with the
+   * current drawn by motor, so consecutive reads return a ride that makes sense.
+   */
+  pub fn ride(&mut self, elapsed: Duration) {
+    let hours = elapsed.as_secs_f64() / 3600.0;
+    let meters = self.speed_kmh as f64 * 1000.0 * hours;
+
+    self.total_distance_m += meters;
+    self.trip_distance_m += meters;
+    self.trip_time += elapsed;
+    self.uptime += elapsed;
+
+    self.current = if self.speed_kmh > 0.0 { 0.5 + self.speed_kmh * 0.4 } else { 0.1 };
+    self.capacity_mah = (self.capacity_mah - self.current as f64 * 1000.0 * hours).max(0.0);
+    self.update_cells();
+  }
+
+  fn update_cells(&mut self) {
+    let charge = self.capacity_mah as f32 / self.design_capacity_mah as f32;
+    let sag = self.current * 0.01;
+
+    for (index, cell) in self.cells.iter_mut().enumerate() {
+      *cell = 3.3 + 0.9 * charge - sag + index as f32 * 0.001;
+    }
+  }
+
+  /**
+   * Read len bytes starting at word register addr
+   */
+  pub fn read(&self, direction: u8, addr: u8, len: usize) -> Vec<u8> {
+    let mut bytes : Vec<u8> = Vec::new();
+    let mut reg = addr as usize;
+
+    while bytes.len() < len && reg <= 0xff {
+      let word = self.word(direction, reg as u8);
+      bytes.extend_from_slice(&word.to_le_bytes());
+      reg += 1;
+    }
+
+    bytes.resize(len, 0);
+    bytes
+  }
+
+  /**
+   * Write bytes starting at word register addr
+   */
+  pub fn write(&mut self, direction: u8, addr: u8, bytes: &[u8]) {
+    for (index, chunk) in bytes.chunks(2).enumerate() {
+      let reg = match addr.checked_add(index as u8) {
+        Some(reg) => reg,
+        None => break
+      };
+
+      let word = u16::from_le_bytes([chunk[0], *chunk.get(1).unwrap_or(&0)]);
+
+      match (direction, reg) {
+        (ESC, 0x7b) => self.kers = word,
+        (ESC, 0x7c) => self.cruise = word == 1,
+        (ESC, 0x7d) => self.tail_light = word,
+        _ => { self.registers.insert((direction, reg), word); }
+      }
+    }
+  }
+
+  fn word(&self, direction: u8, reg: u8) -> u16 {
+    if let Some(word) = self.registers.get(&(direction, reg)) {
+      return *word
+    }
+
+    match direction {
+      ESC => self.esc_word(reg),
+      BMS => self.bms_word(reg),
+      _ => 0
+    }
+  }
+
+  fn esc_word(&self, reg: u8) -> u16 {
+    match reg {
+      0x10..=0x16 => ascii_word(&self.serial, reg - 0x10),
+      0x17..=0x19 => ascii_word(&self.pin, reg - 0x17),
+      0x1a => self.esc_version,
+      0x25 => (self.distance_left_km() * 100.0) as u16,
+      0x3a => self.trip_time.as_secs() as u16,
+      0x3b => self.trip_distance_m as u16,
+      0x3e => (self.frame_temperature * 10.0) as i16 as u16,
+      0x67 => self.bms_version,
+      0x68 => self.ble_version,
+      0x7b => self.kers,
+      0x7c => self.cruise as u16,
+      0x7d => self.tail_light,
+      0xb0 => self.error_code,
+      0xb1 => self.warning_code,
+      0xb2 => self.esc_flags,
+      0xb3 => self.work_mode,
+      0xb4 => self.battery_percent(),
+      0xb5 => (self.speed_kmh * 1000.0) as i16 as u16,
+      0xb6 => (self.average_speed_kmh() * 1000.0) as u16,
+      0xb7 => self.total_distance_m as u32 as u16,
+      0xb8 => (self.total_distance_m as u32 >> 16) as u16,
+      0xb9 => self.trip_distance_m as u16,
+      0xba => self.uptime.as_secs() as u16,
+      0xbb => (self.frame_temperature * 10.0) as i16 as u16,
+      _ => 0
+    }
+  }
+
+  fn bms_word(&self, reg: u8) -> u16 {
+    match reg {
+      0x10..=0x16 => ascii_word(&self.battery_serial, reg - 0x10),
+      0x17 => self.bms_version,
+      0x18 => self.design_capacity_mah,
+      0x1b => self.cycles,
+      0x1c => self.charges,
+      0x1e => self.manufacture_date,
+      0x20 => self.recharged_mah as u16,
+      0x21 => (self.recharged_mah >> 16) as u16,
+      0x31 => self.capacity_mah as u16,
+      0x32 => self.battery_percent(),
+      0x33 => (self.current * 100.0) as i16 as u16,
+      0x34 => (self.voltage() * 100.0).round() as u16,
+      0x35 => u16::from_le_bytes(self.battery_temperatures),
+      0x40..=0x49 => (self.cells[(reg - 0x40) as usize] * 1000.0).round() as u16,
+      _ => 0
+    }
+  }
+}
+
+/**
+ * Two ascii characters of text, starting at word offset
+ */
+fn ascii_word(text: &str, offset: u8) -> u16 {
+  let bytes = text.as_bytes();
+  let index = offset as usize * 2;
+
+  u16::from_le_bytes([
+    *bytes.get(index).unwrap_or(&0),
+    *bytes.get(index + 1).unwrap_or(&0),
+  ])
+}
@@ -0,0 +1,95 @@
+use crate::login::LoginError;
+use crate::register::RegistrationError;
+use crate::scanner::ScannerError;
+use crate::mi_crypto::MiCryptoError;
+
+use std::time::Duration;
+use thiserror::Error;
+
+/**
+ * Every error the crate can return. Variants carry enough context to decide what to do next:
+ * drop a single poll, login again, or reconnect the scooter from scratch.
+ */
+#[derive(Error, Debug)]
+pub enum M365Error {
+  #[error(transparent)]
+  Scanner(#[from] ScannerError),
+  #[error(transparent)]
+  Login(#[from] LoginError),
+  #[error(transparent)]
+  Registration(#[from] RegistrationError),
+  #[error(transparent)]
+  Crypto(#[from] MiCryptoError),
+
+  #[error("Bluetooth error, {action}: {source}")]
+  Bluetooth { action: String, source: btleplug::Error },
+  #[error("Scooter has no {0}")]
+  MissingCharacteristic(String),
+  #[error("Scooter disconnected: {0}")]
+  Disconnected(String),
+  #[error("Scooter did not answer in {0:?}")]
+  Timeout(Duration),
+  #[error("No reply with direction {direction:#04x} and attribute {attribute:#04x} in {timeout:?}")]
+  NoReply { direction: u8, attribute: u8, timeout: Duration },
+  #[error("Expected {expected} from scooter, but received: {received}")]
+  UnexpectedResponse { expected: String, received: String },
+  #[error("Could not parse {what}: {reason}")]
+  Parse { what: &'static str, reason: String },
+
+  #[error("GPS error: {0}")]
+  Gps(String),
+  #[error("Serial port error: {0}")]
+  Serial(#[from] serialport::Error),
+  #[error("IO error: {0}")]
+  Io(#[from] std::io::Error),
+}
+
+pub type Result<T, E = M365Error> = std::result::Result<T, E>;
+
+impl M365Error {
+  /**
+   * Link to scooter is gone or keys are no longer valid. Reconnect and login again.
+   */
+  pub fn needs_reconnect(&self) -> bool {
+    match self {
+      M365Error::Bluetooth { .. } | M365Error::Disconnected(_) | M365Error::MissingCharacteristic(_) => true,
+      M365Error::Login(_) | M365Error::Scanner(_) => true,
+      M365Error::Crypto(error) => !error.is_ignorable(),
+      _ => false
+    }
+  }
+
+  /**
+   * Only this request failed (damaged frame, slow reply, bad GPS read). Session can still be used.
+   */
+  pub fn is_transient(&self) -> bool {
+    match self {
+      M365Error::Timeout(_) | M365Error::NoReply { .. } => true,
+      M365Error::Parse { .. } | M365Error::UnexpectedResponse { .. } => true,
+      M365Error::Gps(_) | M365Error::Serial(_) | M365Error::Io(_) => true,
+      M365Error::Crypto(error) => error.is_ignorable(),
+      _ => false
+    }
+  }
+
+  pub(crate) fn parse(what: &'static str, reason: impl ToString) -> Self {
+    M365Error::Parse { what, reason: reason.to_string() }
+  }
+
+  pub(crate) fn unexpected(expected: impl std::fmt::Debug, received: impl std::fmt::Debug) -> Self {
+    M365Error::UnexpectedResponse { expected: format!("{:?}", expected), received: format!("{:?}", received) }
+  }
+}
+
+/**
+ * Same as anyhow Context, but for bluetooth errors: keeps btleplug error and describes what we were doing
+ */
+pub(crate) trait BluetoothContext<T> {
+  fn with_context<S: Into<String>, F: FnOnce() -> S>(self, action: F) -> Result<T>;
+}
+
+impl<T> BluetoothContext<T> for std::result::Result<T, btleplug::Error> {
+  fn with_context<S: Into<String>, F: FnOnce() -> S>(self, action: F) -> Result<T> {
+    self.map_err(|source| M365Error::Bluetooth { action: action().into(), source })
+  }
+}
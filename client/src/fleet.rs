@@ -0,0 +1,192 @@
+use crate::error::Result;
+use crate::session::{MiSession, SessionHandle};
+use crate::supervisor::{Connector, Supervisor};
+use crate::transport::Transport;
+
+use futures::future::BoxFuture;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{watch, OwnedSemaphorePermit, Semaphore};
+use tokio::task::JoinHandle;
+use tokio::time::{sleep_until, Instant};
+use tokio_util::sync::CancellationToken;
+
+/**
+ * Limits how many scooters of the fleet are connected at the same time. Adapters (and bluez) support only
+ * a few concurrent connections, with more scooters they take turns: each one keeps the adapter for a slot,
+ * then releases the link and waits in queue (first come first served) for the next turn.
+ *
+ * Cloned share is the same share, give clone to every monitored scooter.
+ */
+#[derive(Debug, Clone)]
+pub struct AdapterShare {
+  permits: Option<Arc<Semaphore>>,
+  slot: Duration,
+}
+
+impl AdapterShare {
+  /**
+   * Every scooter stays connected all the time
+   */
+  pub fn unlimited() -> Self {
+    Self { permits: None, slot: Duration::MAX }
+  }
+
+  pub fn new(max_connections: usize, slot: Duration) -> Self {
+    Self { permits: Some(Arc::new(Semaphore::new(max_connections.max(1)))), slot }
+  }
+
+  pub fn is_limited(&self) -> bool {
+    self.permits.is_some()
+  }
+
+  async fn acquire(&self) -> Option<OwnedSemaphorePermit> {
+    match &self.permits {
+      Some(permits) => permits.clone().acquire_owned().await.ok(),
+      None => None
+    }
+  }
+
+  fn slot_end(&self) -> Option<Instant> {
+    self.permits.as_ref().map(|_| Instant::now() + self.slot)
+  }
+}
+
+/**
+ * Keep polling one scooter of the fleet every interval until stop is cancelled. Session is established
+ * (and recovered) by supervisor. When adapter is shared, link is released at the end of each slot and
+ * session resumed on next turn; scooter that doesn't show up during its turn gives the adapter to the next one.
+ * Returns last session, if there is one.
+ *
+ * While logged in, session runs inside SessionHandle actor published on current, so other tasks (remote
+ * commands) can use it between polls. They should clone the handle for each command and not keep it:
+ * session is taken back from the actor only after every handle is dropped.
+ *
+ * let (current, _) = watch::channel(None);
+ * monitor(&mut supervisor, &share, interval, &stop, &current, |session| Box::pin(async move {
+ *   let motor_info = session.motor_info().await?;
+ *   ...
+ *   Ok(())
+ * })).await;
+ */
+pub async fn monitor<C, F>(supervisor: &mut Supervisor<C>, adapter: &AdapterShare, interval: Duration, stop: &CancellationToken,
+                           current: &watch::Sender<Option<SessionHandle<C::Transport>>>, mut poll: F) -> Option<MiSession<C::Transport>>
+  where C: Connector,
+        C::Transport: 'static,
+        F: FnMut(SessionHandle<C::Transport>) -> BoxFuture<'static, Result<()>>
+{
+  let mut session = None;
+
+  'turns: while !stop.is_cancelled() {
+    let _permit = tokio::select! {
+      permit = adapter.acquire() => permit,
+      _ = stop.cancelled() => break
+    };
+    let slot_end = adapter.slot_end();
+
+    let connect = async {
+      match session.take() {
+        Some(released) => supervisor.resume(released).await,
+        None => supervisor.establish().await
+      }
+    };
+    let Some(established) = until(slot_end, stop, connect).await else {
+      if adapter.is_limited() && !stop.is_cancelled() {
+        tracing::info!("Scooter not reachable during its turn, giving adapter to next one");
+        supervisor.release().await;
+      }
+      continue
+    };
+    let (mut handle, mut actor) = share(current, established);
+
+    loop {
+      match poll(handle.clone()).await {
+        Ok(()) => {},
+        Err(error) if error.is_transient() => {
+          //Damaged frame or slow reply does not mean the link is gone, drop it and poll again
+          tracing::warn!("Dropping failed poll: {}", error);
+        },
+        Err(error) => {
+          tracing::error!("Error polling scooter: {}", error);
+          let Some(failed) = take_back(current, handle, actor).await else {
+            if adapter.is_limited() {
+              supervisor.release().await;
+            }
+            continue 'turns
+          };
+          match until(slot_end, stop, supervisor.recover(failed, &error)).await {
+            Some(recovered) => (handle, actor) = share(current, recovered),
+            None => {
+              if adapter.is_limited() {
+                supervisor.release().await;
+              }
+              continue 'turns
+            }
+          }
+        }
+      }
+
+      let next_poll = Instant::now() + interval;
+      if slot_end.is_some_and(|end| next_poll >= end) {
+        break
+      }
+
+      tokio::select! {
+        _ = sleep_until(next_poll) => {},
+        _ = stop.cancelled() => break
+      }
+    }
+
+    session = take_back(current, handle, actor).await;
+    if adapter.is_limited() {
+      supervisor.release().await;
+    }
+  }
+
+  session
+}
+
+/**
+ * Move logged in session into actor and let other tasks see its handle
+ */
+fn share<T: Transport + 'static>(current: &watch::Sender<Option<SessionHandle<T>>>, session: MiSession<T>) -> (SessionHandle<T>, JoinHandle<MiSession<T>>) {
+  let (handle, actor) = SessionHandle::spawn(session);
+  current.send_replace(Some(handle.clone()));
+  (handle, actor)
+}
+
+/**
+ * Hide handle from other tasks and wait for commands already queued, actor gives session back when
+ * the last handle is dropped. Session is lost only when a command panicked inside actor.
+ */
+async fn take_back<T: Transport>(current: &watch::Sender<Option<SessionHandle<T>>>, handle: SessionHandle<T>, actor: JoinHandle<MiSession<T>>) -> Option<MiSession<T>> {
+  current.send_replace(None);
+  drop(handle);
+
+  match actor.await {
+    Ok(session) => Some(session),
+    Err(error) => {
+      tracing::error!("Session actor failed, logging in again: {}", error);
+      None
+    }
+  }
+}
+
+/**
+ * Run operation until it finishes, deadline passes or stop is cancelled
+ */
+async fn until<T>(deadline: Option<Instant>, stop: &CancellationToken, operation: impl Future<Output = T>) -> Option<T> {
+  let deadline = async {
+    match deadline {
+      Some(deadline) => sleep_until(deadline).await,
+      None => std::future::pending().await
+    }
+  };
+
+  tokio::select! {
+    result = operation => Some(result),
+    _ = deadline => None,
+    _ = stop.cancelled() => None
+  }
+}
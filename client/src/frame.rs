@@ -0,0 +1,151 @@
+use crate::mi_crypto::{crc16, HEADER, LEGACY_HEADER, ENCRYPTED_OVERHEAD, LEGACY_OVERHEAD};
+use crate::ninebot;
+
+use pretty_hex::*;
+
+/**
+ * All uart frames start with two bytes header followed by length byte L. Header tells how many
+ * bytes are there in frame besides the ones counted by L:
+ *
+ * 55 AB L it(2) ct(L + 9) ck(2)     => L + 16
+ * 55 AA L D T c data(L - 2) ck(2)   => L + 6
+ * 5A A5 L src dst cmd arg data(L) ck(2)  => L + 9
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameKind {
+  Encrypted,
+  Legacy,
+  Ninebot,
+}
+
+impl FrameKind {
+  pub fn header(&self) -> [u8; 2] {
+    match self {
+      FrameKind::Encrypted  => HEADER,
+      FrameKind::Legacy     => LEGACY_HEADER,
+      FrameKind::Ninebot    => ninebot::HEADER,
+    }
+  }
+
+  /**
+   * Number of bytes in frame that are not counted by length byte
+   */
+  pub fn overhead(&self) -> usize {
+    match self {
+      FrameKind::Encrypted  => ENCRYPTED_OVERHEAD,
+      FrameKind::Legacy     => LEGACY_OVERHEAD,
+      FrameKind::Ninebot    => ninebot::FRAME_OVERHEAD,
+    }
+  }
+
+  pub fn from_header(bytes: &[u8]) -> Option<Self> {
+    [FrameKind::Encrypted, FrameKind::Legacy, FrameKind::Ninebot].into_iter()
+      .find(|kind| bytes.starts_with(&kind.header()))
+  }
+}
+
+/**
+ * Streaming decoder for uart frames. Scooter splits replies into notifications however it likes,
+ * so push every notification here and take whole frames out. Bytes that don't start a known
+ * header are dropped until decoder finds one again.
+ *
+ * let mut decoder = FrameDecoder::new();
+ * decoder.push(&notification.value);
+ * while let Some(frame) = decoder.next_frame() { ... }
+ */
+#[derive(Debug, Default)]
+pub struct FrameDecoder {
+  buffer: Vec<u8>,
+}
+
+impl FrameDecoder {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn push(&mut self, bytes: &[u8]) {
+    self.buffer.extend_from_slice(bytes);
+  }
+
+  /**
+   * Forget everything buffered, for example after reconnecting
+   */
+  pub fn clear(&mut self) {
+    self.buffer.clear();
+  }
+
+  /**
+   * Number of bytes waiting for the rest of the frame
+   */
+  pub fn pending(&self) -> usize {
+    self.buffer.len()
+  }
+
+  /**
+   * Take next complete frame, including header and checksum. Frame with bad checksum is only
+   * a false header in the noise, so one byte is dropped and the rest is searched again.
+   */
+  pub fn next_frame(&mut self) -> Option<Vec<u8>> {
+    loop {
+      self.resync();
+
+      if self.buffer.len() < 3 {
+        return None
+      }
+
+      let kind = FrameKind::from_header(&self.buffer)?;
+      let frame_len = self.buffer[2] as usize + kind.overhead();
+      if self.buffer.len() < frame_len {
+        // False header with big length byte would hold back real frames that came after it
+        match (1..self.buffer.len()).find(|index| is_valid_frame(&self.buffer[*index..])) {
+          Some(start) => {
+            tracing::debug!("  Dropping unfinished frame: {:?}", (&self.buffer[0..start]).hex_dump());
+            self.buffer.drain(0..start);
+            continue
+          },
+          None => return None
+        }
+      }
+
+      if !is_valid_frame(&self.buffer) {
+        tracing::debug!("  Dropping byte of invalid {:?} frame: {:?}", kind, (&self.buffer[0..frame_len]).hex_dump());
+        self.buffer.drain(0..1);
+        continue
+      }
+
+      let frame : Vec<u8> = self.buffer.drain(0..frame_len).collect();
+      tracing::debug!("  Decoded {:?} frame: {:?}", kind, frame.hex_dump());
+      return Some(frame)
+    }
+  }
+
+  /**
+   * Drop garbage in front of buffer, until it starts with known header
+   */
+  fn resync(&mut self) {
+    let start = (0..self.buffer.len())
+      .find(|index| {
+        let rest = &self.buffer[*index..];
+        // Keep last byte if it might be first half of a header
+        FrameKind::from_header(rest).is_some() || (rest.len() == 1 && (rest[0] == 0x55 || rest[0] == 0x5a))
+      })
+      .unwrap_or(self.buffer.len());
+
+    if start > 0 {
+      tracing::debug!("  Dropping garbage: {:?}", (&self.buffer[0..start]).hex_dump());
+      self.buffer.drain(0..start);
+    }
+  }
+}
+
+/**
+ * Bytes start with complete frame that has correct checksum
+ */
+fn is_valid_frame(bytes: &[u8]) -> bool {
+  let (Some(kind), Some(len)) = (FrameKind::from_header(bytes), bytes.get(2)) else {
+    return false
+  };
+
+  let frame_len = *len as usize + kind.overhead();
+  bytes.len() >= frame_len && crc16(&bytes[2..frame_len - 2]) == bytes[frame_len - 2..frame_len]
+}
@@ -5,7 +5,7 @@ use std::thread::sleep;
 use std::time::Duration;
 use tracing::{debug, error, info};
 // Use https://crates.io/crates/serialport
-use anyhow::{anyhow, Error, Result};
+use crate::error::{M365Error, Result};
 use regex::Regex;
 
 /**
@@ -177,7 +177,7 @@ impl GPSInfo {
                 Ok(GPSInfo::parse(&response)?)
             }
         } else {
-            Err(anyhow!("Command output mismatch GPS!"))
+            Err(M365Error::Gps("Command output mismatch GPS!".to_string()))
         }
     }
 }
@@ -197,8 +197,8 @@ fn dms_to_decimal(degrees_minutes: &str, direction: char, axis: i8) -> Result<f6
         LATITUDE => (degrees_minutes[0..2].parse::<f64>().unwrap(), 2),
         LONGITUDE => (degrees_minutes[0..3].parse::<f64>().unwrap(), 3),
         _ => {
-            return Err(anyhow!(
-                "Invalid axis: only LATITUDE and LONGITUDE are compatible!"
+            return Err(M365Error::Gps(
+                "Invalid axis: only LATITUDE and LONGITUDE are compatible!".to_string(),
             ))
         }
     };
@@ -249,7 +249,7 @@ fn gps_status(port: &mut dyn SerialPort) -> Result<bool> {
         return Ok(true);
     }
 
-    Err(anyhow!("Can´t get GPS status!"))
+    Err(M365Error::Gps("Can´t get GPS status!".to_string()))
 }
 
 pub fn enable_gps(port: &mut dyn SerialPort) -> Result<bool> {
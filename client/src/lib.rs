@@ -1,32 +1,48 @@
 extern crate uuid;
 
+pub mod adapter;
+pub mod btsnoop;
+pub mod command;
 pub mod consts;
 pub mod mi_crypto;
 //mod mi_crypto;
 pub mod config;
 mod connection;
+#[cfg(feature = "emulator")]
+pub mod emulator;
+pub mod error;
+pub mod fleet;
+pub mod frame;
 pub mod gps_location;
 mod login;
+pub mod model;
 mod mqtt_data;
+pub mod ninebot;
 pub mod protocol;
 //mod protocol;
 mod register;
 mod scanner;
 mod session;
+pub mod supervisor;
 pub mod telemetry;
+pub mod transport;
 
 //mod main;
 
 //pub use config::Config;
 pub use connection::ConnectionHelper;
+pub use login::LoginError;
 pub use login::LoginRequest;
 pub use mi_crypto::AuthToken;
+pub use model::ScooterModel;
 pub use mqtt_data::MqttClient;
 pub use register::RegistrationError;
 pub use register::RegistrationRequest;
+pub use scanner::DeviceTracker;
 pub use scanner::ScannerError;
 pub use scanner::ScannerEvent;
 pub use scanner::ScooterScanner;
 pub use scanner::TrackedDevice;
+pub use scanner::SCOOTER_MANUFACTURER_ID;
 
-pub use session::{BatteryInfo, GeneralInfo, MiSession, MotorInfo, Payload, TailLight};
+pub use session::{BatteryInfo, BmsInfo, Controller, Direction, EscError, EscStatus, EscWarning, FirmwareVersion, FirmwareVersions, GeneralInfo, MiSession, MotorInfo, Payload, Priority, RegisterSnapshot, RegisterValue, SessionHandle, TailLight, TripInfo, UartMode};
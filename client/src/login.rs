@@ -1,14 +1,23 @@
 use crate::mi_crypto::{
-  AuthToken, RandKey, LoginKeychain,
-  gen_rand_key, calc_login_did
+  AuthToken, RandKey, LoginKeychain, LEGACY_HEADER,
+  gen_rand_key, calc_login_did, encode_uart
 };
 use crate::session::MiSession;
+use crate::session::commands::{ScooterCommand, Direction, ReadWrite, Attribute};
 use crate::consts::{MiCommands, Registers};
 use crate::protocol::MiProtocol;
-use anyhow::Result;
+use crate::transport::{Transport, BleTransport};
+use crate::error::Result;
 use pretty_hex::*;
 use btleplug::platform::Peripheral;
 use thiserror::Error;
+use std::time::Duration;
+use tokio::time::timeout;
+
+/**
+ * How long to wait for legacy firmware to answer plain probe, before falling back to encrypted login
+ */
+const LEGACY_PROBE_TIMEOUT : Duration = Duration::from_secs(1);
 
 #[derive(Error, Debug)]
 pub enum LoginError {
@@ -16,14 +25,10 @@ pub enum LoginError {
   LoginFailed,
   #[error("Scooter sent invalid remote key")]
   InvalidDid,
-  #[error("Login failed: {0}")]
-  Other(anyhow::Error)
-}
-
-impl From<anyhow::Error> for LoginError {
-  fn from(other: anyhow::Error) -> Self {
-    LoginError::Other(other)
-  }
+  #[error("Scooter sent remote info of {received} bytes, expected 32")]
+  InvalidRemoteInfo { received: usize },
+  #[error("Ninebot scooter does not answer plain 5AA5 frames, its firmware probably encrypts them (Max G30 and other encrypted firmware is not supported)")]
+  NinebotEncrypted,
 }
 
 /**
@@ -32,11 +37,10 @@ impl From<anyhow::Error> for LoginError {
  * If everything goes right, you will receive MiSession which allows you to send commands and read
  * read responses back from the scooter
  */
-pub struct LoginRequest {
-  protocol: MiProtocol,
+pub struct LoginRequest<T: Transport = BleTransport> {
+  protocol: MiProtocol<T>,
   auth_token: AuthToken,
   rand_key: RandKey,
-  device: Peripheral,
   remote_info: Option<[u8; 32]>,
   keys: Option<LoginKeychain>,
   remote_key: Option<Vec<u8>>,
@@ -45,38 +49,87 @@ pub struct LoginRequest {
 impl LoginRequest {
   pub async fn new(device : &Peripheral, token: &AuthToken) -> Result<Self> {
     let protocol = MiProtocol::new(device).await?;
+
+    Ok(Self::with_protocol(protocol, token))
+  }
+}
+
+impl<T: Transport> LoginRequest<T> {
+  /**
+   * Login over any transport, for example ChannelTransport when there is no bluetooth around
+   */
+  pub fn with_protocol(protocol: MiProtocol<T>, token: &AuthToken) -> Self {
     let rand_key = gen_rand_key();
 
-    Ok(
-      Self {
-        remote_info: None,
-        remote_key: None,
-        keys: None,
-        rand_key,
-        protocol,
-        device: device.clone(),
-        auth_token: token.clone()
-      }
-    )
+    Self {
+      remote_info: None,
+      remote_key: None,
+      keys: None,
+      rand_key,
+      protocol,
+      auth_token: *token
+    }
   }
 
   /**
-  * Start login process. If everything goes well it returns a MiSession.
+   * Use given random key instead of fresh one. Session keys are derived from it, so recorded login can be
+   * replayed only with the key it was recorded with (see recorded_rand_key)
+   */
+  pub fn with_rand_key(mut self, rand_key: RandKey) -> Self {
+    self.rand_key = rand_key;
+    self
+  }
+
+  /**
+  * Start login process. If everything goes well it returns a MiSession, which takes over the protocol.
   */
 
-  pub async fn start(&mut self) -> Result<MiSession> {
+  pub async fn start(mut self) -> Result<MiSession<T>> {
     self.send_key().await?;
     self.read_remote_key().await?;
     self.read_remote_info().await?;
     self.validate_remote_key_and_send_did().await?;
     self.confirm().await?;
 
-    self.protocol.dispose().await?;
     let keys = self.keys.as_ref().unwrap();
-    let session = MiSession::new(&self.device, keys).await?;
+    let session = MiSession::with_protocol(self.protocol, keys);
     Ok(session)
   }
 
+  /**
+   * Same as start, but first check if scooter runs legacy firmware (ESC < 1.5) which talks plain
+   * 55AA frames. Such scooter does not need login at all, so session without encryption is returned.
+   */
+  pub async fn start_auto(mut self) -> Result<MiSession<T>> {
+    if self.is_legacy_firmware().await? {
+      tracing::info!("Scooter runs legacy firmware, skipping login");
+      return Ok(MiSession::legacy(self.protocol))
+    }
+
+    self.start().await
+  }
+
+  /**
+   * Ask ESC for firmware version using plain frame. Only legacy firmware will respond to it,
+   * newer one ignores anything that is not encrypted.
+   */
+  async fn is_legacy_firmware(&mut self) -> Result<bool> {
+    let probe = ScooterCommand {
+      direction: Direction::MasterToMotor,
+      read_write: ReadWrite::Read,
+      attribute: Attribute::FirmwareVersion,
+      payload: vec![0x02]
+    };
+
+    tracing::debug!("-> legacy probe");
+    self.protocol.write_nb_parcel(&Registers::TX, &encode_uart(&probe.as_bytes())).await?;
+
+    match timeout(LEGACY_PROBE_TIMEOUT, self.protocol.read_frame()).await {
+      Ok(Ok(frame)) => Ok(frame.starts_with(&LEGACY_HEADER)),
+      _ => Ok(false)
+    }
+  }
+
   async fn send_key(&mut self) -> Result<bool> {
     self.protocol.write(&Registers::UPNP, MiCommands::CMD_LOGIN).await?;
     self.protocol.write(&Registers::AVDTP, MiCommands::CMD_SEND_KEY).await?;
@@ -99,12 +152,13 @@ impl LoginRequest {
   async fn read_remote_info(&mut self) -> Result<bool> {
     tracing::debug!("<- remote_info");
     let remote_info = self.protocol.read_mi_parcel(&Registers::AVDTP).await?;
-    self.remote_info = Some(remote_info.try_into().unwrap());
+    let received = remote_info.len();
+    self.remote_info = Some(remote_info.try_into().map_err(|_| LoginError::InvalidRemoteInfo { received })?);
 
     Ok(true)
   }
 
-  async fn validate_remote_key_and_send_did(&mut self) -> Result<bool, LoginError> {
+  async fn validate_remote_key_and_send_did(&mut self) -> Result<bool> {
     tracing::info!("Validating did");
 
     let rand_key = self.rand_key.as_mut();
@@ -129,10 +183,10 @@ impl LoginRequest {
     tracing::error!("   Expected: {:?}", expected_remote_info.hex_dump());
     tracing::error!("   Received: {:?}", remote_info.hex_dump());
 
-    Err(LoginError::InvalidDid)
+    Err(LoginError::InvalidDid.into())
   }
 
-  async fn confirm(&mut self) -> Result<bool, LoginError> {
+  async fn confirm(&mut self) -> Result<bool> {
     match self.protocol.next_mi_response().await {
       Some(MiCommands::RCV_LOGIN_OK) => {
         tracing::info!("Logged in!");
@@ -140,12 +194,12 @@ impl LoginRequest {
 
       Some(error) => {
         tracing::error!("Login failed: {:?}", error);
-        return Err(LoginError::LoginFailed)
+        return Err(LoginError::LoginFailed.into())
       },
 
       None => {
         tracing::error!("Login failed, scooter did not respond");
-        return Err(LoginError::LoginFailed)
+        return Err(LoginError::LoginFailed.into())
       }
     }
 
@@ -1,130 +1,322 @@
 /*
    This file contains the main activity of the program.
    - It loads all the configuration parameters from the config file. => Config is loaded in config.rs file.
-   - It connects to the scooter
    - It connects to the MQTT broker
-   - It pull data from the scooter and sends it via MQTT to the server
+   - It connects to every scooter of the fleet
+   - It pull data from the scooters and sends it via MQTT to the server
 
 */
-use anyhow::{anyhow, Result};
+use anyhow::Result;
 use btleplug::api::BDAddr;
-use btleplug::platform::Peripheral;
 
-use m365::config::CONFIG;
-use m365::gps_location::enable_gps;
-use m365::telemetry::Telemetry;
-use m365::{AuthToken, ConnectionHelper, LoginRequest, MiSession, MqttClient, ScooterScanner};
+use m365::command::Command;
+use m365::config::{Scooter, CONFIG};
+use m365::fleet::{monitor, AdapterShare};
+use m365::gps_location::{enable_gps, GPSInfo};
+use m365::telemetry::{DeviceInfo, FaultTracker, LowRate, Retry, Telemetry};
+use m365::transport::Recorder;
+use m365::supervisor::{Backoff, BleConnector, Supervisor, SupervisorState};
+use m365::transport::{BleTransport, RecordingTransport, Transport};
+use m365::{AuthToken, MqttClient, Priority, SessionHandle};
+use paho_mqtt::{AsyncClient, AsyncReceiver, Message};
+use serialport::SerialPort;
+use std::collections::HashMap;
 use std::path::Path;
-use std::process::exit;
-use std::thread::sleep;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use std::time::Duration;
 use tokio::fs::File;
 use tokio::io::AsyncReadExt;
-use tracing::{error, info, Level};
-use tracing_subscriber;
+use tokio::sync::{watch, Mutex};
+use tokio::task::JoinSet;
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, warn, Level};
 use tracing_subscriber::fmt::format::FmtSpan;
 
 /**
  Provided a path it loads the contents of the MI token file necessary to connect to the scooter
 */
-async fn load_token() -> Result<AuthToken> {
-    let path = Path::new(&CONFIG.scooter.token_file_path);
+async fn load_token(scooter: &Scooter) -> Result<AuthToken> {
+    let path = Path::new(&scooter.token_file_path);
     tracing::debug!("Opening token: {:?}", path);
 
     let mut f = File::open(path).await?;
     let mut buffer: AuthToken = [0; 12];
 
-    f.read(&mut buffer).await?;
+    f.read_exact(&mut buffer).await?;
 
     Ok(buffer)
 }
 
-async fn load_mac() -> Result<BDAddr> {
-    let mac = BDAddr::from_str_no_delim(&CONFIG.scooter.mac.trim()).expect("Invalid mac address");
+async fn load_mac(scooter: &Scooter) -> Result<BDAddr> {
+    let mac = BDAddr::from_str_no_delim(scooter.mac.trim()).expect("Invalid mac address");
 
     Ok(mac)
 }
 
-async fn relink_scooter(
-    connection: ConnectionHelper,
-    device: &Peripheral,
-    token: &AuthToken,
-    mi_session: MiSession,
-) -> Result<MiSession> {
-    loop {
-        info!("Establishing connection with the scooter");
-
-        match connection.connect().await {
-            Ok(true) => {}
-            Ok(false) => {
-                info!("Already connected!");
-                return Ok(mi_session);
-            }
-            Err(e) => {
-                info!("Can't reach the scooter! Is it powered on? Relaunching the program....");
-                return Err(anyhow!("Can't reach the scooter! Is it power on?: {}", e));
+/**
+ Session of a scooter shared by its poller and remote commands, see BleConnector
+*/
+type ScooterSession = SessionHandle<RecordingTransport<BleTransport>>;
+
+/**
+ Publish serial and firmware versions of the scooter as retained message
+*/
+async fn publish_device_info<T: Transport + 'static>(
+    session: &SessionHandle<T>,
+    mqtt: &AsyncClient,
+    name: &str,
+    topic: &str,
+) -> m365::error::Result<()> {
+    let device_info = session
+        .run(Priority::Normal, |session| Box::pin(DeviceInfo::pull_scooter(session)))
+        .await?
+        .with_scooter(name);
+
+    let json_payload = match serde_json::to_string(&device_info) {
+        Ok(json_payload) => json_payload,
+        Err(e) => {
+            error!("Could not serialize device info: {}", e);
+            return Ok(());
+        }
+    };
+
+    info!("Publishing device info: {}", json_payload);
+    //Retained, so the server learns firmware of every scooter even when it subscribes later
+    let msg = paho_mqtt::Message::new_retained(topic, json_payload, paho_mqtt::QOS_1);
+
+    if let Err(e) = mqtt.publish(msg).await {
+        error!("Failed to send MQTT message: {:?}", e);
+    }
+
+    Ok(())
+}
+
+/**
+ Keeps one scooter of the fleet connected and publishes its telemetry tagged with scooter name
+*/
+async fn monitor_scooter(
+    scooter: &'static Scooter,
+    mqtt: AsyncClient,
+    gps: Arc<Mutex<Box<dyn SerialPort>>>,
+    adapter: AdapterShare,
+    current: watch::Sender<Option<ScooterSession>>,
+    stop: CancellationToken,
+) -> Result<()> {
+    let name = scooter.name();
+
+    //Load token
+    let token = if scooter.model.is_ninebot() {
+        [0; 12] // Ninebot scooters don't use Mi login
+    } else {
+        load_token(scooter).await?
+    };
+    //Load MAC
+    let mac = load_mac(scooter).await?;
+
+    //Scan, connect and login (key exchange, read more in the protocol documentation). Keeps retrying while the scooter is off
+    let mut connector = BleConnector::new(mac, token, scooter.model)
+        .with_adapter(CONFIG.bluetooth.adapter.clone());
+    if let Some(path) = &scooter.record {
+        info!("Recording bluetooth traffic of {} to {}", name, path);
+        connector = connector.with_recorder(Recorder::create(path)?);
+    }
+    let mut supervisor = Supervisor::new(connector, CONFIG.reconnect.backoff());
+
+    //Firmware can only change while scooter is away, so device info is sent again after every login
+    let device_info_due = Arc::new(AtomicBool::new(true));
+    let device_info_retry = Arc::new(Mutex::new(Retry::new(Backoff {
+        initial: Duration::from_secs(10),
+        max: Duration::from_secs(600),
+        ..Backoff::default()
+    })));
+
+    let mut states = supervisor.subscribe();
+    let login_seen = device_info_due.clone();
+    tokio::spawn(async move {
+        while states.changed().await.is_ok() {
+            let state = states.borrow_and_update().clone();
+            info!("Scooter {} link: {:?}", name, state);
+            if state == SupervisorState::LoggingIn {
+                login_seen.store(true, Ordering::Relaxed);
             }
         }
+    });
 
-        info!("Connection established. Now trying to log in");
+    let topic = scooter.topic(&CONFIG.mqtt);
+    let device_topic = scooter.device_topic(&CONFIG.mqtt);
+    let events_topic = scooter.events_topic(&CONFIG.mqtt);
+    let faults = Arc::new(Mutex::new(FaultTracker::new()));
+    let interval = scooter.poll_interval(&CONFIG.mqtt);
+    let bms_rate = Arc::new(Mutex::new(LowRate::new(CONFIG.mqtt.bms_interval())));
 
-        //Once connected, login into the scooter (key exchange, read more in the protocol documentation)
-        let mut request = match LoginRequest::new(&device, &token).await {
-            Ok(req) => req,
-            Err(e) => {
-                error!("Failed to create login request: {}", e);
-                tokio::time::sleep(Duration::from_secs(2)).await;
-                continue; //Try again at next iteration
+    monitor(&mut supervisor, &adapter, interval, &stop, &current, |session| {
+        let mqtt = mqtt.clone();
+        let gps = gps.clone();
+        let bms_rate = bms_rate.clone();
+        let device_info_due = device_info_due.clone();
+        let device_info_retry = device_info_retry.clone();
+        let device_topic = device_topic.clone();
+        let events_topic = events_topic.clone();
+        let faults = faults.clone();
+
+        Box::pin(async move {
+            //Device info is nice to have, it must not hold back telemetry. It is retried later when it fails
+            if device_info_due.load(Ordering::Relaxed) && device_info_retry.lock().await.is_due() {
+                match publish_device_info(&session, &mqtt, name, &device_topic).await {
+                    Ok(()) => {
+                        device_info_due.store(false, Ordering::Relaxed);
+                        device_info_retry.lock().await.succeeded();
+                    }
+                    Err(e) => {
+                        warn!("Could not read device info of {}, retrying later: {}", name, e);
+                        device_info_retry.lock().await.failed();
+                    }
+                }
             }
-        };
 
-        //Start the session
-        match request.start().await {
-            Ok(se) => return Ok(se),
+            //Battery identity and lifetime counters change slowly, they are sent only once in a while.
+            //Failed read is retried with next poll and does not hold back telemetry
+            let bms_info = if bms_rate.lock().await.is_due() {
+                match session.run(Priority::Normal, |session| Box::pin(session.bms_info())).await {
+                    Ok(bms_info) => {
+                        bms_rate.lock().await.mark_done();
+                        Some(bms_info)
+                    }
+                    Err(e) => {
+                        warn!("Could not read BMS info of {}: {}", name, e);
+                        None
+                    }
+                }
+            } else {
+                None
+            };
+
+            //GPS is shared by the whole fleet, it is locked only while reading the fix.
+            //Without a fix telemetry is still sent, with Null Island like when GPS is not ready
+            let gps_info = {
+                let mut port = gps.lock().await;
+                GPSInfo::get_gps_position(&mut **port)
+            }
+            .unwrap_or_else(|e| {
+                warn!("Could not read GPS for {}: {}", name, e);
+                GPSInfo::null_island()
+            });
+
+            let data = session
+                .run(Priority::Normal, move |session| Box::pin(Telemetry::pull_scooter(session, gps_info)))
+                .await?
+                .with_scooter(name)
+                .with_bms_info(bms_info);
+
+            //Errors and warnings are sent when they appear or clear, not with every reading
+            let events = faults.lock().await.update(&data.esc_status);
+            for event in events {
+                let event = event.with_scooter(name);
+                info!("Scooter {} fault: {:?}", name, event);
+                match serde_json::to_string(&event) {
+                    Ok(json_payload) => {
+                        let msg = paho_mqtt::Message::new(events_topic.as_str(), json_payload, paho_mqtt::QOS_1);
+                        if let Err(e) = mqtt.publish(msg).await {
+                            error!("Failed to send MQTT message: {:?}", e);
+                        }
+                    }
+                    Err(e) => error!("Could not serialize fault event: {}", e),
+                }
+            }
+
+            let json_payload = match serde_json::to_string(&data) {
+                Ok(json_payload) => json_payload,
+                Err(e) => {
+                    error!("Could not serialize telemetry: {}", e);
+                    return Ok(());
+                }
+            };
+
+            info!("Publishing message: {}", json_payload);
+            //We use QOS_0 since it is high-frequency and less important data, then it is acceptable to miss a few updates.
+            let msg = paho_mqtt::Message::new(topic, json_payload, paho_mqtt::QOS_0);
+
+            if let Err(e) = mqtt.publish(msg).await {
+                error!("Failed to send MQTT message: {:?}", e);
+            }
+
+            Ok(())
+        })
+    })
+    .await;
+
+    //Stopping, let the scooter go so other clients can connect to it
+    supervisor.release().await;
+
+    Ok(())
+}
+
+/**
+ Sends settings received on the command topics to the logged in session of the scooter, ahead of its polls
+*/
+async fn forward_commands(
+    commands: AsyncReceiver<Option<Message>>,
+    sessions: HashMap<String, watch::Receiver<Option<ScooterSession>>>,
+) {
+    while let Ok(message) = commands.recv().await {
+        //None while the broker connection is down, subscriptions come back with it
+        let Some(message) = message else { continue };
+        let Some(session) = sessions.get(message.topic()) else { continue };
+
+        let command = match Command::parse(message.payload()) {
+            Ok(command) => command,
             Err(e) => {
-                error!("Failed to start session: {}", e);
-                tokio::time::sleep(Duration::from_secs(2)).await;
+                warn!("Ignoring invalid command on {}: {}", message.topic(), e);
                 continue;
             }
         };
-    }
-}
 
-async fn link_scooter(
-    connection: ConnectionHelper,
-    device: &Peripheral,
-    token: &AuthToken,
-) -> Result<MiSession> {
-    loop {
-        info!("Establishing connection with the scooter");
-        match connection.reconnect().await {
-            Ok(_) => {}
-            Err(..) => {
-                info!("Can't reach the scooter! Is it powered on? Relaunching the program....");
-                return Err(anyhow!("Can't reach the scooter! Is it power on?"));
-            }
+        //Handle is cloned only for this command, the session goes back to the supervisor once it is done
+        let Some(session) = session.borrow().clone() else {
+            warn!("Scooter of {} is not connected, dropping {:?}", message.topic(), command);
+            continue;
         };
 
-        info!("Connection established. Now trying to log in");
-        //Once connected, login into the scooter (key exchange, read more in the protocol documentation)
-        let mut request = match LoginRequest::new(&device, &token).await {
-            Ok(req) => req,
-            Err(e) => {
-                error!("Failed to create login request: {}", e);
-                tokio::time::sleep(Duration::from_secs(2)).await;
-                continue; //Try again at next iteration
+        let topic = message.topic().to_owned();
+        tokio::spawn(async move {
+            info!("Sending {:?} from {}", command, topic);
+            if let Err(e) = command.send(&session).await {
+                error!("Could not send command from {}: {}", topic, e);
             }
-        };
+        });
+    }
+}
 
-        //Start the session
-        match request.start().await {
-            Ok(se) => return Ok(se),
+/**
+ Resolves on Ctrl+C, or on SIGTERM sent by systemd/docker when the service is stopped
+*/
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            error!("Could not listen for Ctrl+C: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
             Err(e) => {
-                error!("Failed to start session: {}", e);
-                tokio::time::sleep(Duration::from_secs(2)).await;
-                continue;
+                error!("Could not listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
             }
-        };
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
     }
 }
 
@@ -135,32 +327,6 @@ async fn main() -> Result<()> {
         .with_span_events(FmtSpan::CLOSE)
         .init();
 
-    //Load token
-    let token = load_token().await?;
-    //Load MAC
-    let mac = load_mac().await?;
-
-    info!("Searching scooter with address: {}", mac);
-
-    //Search for scooter with the MAC address provided
-    let mut scanner = ScooterScanner::new().await?; //TODO TIMEOUT?
-    let scooter = scanner.wait_for(&mac).await?;
-    let device = scanner.peripheral(&scooter).await?;
-
-    //Connect with the scooter
-    let mut connection = ConnectionHelper::new(&device);
-
-    //This inside function
-    let mut session = match link_scooter(connection, &device, &token).await {
-        Ok(ses) => ses,
-        Err(e) => {
-            error!("{}", e);
-            exit(1);
-        }
-    };
-
-    //Once we establish an encrypted connection with the scooter, continue the flow by connecting to the MQTT broker
-
     //Call MQTT
     let mqtt_client = MqttClient::new().await?;
 
@@ -172,33 +338,41 @@ async fn main() -> Result<()> {
 
     //Enable GPS
     enable_gps(&mut *port).expect("Can't enable GPS");
+    let gps = Arc::new(Mutex::new(port));
 
-    loop {
-        let data = match Telemetry::pull_scooter(&mut session, &mut *port).await {
-            Ok(data) => data,
-            Err(e) => {
-                error!("Error pulling data from scooter: {}", e);
-                connection = ConnectionHelper::new(&device);
-                session = match relink_scooter(connection, &device, &token, session).await {
-                    Ok(ses) => ses,
-                    Err(..) => {
-                        exit(1);
-                    }
-                };
-                continue; //Try to pull data again on next iteration
-            }
-        };
+    //One supervised session per scooter, taking turns on the adapter when connections are limited
+    let adapter = CONFIG.bluetooth.share();
+    let stop = CancellationToken::new();
 
-        let json_payload = serde_json::to_string(&data)?;
+    let shutdown = stop.clone();
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        info!("Stopping, releasing scooters");
+        shutdown.cancel();
+    });
 
-        info!("Publishing message: {}", json_payload);
-        //We use QOS_0 since it is high-frequency and less important data, then it is acceptable to miss a few updates.
-        let msg = paho_mqtt::Message::new(&CONFIG.mqtt.topic, json_payload, paho_mqtt::QOS_0);
+    let mut scooters = JoinSet::new();
+    let mut sessions = HashMap::new();
+    for scooter in CONFIG.fleet() {
+        info!("Monitoring scooter {}", scooter.name());
+        let (current, session) = watch::channel(None);
+        sessions.insert(scooter.command_topic(&CONFIG.mqtt), session);
+        scooters.spawn(monitor_scooter(
+            scooter,
+            mqtt_client.client.clone(),
+            gps.clone(),
+            adapter.clone(),
+            current,
+            stop.clone(),
+        ));
+    }
+    tokio::spawn(forward_commands(mqtt_client.commands.clone(), sessions));
 
-        if let Err(e) = mqtt_client.client.publish(msg).await {
-            error!("Failed to send MQTT message: {:?}", e);
+    while let Some(result) = scooters.join_next().await {
+        if let Err(e) = result? {
+            error!("Scooter monitor stopped: {}", e);
         }
-
-        tokio::time::sleep(Duration::from_secs(CONFIG.mqtt.send_interval)).await;
     }
+
+    Ok(())
 }
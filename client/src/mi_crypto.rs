@@ -8,7 +8,6 @@ use hkdf::Hkdf;
 use hmac::{Hmac, Mac};
 use p256::{PublicKey, ecdh::EphemeralSecret};
 use rand_core::{OsRng, RngCore};
-use anyhow::Result;
 use thiserror::Error;
 
 type HmacSha256 = Hmac<Sha256>;
@@ -18,15 +17,40 @@ type AesCcm = Ccm<Aes128, U4, U12>; //Block cypher for encryption/decryption
 pub enum MiCryptoError {
   #[error("Header for message is invalid")]
   InvalidHeader,
+  #[error("Message is truncated: expected {expected} bytes, received {received}")]
+  Truncated { expected: usize, received: usize },
+  #[error("Length byte does not match message: expected {expected} bytes, received {received}")]
+  BadLength { expected: usize, received: usize },
+  #[error("Checksum for message is invalid")]
+  BadChecksum,
+  #[error("Scooter sent stale or replayed counter {received:#06x}, last accepted was {last:#010x}")]
+  ReplayedCounter { received: u16, last: u32 },
+  #[error("Uart counter exhausted, login again to get new keys")]
+  CounterExhausted,
   #[error("Error when tried decrypt uart message: {0}")]
   DecryptUart(ccm::aead::Error),
-  #[error("Crypto Failure: {0}")]
-  Other(anyhow::Error)
 }
 
-impl From<anyhow::Error> for MiCryptoError {
-  fn from(other: anyhow::Error) -> Self {
-    MiCryptoError::Other(other)
+impl MiCryptoError {
+  /**
+   * Frame was damaged on the way (cut, wrong length or checksum). Such frame can be dropped and
+   * communication can go on, unlike with invalid keys.
+   */
+  pub fn is_corrupted_frame(&self) -> bool {
+    matches!(self,
+      MiCryptoError::InvalidHeader |
+      MiCryptoError::Truncated { .. } |
+      MiCryptoError::BadLength { .. } |
+      MiCryptoError::BadChecksum
+    )
+  }
+
+  /**
+   * Frame can be dropped while waiting for reply: it is damaged, or it is an old frame scooter
+   * (or someone else) sent again. Keys are still good in both cases.
+   */
+  pub fn is_ignorable(&self) -> bool {
+    self.is_corrupted_frame() || matches!(self, MiCryptoError::ReplayedCounter { .. })
   }
 }
 
@@ -201,12 +225,86 @@ pub fn gen_rand_key() -> RandKey {
   data
 }
 
-const HEADER : [u8; 2] = [0x55, 0xab];
+pub const HEADER : [u8; 2] = [0x55, 0xab];
+pub const LEGACY_HEADER : [u8; 2] = [0x55, 0xaa];
+
+/**
+ * Bytes in frame not counted by its length byte
+ * 55 AB L it(2) ct(L + 9) ck(2)
+ * 55 AA L D T c data(L - 2) ck(2)
+ */
+pub const ENCRYPTED_OVERHEAD : usize = 16;
+pub const LEGACY_OVERHEAD : usize = 6;
+
+/**
+ * Iteration counters used in nonce of encrypted uart frames. AES-CCM must never see the same nonce
+ * twice, so every frame gets new counter, one sequence for each direction. Only lower 16 bits
+ * travel inside the frame, upper ones are tracked here.
+ */
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct UartCounters {
+  /**
+   * Counter for next frame sent to scooter
+   */
+  pub tx: u32,
+  /**
+   * Last counter accepted from scooter
+   */
+  pub rx: Option<u32>,
+}
+
+impl UartCounters {
+  /**
+   * Take counter for next frame we send
+   */
+  pub fn next_tx(&mut self) -> Result<u32, MiCryptoError> {
+    if self.tx == u32::MAX {
+      return Err(MiCryptoError::CounterExhausted)
+    }
+
+    let it = self.tx;
+    self.tx += 1;
+    Ok(it)
+  }
+
+  /**
+   * Rebuild full counter from lower 16 bits sent by scooter. Counter must always go forward, by less
+   * than half of 16 bit range, this way it keeps working when lower bits wrap around. Anything else
+   * is stale or replayed frame.
+   */
+  pub fn expected_rx(&self, received: u16) -> Result<u32, MiCryptoError> {
+    let last = match self.rx {
+      Some(last) => last,
+      None => return Ok(received as u32)
+    };
+
+    let step = received.wrapping_sub(last as u16);
+    if step == 0 || step >= 0x8000 {
+      return Err(MiCryptoError::ReplayedCounter { received, last })
+    }
+
+    last.checked_add(step as u32).ok_or(MiCryptoError::CounterExhausted)
+  }
+
+  /**
+   * Remember counter of frame that was decrypted successfully
+   */
+  pub fn accept_rx(&mut self, it: u32) {
+    self.rx = Some(it);
+  }
+}
+
+/**
+ * Lower 16 bits of counter carried by encrypted frame
+ */
+pub fn uart_counter(msg: &[u8]) -> Option<u16> {
+  msg.get(3..5).map(|it| u16::from_le_bytes([it[0], it[1]]))
+}
 
 pub fn encrypt_uart(encryption_key: &EncryptionKey, msg: &[u8], it : u32, rand: Option<[u8; 4]>) -> Vec<u8> {
   tracing::debug!("Encrypting UART");
 
-  let it = it.to_be_bytes();
+  let it = it.to_le_bytes(); // Same byte order decrypt_uart expects, lower half goes into frame
 
   let rand = rand.or_else(|| {
     let mut rand : [u8; 4] = [0u8; 4];
@@ -262,27 +360,64 @@ pub fn encrypt_uart(encryption_key: &EncryptionKey, msg: &[u8], it : u32, rand:
   send_data
 }
 
+/**
+ * Checksum used by all uart frames: sum of bytes, inverted, little endian
+ */
 pub fn crc16(bytes: &[u8]) -> [u8; 2] {
-  let mut sum : i16 = 0;
-  for byte in bytes {
-    sum += *byte as i16;
-  }
+  let sum = bytes.iter()
+    .fold(0u16, |sum, byte| sum.wrapping_add(*byte as u16));
 
-  let mut res = (-(sum) - 1).to_be_bytes();
-  res.reverse();
-  res
+  (!sum).to_le_bytes()
 }
 
-pub fn decrypt_uart(encryption_key: &EncryptionKey, msg: &[u8]) -> Result<Vec<u8>, MiCryptoError> {
-  tracing::debug!("  Decrypting data: {:?}", msg.hex_dump());
-  let header = &msg[0..2];
+/**
+ * Bounds check frame that starts with header and length byte L, and has `overhead` bytes more than L.
+ * Checksum is verified over everything between header and checksum itself.
+ */
+pub(crate) fn check_frame(msg: &[u8], header: &[u8; 2], overhead: usize) -> Result<(), MiCryptoError> {
+  if msg.len() < 3 {
+    return Err(MiCryptoError::Truncated { expected: overhead, received: msg.len() })
+  }
 
-  if header != HEADER {
-    tracing::error!("Invalid header: {:?}", header.hex_dump());
+  if msg[0..2] != *header {
+    tracing::error!("Invalid header: {:?}", (&msg[0..2]).hex_dump());
     return Err(MiCryptoError::InvalidHeader)
   }
 
-  let it = &msg[3..5];
+  let expected = msg[2] as usize + overhead;
+  if msg.len() < expected {
+    return Err(MiCryptoError::Truncated { expected, received: msg.len() })
+  }
+
+  if msg.len() > expected {
+    return Err(MiCryptoError::BadLength { expected, received: msg.len() })
+  }
+
+  let checksum = &msg[expected - 2..];
+  if crc16(&msg[2..expected - 2]) != checksum {
+    tracing::error!("Invalid checksum: {:?}", checksum.hex_dump());
+    return Err(MiCryptoError::BadChecksum)
+  }
+
+  Ok(())
+}
+
+/**
+ * Decrypt frame, assuming upper 16 bits of its counter are zero
+ */
+pub fn decrypt_uart(encryption_key: &EncryptionKey, msg: &[u8]) -> Result<Vec<u8>, MiCryptoError> {
+  let it = uart_counter(msg).unwrap_or(0);
+  decrypt_uart_at(encryption_key, msg, it as u32)
+}
+
+/**
+ * Decrypt frame using full counter, see UartCounters::expected_rx
+ */
+pub fn decrypt_uart_at(encryption_key: &EncryptionKey, msg: &[u8], it: u32) -> Result<Vec<u8>, MiCryptoError> {
+  tracing::debug!("  Decrypting data: {:?}", msg.hex_dump());
+  check_frame(msg, &HEADER, ENCRYPTED_OVERHEAD)?;
+
+  let it = it.to_le_bytes();
   let ct = &msg[5..msg.len() - 2];
 
   tracing::debug!("  it: {:?}", it.hex_dump());
@@ -292,8 +427,7 @@ pub fn decrypt_uart(encryption_key: &EncryptionKey, msg: &[u8]) -> Result<Vec<u8
   let mut nonce : Vec<u8> = Vec::new();
   nonce.extend_from_slice(&encryption_key.iv);
   for _ in 0..4 { nonce.push(0); }
-  nonce.extend_from_slice(it);
-  for _ in 0..2 { nonce.push(0); }
+  nonce.extend_from_slice(&it);
   tracing::debug!("  nonce: {:?}", nonce.hex_dump());
 
   let key = GenericArray::from_slice(&encryption_key.key);
@@ -317,3 +451,31 @@ pub fn decrypt_uart(encryption_key: &EncryptionKey, msg: &[u8]) -> Result<Vec<u8
 
   Ok(data)
 }
+
+/**
+ * Wrap command into plain 55AA frame, used by legacy firmware (ESC < 1.5) that has no encryption
+ */
+pub fn encode_uart(msg: &[u8]) -> Vec<u8> {
+  let mut send_data : Vec<u8> = Vec::new();
+  send_data.extend_from_slice(&LEGACY_HEADER);
+  send_data.extend_from_slice(msg);
+  send_data.extend_from_slice(&crc16(msg));
+
+  tracing::debug!("  Plain data: {:?}", send_data.hex_dump());
+
+  send_data
+}
+
+/**
+ * Unwrap plain 55AA frame. Returns same layout as decrypt_uart: direction, read/write, attribute and data
+ */
+pub fn decode_uart(msg: &[u8]) -> Result<Vec<u8>, MiCryptoError> {
+  tracing::debug!("  Decoding data: {:?}", msg.hex_dump());
+  check_frame(msg, &LEGACY_HEADER, LEGACY_OVERHEAD)?;
+
+  if msg[2] < 2 { // Length counts at least read/write and attribute
+    return Err(MiCryptoError::BadLength { expected: LEGACY_OVERHEAD + 2, received: msg.len() })
+  }
+
+  Ok(msg[3..msg.len() - 2].to_vec())
+}
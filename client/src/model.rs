@@ -0,0 +1,41 @@
+use serde::Deserialize;
+
+/**
+ * Scooter models we know how to talk to. Model decides which protocol family is used on uart:
+ * xiaomi scooters use 55AB (or 55AA on legacy firmware), ninebot/segway ones use 5AA5 frames.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScooterModel {
+  #[default]
+  M365,
+  M365Pro,
+  Pro2,
+  Essential,
+  /**
+   * Ninebot/Segway scooter whose firmware talks plain (unencrypted) 5AA5 frames. Max G30 and other
+   * current firmware encrypt them and are not supported, see ninebot module.
+   */
+  NinebotPlain,
+}
+
+impl ScooterModel {
+  /**
+   * Scooter talks 5AA5 ninebot protocol instead of xiaomi one, and does not need mi login
+   */
+  pub fn is_ninebot(&self) -> bool {
+    matches!(self, ScooterModel::NinebotPlain)
+  }
+
+  /**
+   * Decode model from manufacturer data advertised under id 0x424e (bytes after the id).
+   * Only ids seen in captured advertisements are known (docs/protocol.md), None for the rest.
+   * Ninebot models are never detected this way, set them in config.
+   */
+  pub fn from_advertisement(data: &[u8]) -> Option<Self> {
+    match data.first() {
+      Some(0x20) => Some(ScooterModel::M365), // ff 4e42 20 00000000 df
+      _ => None
+    }
+  }
+}
@@ -1,12 +1,13 @@
 use crate::config::CONFIG;
 use anyhow::{anyhow, Error, Result};
-use paho_mqtt::AsyncClient;
+use paho_mqtt::{AsyncClient, AsyncReceiver, Message};
 use paho_mqtt::{ConnectOptionsBuilder, CreateOptionsBuilder};
 use std::time::Duration;
 use tracing::{error, info};
 
 pub struct MqttClient {
     pub client: AsyncClient,
+    pub commands: AsyncReceiver<Option<Message>>, // Messages of the command topics, None while reconnecting
 }
 
 impl MqttClient {
@@ -17,10 +18,21 @@ impl MqttClient {
             .mqtt_version(5) // Use MQTTv5
             .finalize();
 
-        let mqtt_client = AsyncClient::new(create_opts).unwrap_or_else(|err| {
+        let mut mqtt_client = AsyncClient::new(create_opts).unwrap_or_else(|err| {
             panic!("Error creating the MQTT client: {:?}", err);
         });
 
+        //Stream must exist before connecting. Clean start forgets subscriptions, they are made again on every connection
+        let commands = mqtt_client.get_stream(32);
+        let command_topics: Vec<String> = CONFIG
+            .fleet()
+            .iter()
+            .map(|scooter| scooter.command_topic(&CONFIG.mqtt))
+            .collect();
+        mqtt_client.set_connected_callback(move |client| {
+            client.subscribe_many_same_qos(&command_topics, paho_mqtt::QOS_1);
+        });
+
         let conn_opts = ConnectOptionsBuilder::new_v5()
             .keep_alive_interval(Duration::from_secs(CONFIG.mqtt.keep_alive))
             .automatic_reconnect(
@@ -52,6 +64,7 @@ impl MqttClient {
 
         Ok(MqttClient {
             client: mqtt_client,
+            commands,
         })
     }
 }
@@ -0,0 +1,107 @@
+use crate::mi_crypto::{MiCryptoError, crc16, check_frame};
+use crate::error::{M365Error, Result};
+
+use pretty_hex::*;
+
+/**
+ * Ninebot/Segway scooters talk 5AA5 frames over the same Nordic uart service:
+ *
+ * 5A A5 L src dst cmd arg payload(L) ck0 ck1
+ *
+ * Unlike 55AA, L is only payload length and every frame carries source and destination address.
+ * Checksum is calculated the same way as for xiaomi frames, from L to the end of payload.
+ *
+ * This is not Max G30 support. Support is limited on purpose:
+ * - only plain frames. Firmware that encrypts 5AA5 frames (NinebotCrypto), which includes current
+ *   Max G30 firmware, is detected by MiSession::start_ninebot and rejected with
+ *   LoginError::NinebotEncrypted
+ * - registers are the xiaomi ones (motor block 0xB0, battery 0x31...), there is no separate
+ *   ninebot register map, so only readings found at the same addresses work
+ * - model is not detected from advertisements, it has to be set in config
+ */
+pub const HEADER : [u8; 2] = [0x5a, 0xa5];
+
+/**
+ * Bytes in frame that are not payload: header, L, src, dst, cmd, arg and checksum
+ */
+pub const FRAME_OVERHEAD : usize = 9;
+
+/**
+ * Bus addresses
+ */
+pub const ESC : u8 = 0x20;
+pub const BLE : u8 = 0x21;
+pub const BMS : u8 = 0x22;
+pub const APP : u8 = 0x3e;
+
+/**
+ * Commands
+ */
+pub const CMD_READ : u8 = 0x01;
+pub const CMD_WRITE : u8 = 0x02;
+pub const CMD_WRITE_NR : u8 = 0x03; // Write without response
+pub const CMD_READ_REPLY : u8 = 0x04;
+pub const CMD_WRITE_REPLY : u8 = 0x05;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct NinebotFrame {
+  pub src: u8,
+  pub dst: u8,
+  pub cmd: u8,
+  /**
+   * Register address for read and write commands
+   */
+  pub arg: u8,
+  pub payload: Vec<u8>
+}
+
+impl NinebotFrame {
+  pub fn as_bytes(&self) -> Vec<u8> {
+    let mut msg : Vec<u8> = vec![self.payload.len() as u8, self.src, self.dst, self.cmd, self.arg];
+    msg.extend_from_slice(&self.payload);
+
+    let mut bytes : Vec<u8> = Vec::new();
+    bytes.extend_from_slice(&HEADER);
+    bytes.extend_from_slice(&msg);
+    bytes.extend_from_slice(&crc16(&msg));
+    bytes
+  }
+
+  /**
+   * Translate frame into the same layout decrypt_uart returns: direction, read/write, attribute and data.
+   * This way session commands can parse replies without knowing which protocol family is used.
+   */
+  pub fn to_uart_message(&self) -> Result<Vec<u8>> {
+    let direction = match self.dst {
+      // ESC 0x20 -> 0x23, BMS 0x22 -> 0x25
+      APP => self.src.checked_add(3)
+        .ok_or_else(|| M365Error::parse("ninebot frame", format!("source address {:#04x} has no reply direction", self.src)))?,
+      _ => self.dst
+    };
+
+    let read_write = match self.cmd {
+      CMD_READ | CMD_READ_REPLY => 0x01,
+      _ => 0x03
+    };
+
+    let mut msg = vec![direction, read_write, self.arg];
+    msg.extend_from_slice(&self.payload);
+    Ok(msg)
+  }
+}
+
+/**
+ * Parse 5AA5 frame and validate its checksum
+ */
+pub fn decode_frame(msg: &[u8]) -> std::result::Result<NinebotFrame, MiCryptoError> {
+  tracing::debug!("  Decoding ninebot frame: {:?}", msg.hex_dump());
+  check_frame(msg, &HEADER, FRAME_OVERHEAD)?;
+
+  Ok(NinebotFrame {
+    src: msg[3],
+    dst: msg[4],
+    cmd: msg[5],
+    arg: msg[6],
+    payload: msg[7..msg.len() - 2].to_vec()
+  })
+}
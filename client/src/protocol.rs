@@ -1,68 +1,44 @@
 use crate::consts::{MiCommands, Registers};
-use uuid::Uuid;
-use futures::Stream;
-use futures::stream::StreamExt;
+use crate::transport::{Transport, BleTransport};
+use crate::frame::FrameDecoder;
 use pretty_hex::*;
-use std::{pin::Pin, boxed::Box};
-use btleplug::platform::{Peripheral};
+use btleplug::platform::Peripheral;
 use tokio::time::timeout;
 use std::time::Duration;
-use btleplug::api::{Peripheral as _, Characteristic, WriteType, ValueNotification};
-use anyhow::{Context, Result, anyhow};
+use btleplug::api::ValueNotification;
+use crate::error::{M365Error, Result};
 
 const NB_CHUNK_SIZE : usize = 20;
 const MI_CHUNK_SIZE : usize = 18;
 
 /**
  * This structs hides all bluetooth shenanigans under easy to use commands.
+ * By default it talks to real scooter over btleplug, but any Transport can be used instead.
  */
-pub struct MiProtocol {
-  device: Peripheral,
-  avdtp: Characteristic,
-  upnp: Characteristic,
-  tx: Characteristic,
-  rx: Characteristic,
-  stream: Pin<Box<dyn Stream<Item = ValueNotification>>>, //Notifications from scooter
+pub struct MiProtocol<T: Transport = BleTransport> {
+  transport: T,
+  decoder: FrameDecoder, // Uart bytes received but not yet returned as frame
 }
 
 impl MiProtocol {
   pub async fn new(device: &Peripheral) -> Result<Self> {
-    let (avdtp, upnp, tx, rx) = setup_channels(&device).await?;
-    let stream : Pin<Box<dyn Stream<Item = ValueNotification>>> = device.notifications().await
-      .with_context(|| format!("Could not load notifications stream"))?;
-    let device = device.clone();
-
-    let instance = Self {
-      device,
-      stream,
-      avdtp,
-      upnp,
-      tx,
-      rx
-    };
-
-    Ok(instance)
-  }
+    let transport = BleTransport::new(device).await?;
 
-  pub async fn dispose(&self) -> Result<bool> { // Unsubscribe from all characteristics
-    self.device.unsubscribe(&self.avdtp).await?;
-    self.device.unsubscribe(&self.upnp).await?;
-    self.device.unsubscribe(&self.rx).await?;
+    Ok(Self::with_transport(transport))
+  }
+}
 
-    Ok(true)
+impl<T: Transport> MiProtocol<T> {
+  pub fn with_transport(transport: T) -> Self {
+    Self { transport, decoder: FrameDecoder::new() }
   }
 
-  /**
-   * Map register type to Bluetooth Characteristic channel
-   */
-  fn reg_to_channel(&self, reg : &Registers) -> Option<&Characteristic> {
-    match reg {
-      Registers::RX => Some(&self.rx),
-      Registers::TX => Some(&self.tx),
-      Registers::AVDTP => Some(&self.avdtp),
-      Registers::UPNP => Some(&self.upnp),
-      _ => None
-    }
+  pub async fn dispose(&mut self) -> Result<bool> { // Unsubscribe from all characteristics
+    self.transport.unsubscribe(&Registers::AVDTP).await?;
+    self.transport.unsubscribe(&Registers::UPNP).await?;
+    self.transport.unsubscribe(&Registers::RX).await?;
+
+    Ok(true)
   }
 
   /**
@@ -70,7 +46,7 @@ impl MiProtocol {
    */
   pub async fn next(&mut self) -> Option<ValueNotification> {
     tracing::debug!("Waiting for notifications...");
-    self.stream.next().await
+    self.transport.next().await
   }
 
   /**
@@ -79,8 +55,8 @@ impl MiProtocol {
   pub async fn wait_for_scooter_to_receive_data(&mut self) -> Result<bool> {
     match self.next_mi_response().await {
       Some(MiCommands::RCV_RDY) => Ok(true),
-      Some(state) => Err(anyhow!("Expected state: {:?}, but received: {:?}", MiCommands::RCV_RDY, state)),
-      None => Err(anyhow!("Invalid response received from scooter"))
+      Some(state) => Err(M365Error::unexpected(MiCommands::RCV_RDY, state)),
+      None => Err(M365Error::unexpected(MiCommands::RCV_RDY, "invalid response"))
     }
   }
 
@@ -90,8 +66,8 @@ impl MiProtocol {
   pub async fn wait_for_scooter_to_ack_data(&mut self) -> Result<bool> {
     match self.next_mi_response().await {
       Some(MiCommands::RCV_OK) => Ok(true),
-      Some(state) => Err(anyhow!("Expected state: {:?}, but received: {:?}", MiCommands::RCV_RDY, state)),
-      None => Err(anyhow!("Invalid response received from scooter"))
+      Some(state) => Err(M365Error::unexpected(MiCommands::RCV_OK, state)),
+      None => Err(M365Error::unexpected(MiCommands::RCV_OK, "invalid response"))
     }
   }
 
@@ -115,13 +91,14 @@ impl MiProtocol {
    * Try to read next notification, If nothing comes in specified duration throw error
    */
   pub async fn wait_for_notification_with_timeout(&mut self, duration : Duration) -> Result<ValueNotification> {
-    let response = timeout(duration, self.next()).await?;//TODO: map to timeout error
+    let response = timeout(duration, self.next()).await
+      .map_err(|_| M365Error::Timeout(duration))?;
 
     if let Some(notification) = response {
       return Ok(notification)
     }
 
-    Err(anyhow!("Received empty message from mi scooter..."))
+    Err(M365Error::Disconnected("notification stream closed".to_owned()))
   }
 
 
@@ -135,35 +112,52 @@ impl MiProtocol {
   /**
    * Send mi command to register on scooter
    */
-  pub async fn write(&self, reg: &Registers, command: MiCommands) -> Result<bool> {
-    let channel = self.reg_to_channel(reg).unwrap();
+  pub async fn write(&mut self, reg: &Registers, command: MiCommands) -> Result<bool> {
     tracing::debug!("-> {:?} -> {:?}", command, &reg);
 
-    self.device.write(&channel, &command.to_bytes(), WriteType::WithoutResponse).await
-      .with_context(|| format!("Could not write command: {:?} to {:?}", command, &reg))?; //Use closure: https://doc.rust-lang.org/rust-by-example/fn/closures.html
+    self.transport.write(reg, &command.to_bytes()).await?;
 
     Ok(true)
   }
 
   /**
-   * Ninebot protocol sends multiple messages. I don't know how long they will be, but this is persistent per command, so you can specify it as arg
+   * Forget bytes of unfinished frame, so they are not glued to the start of next reply
    */
-  pub async fn read_nb_parcel(&mut self, frames: u8) -> Result<Vec<u8>> {
-    let mut buffer : Vec<u8> = Vec::new();
-    let mut frames_left = frames;
+  pub fn clear_frames(&mut self) {
+    self.decoder.clear();
+  }
+
+  /**
+   * Read next whole uart frame (55AB, 55AA or 5AA5). Frames tell their own length, so notifications
+   * are collected until frame is complete, no matter how scooter splits it. Garbage before the
+   * header is skipped and bytes after the frame are kept for the next read.
+   */
+  pub async fn read_frame(&mut self) -> Result<Vec<u8>> {
     let duration = Duration::from_secs(5);
+    let rx = Registers::RX.to_uuid();
+
+    tracing::debug!("Reading uart frame");
+    loop {
+      if let Some(frame) = self.decoder.next_frame() {
+        tracing::debug!("  Finished reading: {:?}", frame.hex_dump());
+        return Ok(frame)
+      }
+
+      let notification = match self.wait_for_notification_with_timeout(duration).await {
+        Ok(notification) => notification,
+        Err(error) => {
+          self.decoder.clear(); // Rest of the frame is not coming anymore
+          return Err(error)
+        }
+      };
+      if notification.uuid != rx {
+        tracing::debug!("  Skipping notification from {}: {:?}", notification.uuid, notification.value.hex_dump());
+        continue;
+      }
 
-    tracing::debug!("Reading nb frames: {}", frames_left);
-    while frames_left > 0 {
-      tracing::debug!("  Reading frame...");
-      let notification = self.wait_for_notification_with_timeout(duration).await?;
       tracing::debug!("  Received data: {:?}", notification.value.hex_dump());
-      buffer.extend_from_slice(notification.value.as_slice());
-      frames_left -= 1;
+      self.decoder.push(&notification.value);
     }
-
-    tracing::debug!("  Finished reading: {:?}", buffer.hex_dump());
-    Ok(buffer)
   }
 
   /**
@@ -175,15 +169,19 @@ impl MiProtocol {
     let mut total_frames : u16 = 0;
     let mut received_data : Vec<u8> = Vec::new();
 
-    if let Some(data) = self.stream.next().await {
+    if let Some(data) = self.next().await {
+      if data.value.len() < 6 {
+        return Err(M365Error::parse("mi parcel header", format!("{:?}", data.value.hex_dump())))
+      }
+
       total_frames = data.value[4] as u16 + 0x100 * data.value[5] as u16; //Read protocol documentation for more info
       tracing::debug!("Expecting {} frames: {:?}", total_frames, data.value.hex_dump());
 
       self.write(reg, MiCommands::RCV_RDY).await?;
     }
 
-    while let Some(data) = self.stream.next().await {
-      let current_frame : u16 = what_frame(&data.value);
+    while let Some(data) = self.next().await {
+      let current_frame : u16 = what_frame(&data.value)?;
       tracing::debug!("Current frame {}: {:?}", current_frame, data.value.hex_dump());
 
       for i in 2..data.value.len() {
@@ -201,13 +199,10 @@ impl MiProtocol {
     Ok(received_data)
   }
 
-  pub async fn write_nb_parcel(&self, reg: &Registers, data: &[u8]) -> Result<bool> {
-    let channel = self.reg_to_channel(reg).unwrap();
-
+  pub async fn write_nb_parcel(&mut self, reg: &Registers, data: &[u8]) -> Result<bool> {
     for chunk in data.chunks(NB_CHUNK_SIZE) { //Slice data in chunks
       tracing::debug!("Writing nb chunk to {:?}: {:?}", reg, chunk.hex_dump());
-      self.device.write(&channel, &chunk, WriteType::WithoutResponse).await
-        .with_context(|| format!("Could not write mi chunk: for channel: {:?}", channel))?;
+      self.transport.write(reg, chunk).await?;
     }
 
     Ok(true)
@@ -216,10 +211,9 @@ impl MiProtocol {
   /**
    * Send big data parcel to scooter using mi protocol
    */
-  pub async fn write_mi_parcel(&self, reg: &Registers, data: &[u8]) -> Result<bool> {
+  pub async fn write_mi_parcel(&mut self, reg: &Registers, data: &[u8]) -> Result<bool> {
     let mut buffer : Vec<u8> = Vec::new();
     let mut chunk_index = 1;
-    let channel = self.reg_to_channel(reg).unwrap();
 
     for chunk in data.chunks(MI_CHUNK_SIZE) {
       buffer.clear();
@@ -231,8 +225,7 @@ impl MiProtocol {
       }
 
       tracing::debug!("Writing mi chunk {} to {:?}: {:?}", chunk_index, reg, buffer.hex_dump());
-      self.device.write(&channel, &buffer, WriteType::WithoutResponse).await
-        .with_context(|| format!("Could not write mi chunk: {} for channel: {:?}", chunk_index, channel))?;
+      self.transport.write(reg, &buffer).await?;
       chunk_index += 1;
     }
 
@@ -240,51 +233,9 @@ impl MiProtocol {
   }
 }
 
-async fn find_characteristic(device : &Peripheral, service_uuid: Uuid, char_uuid: Uuid) -> Result<Characteristic> {
-  device.discover_services().await
-    .with_context(|| format!("Could not enable discovering devices"))?;
-
-  for ch in device.characteristics() {
-    if ch.uuid == char_uuid && ch.service_uuid == service_uuid {
-      tracing::debug!("Found Characteristic: {:?}", ch);
-      return Ok(ch)
-    } else {
-      tracing::debug!("Skipped Characteristic: {:?}", ch);
-    }
-  }
-
-  Err(anyhow!("Could not find characteristic: {}", char_uuid))
-}
-
-// Get frame ID
-fn what_frame(bytes: &Vec<u8>) -> u16 {
-  bytes[0] as u16 & 0xff + 0x100 * bytes[1] as u16 & 0xff
-}
-
-async fn setup_channels(device : &Peripheral) -> Result<(Characteristic, Characteristic, Characteristic, Characteristic)> {
-  device.discover_services().await?;
-
-  // Auth channels
-  tracing::debug!("Setting up AUTH channels");
-  let avdtp = find_characteristic(device, Registers::AUTH.to_uuid(), Registers::AVDTP.to_uuid()).await?;
-  let upnp = find_characteristic(device, Registers::AUTH.to_uuid(), Registers::UPNP.to_uuid()).await?;
-
-  // UART channels
-  tracing::debug!("Setting up UART channels");
-  let tx = find_characteristic(device, Registers::UART.to_uuid(), Registers::TX.to_uuid()).await?;
-  let rx = find_characteristic(device, Registers::UART.to_uuid(), Registers::RX.to_uuid()).await?;
-
-  tracing::debug!("Enabling notify for AVDTP");
-  device.subscribe(&avdtp).await
-    .with_context(|| format!("Could not subscribe to scooter AVDTP notifications"))?;
-
-  tracing::debug!("Enabling notify for UPNP");
-  device.subscribe(&upnp).await
-    .with_context(|| format!("Could not subscribe to scooter UPNP notifications"))?;
-
-  tracing::debug!("Enabling notify for RX");
-  device.subscribe(&rx).await
-    .with_context(|| format!("Could not subscribe to scooter RX notifications"))?;
-
-  Ok((avdtp, upnp, tx, rx))
+// Get frame ID, first two bytes of every parcel notification (little endian)
+fn what_frame(bytes: &[u8]) -> Result<u16> {
+  bytes.get(0..2)
+    .map(|id| u16::from_le_bytes([id[0], id[1]]))
+    .ok_or_else(|| M365Error::parse("mi parcel frame", format!("{:?}", bytes.hex_dump())))
 }
@@ -1,12 +1,13 @@
 use crate::consts::{MiCommands, Registers};
 pub use crate::mi_crypto::AuthToken;
 use crate::protocol::MiProtocol;
+use crate::transport::{Transport, BleTransport};
 use crate::mi_crypto;
 
 use pretty_hex::*;
 use btleplug::platform::Peripheral;
 use p256::{PublicKey, ecdh::EphemeralSecret, EncodedPoint};
-use anyhow::{Result, anyhow};
+use crate::error::{M365Error, Result};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -15,18 +16,10 @@ pub enum RegistrationError {
   RegistrationFailed,
   #[error("Please restart connection and try again")]
   RestartNeeded,
-  #[error("Registration failed: {0}")]
-  Other(anyhow::Error)
 }
 
-impl From<anyhow::Error> for RegistrationError {
-  fn from(other: anyhow::Error) -> Self {
-    RegistrationError::Other(other)
-  }
-}
-
-pub struct RegistrationRequest {
-  protocol: MiProtocol,
+pub struct RegistrationRequest<T: Transport = BleTransport> {
+  protocol: MiProtocol<T>,
   my_secret_key: EphemeralSecret,
   my_public_key: PublicKey,
   remote_info: Option<Vec<u8>>,
@@ -40,18 +33,25 @@ impl RegistrationRequest {
   pub async fn new(device : &Peripheral) -> Result<Self> {
     let protocol = MiProtocol::new(device).await?;
 
+    Ok(Self::with_protocol(protocol))
+  }
+}
+
+impl<T: Transport> RegistrationRequest<T> {
+  /**
+   * Register over any transport, for example ChannelTransport when there is no bluetooth around
+   */
+  pub fn with_protocol(protocol: MiProtocol<T>) -> Self {
     let (my_secret_key, my_public_key) = mi_crypto::gen_key_pair();
     tracing::debug!("Public key: {:?}", my_public_key);
 
-    let request = Self {
+    Self {
       protocol,
       my_secret_key,
       my_public_key,
       remote_info: None,
       token: None
-    };
-
-    Ok(request)
+    }
   }
 
   /**
@@ -59,7 +59,7 @@ impl RegistrationRequest {
    * For this error please disconnect and connect again to scooter and ask user to press power button. Remember to create new instance of
    * RegistrationRequest and start process again. I know this sucks but this is how it works.
    */
-  pub async fn start(&mut self) -> Result<AuthToken, RegistrationError> {
+  pub async fn start(&mut self) -> Result<AuthToken> {
     self.read_remote_info().await?;
     self.send_public_key().await?;
     self.send_did().await?;
@@ -84,14 +84,14 @@ impl RegistrationRequest {
   /**
    * Send public key to scooter and then wait for scooter with
    */
-  async fn send_public_key(&mut self) -> Result<bool, RegistrationError> {
+  async fn send_public_key(&mut self) -> Result<bool> {
     self.protocol.write(&Registers::UPNP, MiCommands::CMD_SET_KEY).await?;
     self.protocol.write(&Registers::AVDTP, MiCommands::CMD_SEND_DATA).await?;
 
     let notification = self.protocol.wait_for_notification().await;
 
     if notification.is_err() {
-      return Err(RegistrationError::RestartNeeded)
+      return Err(RegistrationError::RestartNeeded.into())
     }
 
     let notification = notification.unwrap();
@@ -105,10 +105,10 @@ impl RegistrationRequest {
       },
       Ok(other) => {
         tracing::debug!("Could not match: {:?}", other);
-        return Err(RegistrationError::Other(anyhow!("Scooter responded with: {:?} instead RCV_RDY", other)))
+        return Err(M365Error::unexpected(MiCommands::RCV_RDY, other))
       }
       Err(err) => {
-        return Err(RegistrationError::Other(anyhow!(err)))
+        return Err(M365Error::unexpected(MiCommands::RCV_RDY, err))
       }
     }
 
@@ -118,7 +118,7 @@ impl RegistrationRequest {
       return Ok(true)
     }
 
-    Err(RegistrationError::Other(anyhow!("Sending public key failed...")))
+    Err(M365Error::unexpected(MiCommands::RCV_OK, "no confirmation for public key"))
   }
 
   async fn send_did(&mut self) -> Result<bool> {
@@ -142,7 +142,7 @@ impl RegistrationRequest {
         }
         _ => {
           tracing::error!("Scooter did not receive public key");
-          return Err(anyhow!("Scooter did not receive public key"));
+          return Err(M365Error::unexpected(MiCommands::RCV_OK, "no confirmation for did"));
         }
       }
     }
@@ -150,7 +150,7 @@ impl RegistrationRequest {
     Ok(true)
   }
 
-  async fn perform_auth(&mut self) -> Result<bool, RegistrationError> {
+  async fn perform_auth(&mut self) -> Result<bool> {
     self.protocol.write(&Registers::UPNP, MiCommands::CMD_AUTH).await?;
     match self.protocol.next_mi_response().await {
       Some(MiCommands::RCV_AUTH_OK) => {
@@ -160,12 +160,12 @@ impl RegistrationRequest {
       Some(error) => {
         // something bad happened, error
         tracing::error!("Registration failed: {:?}", error);
-        return Err(RegistrationError::RegistrationFailed)
+        return Err(RegistrationError::RegistrationFailed.into())
       },
 
       None => {
         tracing::error!("Registration failed, scooter did not respond");
-        return Err(RegistrationError::RegistrationFailed)
+        return Err(RegistrationError::RegistrationFailed.into())
       }
     }
 
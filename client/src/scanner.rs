@@ -1,37 +1,48 @@
-use std::hash::Hash;
-use anyhow::Result;
+use std::hash::{Hash, Hasher};
 use tokio::sync::mpsc;
-use std::collections::HashSet;
+use std::collections::HashMap;
 use futures::stream::StreamExt;
 use btleplug::platform::{Adapter, Manager, PeripheralId, Peripheral};
-use btleplug::api::{Central, Manager as _, ScanFilter, BDAddr, Peripheral as _, CentralEvent};
+use btleplug::api::{Central, CentralState, ScanFilter, BDAddr, Peripheral as _, CentralEvent};
+use crate::adapter::{find_adapter, AdapterInfo, AdapterSelector};
+use crate::model::ScooterModel;
+use uuid::Uuid;
 use thiserror::Error;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::sync::RwLock;
+use tokio::time::sleep_until;
+use tokio_util::sync::CancellationToken;
 
-type Devices = Arc<RwLock<HashSet<TrackedDevice>>>; //Store and manage a set of discovered scooters in a thread-safe manner
+type Devices = Arc<RwLock<DeviceTracker>>; //Store and manage discovered devices in a thread-safe manner
+
+/**
+ * Device that was not advertising for this long is reported as lost
+ */
+pub const DEFAULT_LOST_AFTER : Duration = Duration::from_secs(30);
 
 /**
  * All xiaomi scooters start with name MIScooter and random numbers after that
  */
 const XIAOMI_SCOOTER_NAME : &str = "MIScooter";
 
+/**
+ * Scooters advertise custom AD ff 4e42 20 00000000 df, manufacturer id "NB" (0x424e) followed by model byte
+ */
+pub const SCOOTER_MANUFACTURER_ID : u16 = 0x424e;
+
 #[derive(Error, Debug)]
 pub enum ScannerError {
-  #[error("Could not find scooter with addr: {0}")]
-  WaitForScooterFailed(BDAddr),
+  #[error("Could not find scooter with addr: {addr} in {scanned_for:?}, other scooters seen: {}", addresses(.seen))]
+  WaitForScooterFailed { addr: BDAddr, scanned_for: Duration, seen: Vec<TrackedDevice> },
+  #[error("Scan was cancelled")]
+  Cancelled,
   #[error("Could not find working bluetooth adapter")]
   MissingCentral, //Bluetooth Adapter error on host
+  #[error("Could not find bluetooth adapter {requested}, available adapters: {}", adapter_list(.available))]
+  AdapterNotFound { requested: AdapterSelector, available: Vec<AdapterInfo> },
   #[error("Bluetooth error: {0}")]
   BluetoothError(btleplug::Error),
-  #[error("Registration failed: {0}")]
-  Other(anyhow::Error)
-}
-
-impl From<anyhow::Error> for ScannerError {
-  fn from(other: anyhow::Error) -> Self {
-    ScannerError::Other(other)
-  }
 }
 
 impl From<btleplug::Error> for ScannerError {
@@ -40,27 +51,69 @@ impl From<btleplug::Error> for ScannerError {
   }
 }
 
+#[cfg(target_os = "linux")]
+impl From<bluez_async::BluetoothError> for ScannerError {
+  fn from(other: bluez_async::BluetoothError) -> Self {
+    ScannerError::BluetoothError(other.into())
+  }
+}
+
 #[derive(Clone, Debug)]
 pub enum ScannerEvent {
-  DiscoveredScooter(TrackedDevice)
+  DiscoveredScooter(TrackedDevice),
+  /**
+   * Scooter seen before advertised again, with fresh signal strength
+   */
+  Updated(TrackedDevice),
+  /**
+   * Scooter was silent for longer than lost timeout, probably out of range or switched off
+   */
+  Lost(TrackedDevice),
 }
 
-#[derive(Clone, Debug, Hash, Eq)]
+#[derive(Clone, Debug, Eq)]
 pub struct TrackedDevice {  //Store discovered bluetooth device details
   pub id: PeripheralId,
   pub addr: BDAddr,
   pub name: Option<String>,
+  /**
+   * Signal strength of last advertisement, in dBm
+   */
+  pub rssi: Option<i16>,
+  /**
+   * Transmission power announced by device, in dBm
+   */
+  pub tx_power: Option<i16>,
+  pub last_seen: Instant,
+  /**
+   * Advertised manufacturer data, by manufacturer id
+   */
+  pub manufacturer_data: HashMap<u16, Vec<u8>>,
+  /**
+   * Advertised services, scooters send Nordic UART service in scan response
+   */
+  pub services: Vec<Uuid>,
 }
 
 impl TrackedDevice {
   /**
-   * Check if current device is possible the scooter
+   * Check if current device is possible the scooter. Name can be changed, so scooter manufacturer
+   * data counts too. Nordic UART service alone is not enough, plenty of other gadgets advertise it.
    */
-  pub fn is_scooter(&self) -> bool { //Checks if device name starts with MIScooter -> Scooter detected
-    if let Some(name) = &self.name {
-      return name.starts_with(XIAOMI_SCOOTER_NAME);
+  pub fn is_scooter(&self) -> bool {
+    if self.manufacturer_data.contains_key(&SCOOTER_MANUFACTURER_ID) {
+      return true;
     }
-    return false;
+
+    self.name.as_ref().is_some_and(|name| name.starts_with(XIAOMI_SCOOTER_NAME))
+  }
+
+  /**
+   * Model decoded from advertisement, when it is known
+   */
+  pub fn model(&self) -> Option<ScooterModel> {
+    self.manufacturer_data.get(&SCOOTER_MANUFACTURER_ID)
+      .and_then(|data| ScooterModel::from_advertisement(data))
   }
 }
 
@@ -70,6 +123,105 @@ impl PartialEq for TrackedDevice {
   }
 }
 
+impl Hash for TrackedDevice {
+  fn hash<H: Hasher>(&self, state: &mut H) {
+    self.addr.hash(state);
+  }
+}
+
+/**
+ * Latest known state of every device around. Scanner feeds it with advertisements and
+ * asks it periodically which devices went silent.
+ */
+#[derive(Debug)]
+pub struct DeviceTracker {
+  devices: HashMap<BDAddr, TrackedDevice>,
+  lost_after: Duration,
+}
+
+impl DeviceTracker {
+  pub fn new(lost_after: Duration) -> Self {
+    Self { devices: HashMap::new(), lost_after }
+  }
+
+  pub fn lost_after(&self) -> Duration {
+    self.lost_after
+  }
+
+  /**
+   * Record advertisement of device. Values missing in advertisement are kept from previous ones.
+   * Returns event to emit when device is a scooter.
+   */
+  pub fn seen(&mut self, device: TrackedDevice) -> Option<ScannerEvent> {
+    let (device, discovered) = match self.devices.remove(&device.addr) {
+      Some(known) => {
+        let was_scooter = known.is_scooter();
+        (merge(known, device), !was_scooter) // Scan response can reveal scooter seen before
+      },
+      None => (device, true)
+    };
+
+    self.devices.insert(device.addr, device.clone());
+
+    if !device.is_scooter() {
+      return None
+    }
+
+    match discovered {
+      true => Some(ScannerEvent::DiscoveredScooter(device)),
+      false => Some(ScannerEvent::Updated(device))
+    }
+  }
+
+  /**
+   * Forget devices silent for longer than lost timeout. Returns Lost events for scooters.
+   */
+  pub fn expire(&mut self, now: Instant) -> Vec<ScannerEvent> {
+    let lost : Vec<BDAddr> = self.devices.values()
+      .filter(|device| now.saturating_duration_since(device.last_seen) > self.lost_after)
+      .map(|device| device.addr)
+      .collect();
+
+    lost.iter()
+      .filter_map(|addr| self.devices.remove(addr))
+      .inspect(|device| tracing::debug!("Lost device: {}", device.addr))
+      .filter(|device| device.is_scooter())
+      .map(ScannerEvent::Lost)
+      .collect()
+  }
+
+  pub fn get(&self, addr: &BDAddr) -> Option<&TrackedDevice> {
+    self.devices.get(addr)
+  }
+
+  pub fn devices(&self) -> impl Iterator<Item = &TrackedDevice> {
+    self.devices.values()
+  }
+}
+
+fn merge(known: TrackedDevice, update: TrackedDevice) -> TrackedDevice {
+  let mut manufacturer_data = known.manufacturer_data;
+  manufacturer_data.extend(update.manufacturer_data);
+
+  let mut services = known.services;
+  for service in update.services {
+    if !services.contains(&service) {
+      services.push(service);
+    }
+  }
+
+  TrackedDevice {
+    id: update.id,
+    addr: update.addr,
+    name: update.name.or(known.name),
+    rssi: update.rssi.or(known.rssi),
+    tx_power: update.tx_power.or(known.tx_power),
+    last_seen: update.last_seen.max(known.last_seen),
+    manufacturer_data,
+    services,
+  }
+}
+
 /**
  * Use scooter scanner to find scooter.
  * By default all Xiaomi scooter names start with MIScooter and then have few digits after name.
@@ -79,42 +231,94 @@ impl PartialEq for TrackedDevice {
 pub struct ScooterScanner {
   devices: Devices,
   pub central: Adapter, //Bluetooth adapter of host device
+  adapter: AdapterInfo,
+  scan: Option<CancellationToken>, // Stops events processor of running scan
 }
 
 impl ScooterScanner {
+  /**
+   * Scan with first adapter of the host
+   */
   pub async fn new() -> Result<Self, ScannerError> {
+    Self::with_adapter(&AdapterSelector::First).await
+  }
+
+  /**
+   * Scan with chosen adapter, useful when host has more radios (for example onboard and usb dongle)
+   */
+  pub async fn with_adapter(selector: &AdapterSelector) -> Result<Self, ScannerError> {
     let manager  = Manager::new().await?;
-    let central  = find_central(&manager).await?;
-    let devices  = Arc::new(RwLock::new(HashSet::new()));
+    let (central, adapter) = find_adapter(&manager, selector).await?;
+    let devices  = Arc::new(RwLock::new(DeviceTracker::new(DEFAULT_LOST_AFTER)));
+    tracing::debug!("Using bluetooth adapter: {}", adapter);
+
+    Ok(Self { central, adapter, devices, scan: None })
+  }
+
+  /**
+   * Adapter used for scanning, as it was when scanner was created
+   */
+  pub fn adapter(&self) -> &AdapterInfo {
+    &self.adapter
+  }
 
-    Ok(Self { central, devices })
+  /**
+   * Current power state of the adapter
+   */
+  pub async fn is_powered(&self) -> Result<bool, ScannerError> {
+    Ok(self.central.adapter_state().await? == CentralState::PoweredOn)
   }
 
   /**
-   * Wait for scooter with specific mac address to appear and return it.
+   * Report scooters as lost after they don't advertise for given time. Set it before start.
    */
-  pub async fn wait_for(&mut self, scooter_with_address: &BDAddr) -> Result<TrackedDevice, ScannerError> {
+  pub fn with_lost_after(mut self, lost_after: Duration) -> Self {
+    self.devices = Arc::new(RwLock::new(DeviceTracker::new(lost_after)));
+    self
+  }
+
+  /**
+   * Wait for scooter with specific mac address to appear and return it. Gives up at deadline or when
+   * cancel token is cancelled, scanning is stopped in every case.
+   */
+  pub async fn wait_for(&mut self, scooter_with_address: &BDAddr, deadline: tokio::time::Instant, cancel: &CancellationToken) -> Result<TrackedDevice, ScannerError> {
+    let started = Instant::now();
     let mut rx = self.start().await?;
-    while let Some(event) = rx.recv().await {
+
+    let found = loop {
+      let event = tokio::select! {
+        event = rx.recv() => event,
+        _ = sleep_until(deadline) => None,
+        _ = cancel.cancelled() => break Err(ScannerError::Cancelled)
+      };
+
       match event {
-        ScannerEvent::DiscoveredScooter(scooter) => {
-          if scooter.addr == *scooter_with_address {
-            tracing::info!("Found your scooter");
-            return Ok(scooter)
-          } else {
-            tracing::info!("Found scooter nearby: {} with mac: {}", scooter.name.unwrap(), scooter.addr);
-          }
-        }
+        Some(ScannerEvent::DiscoveredScooter(scooter)) | Some(ScannerEvent::Updated(scooter)) if scooter.addr == *scooter_with_address => {
+          tracing::info!("Found your scooter");
+          break Ok(scooter)
+        },
+        Some(ScannerEvent::DiscoveredScooter(scooter)) => {
+          tracing::info!("Found scooter nearby: {:?} ({:?}) with mac: {}", scooter.name, scooter.model(), scooter.addr);
+        },
+        Some(_) => {},
+        None => break Err(ScannerError::WaitForScooterFailed {
+          addr: *scooter_with_address,
+          scanned_for: started.elapsed(),
+          seen: self.scooters().await
+        })
       }
-    }
+    };
 
-    Err(ScannerError::WaitForScooterFailed(*scooter_with_address))
+    if let Err(error) = self.stop().await {
+      tracing::warn!("Could not stop scan: {}", error);
+    }
+    found
   }
 
   /**
    * Get bluetooth Peripheral/Device using TrackedDevice struct
    */
-  pub async fn peripheral(&self, tracked_device : &TrackedDevice) -> Result<Peripheral> {
+  pub async fn peripheral(&self, tracked_device : &TrackedDevice) -> Result<Peripheral, ScannerError> {
     Ok(self.central.peripheral(&tracked_device.id).await?)
   }
 
@@ -122,7 +326,7 @@ impl ScooterScanner {
    * Start scanning for scooters. This method returns receiver which emits
    * events every time a scooter is visible by bluetooth adapter
    */
-  pub async fn start(&mut self) -> Result<mpsc::Receiver<ScannerEvent>> {
+  pub async fn start(&mut self) -> Result<mpsc::Receiver<ScannerEvent>, ScannerError> {
     let (tx, rx) = mpsc::channel::<ScannerEvent>(32);
     tracing::debug!("Starting scanning for new devices");
     self.central.start_scan(ScanFilter::default()).await?;
@@ -130,9 +334,13 @@ impl ScooterScanner {
     tracing::debug!("Watching for events in background");
     let central = self.central.clone();
     let devices = self.devices.clone();
+    let stopped = CancellationToken::new();
+    if let Some(previous) = self.scan.replace(stopped.clone()) {
+      previous.cancel();
+    }
 
     tokio::spawn(async move {
-      if let Err(e) = CentralEventsProcessor::new(tx, central, devices).run().await {
+      if let Err(e) = CentralEventsProcessor::new(tx, central, devices).run(stopped).await {
         tracing::error!("Stopped processed events {}", e);
       }
     });
@@ -140,6 +348,19 @@ impl ScooterScanner {
     Ok(rx)
   }
 
+  /**
+   * Stop scanning and background events processor. Receiver returned by start is closed.
+   */
+  pub async fn stop(&mut self) -> Result<(), ScannerError> {
+    if let Some(scan) = self.scan.take() {
+      tracing::debug!("Stopping scan");
+      scan.cancel();
+      self.central.stop_scan().await?;
+    }
+
+    Ok(())
+  }
+
   /**
    * Get list of scooters nearby you. Filter scooter inside devices list
    */
@@ -147,7 +368,7 @@ impl ScooterScanner {
     self.devices
       .read()
       .await
-      .iter()
+      .devices()
       .filter(|tracked_device| tracked_device.is_scooter())
       .map(|tracked_device| tracked_device.clone())
       .collect::<Vec<TrackedDevice>>()
@@ -160,10 +381,17 @@ impl ScooterScanner {
     self.devices
       .read()
       .await
-      .iter()
+      .devices()
       .map(|tracked_device| tracked_device.clone())
       .collect::<Vec<TrackedDevice>>()
   }
+
+  /**
+   * Latest state of device with address, None when it is not in range (never seen or lost)
+   */
+  pub async fn device(&self, addr: &BDAddr) -> Option<TrackedDevice> {
+    self.devices.read().await.get(addr).cloned()
+  }
 }
 
 struct CentralEventsProcessor {
@@ -181,59 +409,81 @@ impl CentralEventsProcessor {
     }
   }
   //Listen for Bluetooth events and processes then
-  pub async fn run(&mut self) -> Result<()> {
+  pub async fn run(&mut self, stopped: CancellationToken) -> anyhow::Result<()> {
     let mut events = self.central.events().await?;
-
-    while let Some(event) = events.next().await {
-      match event {
-        CentralEvent::DeviceDiscovered(peer_id) => {
-          if let Some(tracked_device) = self.track_device(&peer_id).await? { //If event == discover scooter => Send event
-            if tracked_device.is_scooter() {
-              self.tx.send(ScannerEvent::DiscoveredScooter(tracked_device)).await?;
+    let period = (self.devices.read().await.lost_after() / 4).max(Duration::from_millis(100));
+    let mut expiry = tokio::time::interval(period);
+
+    loop {
+      tokio::select! {
+        event = events.next() => match event {
+          Some(CentralEvent::DeviceDiscovered(peer_id)) | Some(CentralEvent::DeviceUpdated(peer_id)) => {
+            if let Some(event) = self.track_device(&peer_id).await? {
+              self.tx.send(event).await?;
             }
-          }
+          },
+          Some(CentralEvent::DeviceDisconnected(peer_id)) => {
+            // Connection closed, device may still be in range. It is lost only when it stops advertising
+            tracing::debug!("Disconnected peer: {:?}", peer_id);
+          },
+          Some(_) => {},
+          None => break
         },
-        _ => {}
+        _ = stopped.cancelled() => break,
+        _ = expiry.tick() => {
+          let lost = self.devices.write().await.expire(Instant::now());
+          for event in lost {
+            self.tx.send(event).await?;
+          }
+        }
       }
     }
     Ok(())
   }
 
-  async fn track_device(&mut self, peer_id: &PeripheralId) -> Result<Option<TrackedDevice>> {
+  async fn track_device(&mut self, peer_id: &PeripheralId) -> anyhow::Result<Option<ScannerEvent>> {
     tracing::debug!("Discovered peer: {:?}", peer_id);
     let device = self.central.peripheral(peer_id).await?;
 
-    let mut tracked_device = TrackedDevice {
+    let props = match device.properties().await? {
+      Some(props) => props,
+      None => return Ok(None)
+    };
+    tracing::debug!("Props: {:?}", props);
+
+    let tracked_device = TrackedDevice {
       id: peer_id.clone(),
       addr: device.address(),
-      name: None,
+      name: props.local_name,
+      rssi: props.rssi,
+      tx_power: props.tx_power_level,
+      last_seen: Instant::now(),
+      manufacturer_data: props.manufacturer_data,
+      services: props.services,
     };
 
-    let mut devices = self.devices.write().await;
-
-    if devices.contains(&tracked_device) {
-      tracing::debug!("Already discovered: {}", tracked_device.addr);
-      Ok(None)
-    } else {
-      let props = device.properties().await?.unwrap();
-      tracing::debug!("Props: {:?}", props);
-
-      let name = props.local_name.unwrap_or("(peripheral name unknown)".to_owned());
-      tracing::debug!("Device name: {}", name);
-      tracked_device.name = Some(name);
-
-      devices.insert(tracked_device.clone());
-      Ok(Some(tracked_device))
-    }
+    Ok(self.devices.write().await.seen(tracked_device))
   }
 }
 
-async fn find_central(manager: &Manager) -> Result<Adapter, ScannerError> { //Find first available host Bluetooth adapter
-  let adapters = manager.adapters().await?;
+fn addresses(devices: &[TrackedDevice]) -> String {
+  if devices.is_empty() {
+    return "none".to_owned()
+  }
 
-  if let Some(adapter) = adapters.into_iter().nth(0) {
-    Ok(adapter)
-  } else {
-    Err(ScannerError::MissingCentral)
+  devices.iter()
+    .map(|device| device.addr.to_string())
+    .collect::<Vec<String>>()
+    .join(", ")
+}
+
+fn adapter_list(adapters: &[AdapterInfo]) -> String {
+  if adapters.is_empty() {
+    return "none".to_owned()
   }
+
+  adapters.iter()
+    .map(|adapter| adapter.to_string())
+    .collect::<Vec<String>>()
+    .join(", ")
 }
@@ -1,7 +1,9 @@
 use super::{MiSession, Payload};
 use super::commands::{ScooterCommand, Direction, Attribute, ReadWrite};
+use crate::transport::Transport;
 
-use anyhow::Result;
+use crate::error::{M365Error, Result};
+use chrono::NaiveDate;
 use serde::{Deserialize, Serialize};
 
 pub type BatteryCellsVoltage = [f32; 10];
@@ -27,7 +29,7 @@ pub struct BatteryInfo {
 }
 
 impl TryFrom<Payload> for BatteryInfo {
-  type Error = anyhow::Error;
+  type Error = M365Error;
 
   fn try_from(payload: Payload) -> Result<Self, Self::Error> {
     let mut payload = payload;
@@ -46,21 +48,49 @@ impl TryFrom<Payload> for BatteryInfo {
   }
 }
 
-impl MiSession {
+/**
+ * Identity and lifetime counters of the battery pack, they change slowly and are worth reading
+ * only once in a while to follow pack ageing
+ */
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BmsInfo {
+  pub serial: String,
+  /**
+   * Capacity of the new pack, in mAh
+   */
+  pub design_capacity: u16,
+  /**
+   * Full charge cycles
+   */
+  pub cycles: u16,
+  /**
+   * Times the charger was plugged in
+   */
+  pub charges: u16,
+  /**
+   * Charge put into the pack over its life, in mAh
+   */
+  pub recharged: u32,
+  /**
+   * Date the pack was made (YYYY-MM-DD), missing when BMS has no valid date
+   */
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub manufacture_date: Option<String>,
+}
+
+impl<T: Transport> MiSession<T> {
   /**
    * Battery voltage in volts
    */
   pub async fn battery_voltage(&mut self) -> Result<f32> {
     tracing::debug!("Reading battery voltage");
 
-    self.send(&ScooterCommand {
+    let mut payload = self.request(&ScooterCommand {
       direction: Direction::MasterToBattery,
       read_write: ReadWrite::Read,
       attribute: Attribute::BatteryVoltage,
       payload: vec![0x02]
     }).await?;
-
-    let mut payload = self.read(2).await?;
     payload.pop_head()?;
 
     let voltage = payload.pop_u16()? as f32 / 100.0;
@@ -74,14 +104,12 @@ impl MiSession {
   pub async fn battery_amperage(&mut self) -> Result<f32> {
     tracing::debug!("Reading battery amperage");
 
-    self.send(&ScooterCommand {
+    let mut payload = self.request(&ScooterCommand {
       direction: Direction::MasterToBattery,
       read_write: ReadWrite::Read,
       attribute: Attribute::BatteryCurrent,
       payload: vec![0x02]
     }).await?;
-
-    let mut payload = self.read(2).await?;
     payload.pop_head()?;
 
     let amperage = payload.pop_i16()? as f32 / 100.0; //As per original documentation divide by 100
@@ -95,14 +123,12 @@ impl MiSession {
   pub async fn battery_percentage(&mut self) -> Result<f32> {
     tracing::debug!("Reading battery amperage");
 
-    self.send(&ScooterCommand {
+    let mut payload = self.request(&ScooterCommand {
       direction: Direction::MasterToBattery,
       read_write: ReadWrite::Read,
       attribute: Attribute::BatteryPercent,
       payload: vec![0x02]
     }).await?;
-
-    let mut payload = self.read(2).await?;
     payload.pop_head()?;
 
     let percent = payload.pop_u16()? as f32;
@@ -113,14 +139,12 @@ impl MiSession {
   pub async fn battery_cell_voltages(&mut self) -> Result<BatteryCellsVoltage> {
     tracing::debug!("Reading battery cell voltages");
 
-    self.send(&ScooterCommand {
+    let mut payload = self.request(&ScooterCommand {
       direction: Direction::MasterToBattery,
       read_write: ReadWrite::Read,
       attribute: Attribute::BatteryCellVoltages,
       payload: vec![0x1B]
     }).await?;
-
-    let mut payload = self.read(3).await?;
     payload.pop_head()?;
 
     let voltages : BatteryCellsVoltage = [
@@ -140,17 +164,71 @@ impl MiSession {
   }
 
   pub async fn battery_info(&mut self) -> Result<BatteryInfo> {
-    self.send(&ScooterCommand {
+    let payload = self.request(&ScooterCommand {
       direction: Direction::MasterToBattery,
       read_write: ReadWrite::Read,
       attribute: Attribute::BatteryInfo,
       payload: vec![0x0A]
     }).await?;
 
-    let payload = self.read(2).await?;
-
     Ok(
       BatteryInfo::try_from(payload)?
     )
   }
+
+  /**
+   * Serial, design capacity and lifetime counters of the battery pack. Word 0x17, between serial
+   * and design capacity, is BMS firmware (same value as ESC register 0x67) and is skipped.
+   */
+  pub async fn bms_info(&mut self) -> Result<BmsInfo> {
+    tracing::debug!("Reading BMS info");
+
+    let mut payload = self.request(&ScooterCommand {
+      direction: Direction::MasterToBattery,
+      read_write: ReadWrite::Read,
+      attribute: Attribute::GeneralInfo,
+      payload: vec![0x12]
+    }).await?;
+    payload.pop_head()?;
+
+    let serial = payload.pop_string_utf8(14)?.trim_end_matches('\0').to_owned(); // ---0x10-0x16 ascii
+    payload.pad_bytes(2)?; // ---0x17 BMS firmware
+    let design_capacity = payload.pop_u16()?; // ---0x18 design capacity 0x1e78=7800
+
+    let mut payload = self.request(&ScooterCommand {
+      direction: Direction::MasterToBattery,
+      read_write: ReadWrite::Read,
+      attribute: Attribute::BatteryCycles,
+      payload: vec![0x08]
+    }).await?;
+    payload.pop_head()?;
+
+    let cycles = payload.pop_u16()?; // ---0x1b
+    let charges = payload.pop_u16()?; // ---0x1c
+    payload.pad_bytes(2)?; // ---0x1d
+    let manufacture_date = manufacture_date(payload.pop_u16()?); // ---0x1e
+
+    let mut payload = self.request(&ScooterCommand {
+      direction: Direction::MasterToBattery,
+      read_write: ReadWrite::Read,
+      attribute: Attribute::BatteryRecharged,
+      payload: vec![0x04]
+    }).await?;
+    payload.pop_head()?;
+
+    let recharged = payload.pop_u32()?;
+
+    Ok(BmsInfo { serial, design_capacity, cycles, charges, recharged, manufacture_date })
+  }
+}
+
+/**
+ * BMS register 0x1e packs the date: bits 15-9 are year - 2000, bits 8-5 month and bits 4-0 day
+ */
+fn manufacture_date(word: u16) -> Option<String> {
+  let year = 2000 + (word >> 9) as i32;
+  let month = ((word >> 5) & 0x0f) as u32;
+  let day = (word & 0x1f) as u32;
+
+  NaiveDate::from_ymd_opt(year, month, day).map(|date| date.format("%Y-%m-%d").to_string())
 }
@@ -1,12 +1,13 @@
 use core::fmt::Debug;
 use pretty_hex::*;
+use crate::ninebot::{self, NinebotFrame};
 
 /**
 * Check protocol documentation on docs folder to get more info on the commands
 */
 
 // Communications direction
-#[derive(Clone)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Direction {
   MasterToMotor,
   MasterToBattery,
@@ -15,6 +16,16 @@ pub enum Direction {
 }
 
 impl Direction {
+  pub(crate) fn from_value(value: u8) -> Option<Self> {
+    match value {
+      0x20 => Some(Direction::MasterToMotor),
+      0x22 => Some(Direction::MasterToBattery),
+      0x23 => Some(Direction::MotorToMaster),
+      0x25 => Some(Direction::BatteryToMaster),
+      _ => None
+    }
+  }
+
   fn value(&self) -> u8 {
     match self {
       Direction::MasterToMotor      => 0x20,
@@ -23,15 +34,46 @@ impl Direction {
       Direction::BatteryToMaster    => 0x25,
     }
   }
+
+  /**
+   * Direction scooter uses when it answers command sent this way
+   */
+  fn reply(&self) -> Direction {
+    match self {
+      Direction::MasterToMotor      => Direction::MotorToMaster,
+      Direction::MasterToBattery    => Direction::BatteryToMaster,
+      other                         => *other,
+    }
+  }
+
+  /**
+   * Source and destination addresses used by 5AA5 frames
+   */
+  fn ninebot_addresses(&self) -> (u8, u8) {
+    match self {
+      Direction::MasterToMotor      => (ninebot::APP, ninebot::ESC),
+      Direction::MasterToBattery    => (ninebot::APP, ninebot::BMS),
+      Direction::MotorToMaster      => (ninebot::ESC, ninebot::APP),
+      Direction::BatteryToMaster    => (ninebot::BMS, ninebot::APP),
+    }
+  }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub enum ReadWrite {
   Read,
   Write
 }
 
 impl ReadWrite {
+  pub(crate) fn from_value(value: u8) -> Option<Self> {
+    match value {
+      0x01 => Some(ReadWrite::Read),
+      0x03 => Some(ReadWrite::Write),
+      _ => None
+    }
+  }
+
   fn value(&self) -> u8 {
     match self {
       ReadWrite::Read     => 0x01,
@@ -40,9 +82,10 @@ impl ReadWrite {
   }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub enum Attribute {
   GeneralInfo,
+  FirmwareVersion,
   MotorInfo,
   DistanceLeft,
   Speed,
@@ -54,13 +97,34 @@ pub enum Attribute {
   Supplementary,
   Cruise,
   TailLight,
-  BatteryInfo
+  BatteryInfo,
+  BatteryCycles,
+  BatteryRecharged,
+  ModuleVersions,
+  TripInfo,
+  /**
+   * Any other register, by its address
+   */
+  Register(u8)
 }
 
 impl Attribute {
+  const ALL : [Attribute; 18] = [
+    Attribute::GeneralInfo, Attribute::FirmwareVersion, Attribute::MotorInfo, Attribute::DistanceLeft,
+    Attribute::Speed, Attribute::TripDistance, Attribute::BatteryVoltage, Attribute::BatteryCurrent,
+    Attribute::BatteryPercent, Attribute::BatteryCellVoltages, Attribute::Supplementary, Attribute::Cruise,
+    Attribute::TailLight, Attribute::BatteryInfo, Attribute::BatteryCycles, Attribute::BatteryRecharged,
+    Attribute::ModuleVersions, Attribute::TripInfo
+  ];
+
+  pub(crate) fn from_value(value: u8) -> Option<Self> {
+    Self::ALL.into_iter().find(|attribute| attribute.value() == value)
+  }
+
   fn value(&self) -> u8 {
     match self {
       Attribute::GeneralInfo          => 0x10,
+      Attribute::FirmwareVersion      => 0x1A,
       Attribute::DistanceLeft         => 0x25,
       Attribute::Speed                => 0xB5,
       Attribute::TripDistance         => 0xB9,
@@ -72,7 +136,12 @@ impl Attribute {
       Attribute::Supplementary        => 0x7B,
       Attribute::Cruise               => 0x7C,
       Attribute::TailLight            => 0x7D,
-      Attribute::BatteryInfo          => 0x31
+      Attribute::BatteryInfo          => 0x31,
+      Attribute::BatteryCycles        => 0x1B,
+      Attribute::BatteryRecharged     => 0x20,
+      Attribute::ModuleVersions       => 0x67,
+      Attribute::TripInfo             => 0x3A,
+      Attribute::Register(addr)       => *addr
     }
   }
 }
@@ -105,4 +174,39 @@ impl ScooterCommand {
     }
     bytes
   }
+
+  /**
+   * Check if decoded message (direction, read/write, attribute, data) answers this command.
+   * Late reply to some earlier command has different direction or attribute.
+   */
+  pub fn is_answered_by(&self, msg: &[u8]) -> bool {
+    msg.len() >= 3 && msg[0] == self.direction.reply().value() && msg[2] == self.attribute.value()
+  }
+
+  /**
+   * Direction and attribute bytes expected in reply, for error messages
+   */
+  pub fn reply_header(&self) -> (u8, u8) {
+    (self.direction.reply().value(), self.attribute.value())
+  }
+
+  /**
+   * Same command in plain 5AA5 frame for ninebot scooters. Attribute address is sent as is,
+   * only registers at the same address as on xiaomi scooters are supported (see ninebot module)
+   */
+  pub fn as_ninebot_frame(&self) -> NinebotFrame {
+    let (src, dst) = self.direction.ninebot_addresses();
+    let cmd = match self.read_write {
+      ReadWrite::Read   => ninebot::CMD_READ,
+      ReadWrite::Write  => ninebot::CMD_WRITE_NR
+    };
+
+    NinebotFrame {
+      src,
+      dst,
+      cmd,
+      arg: self.attribute.value(),
+      payload: self.payload.clone()
+    }
+  }
 }
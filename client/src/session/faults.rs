@@ -0,0 +1,203 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/**
+ * Error shown as code on the dashboard, ESC register 0xB0. Published as its number.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "u16", into = "u16")]
+pub enum EscError {
+  None,
+  BleCommunication,
+  MotorPhaseA,
+  MotorPhaseB,
+  MotorPhaseC,
+  Throttle,
+  Brake,
+  MotorHall,
+  BmsCommunication,
+  BmsPassword,
+  BmsSerial,
+  SupplyVoltage,
+  FlashSave,
+  HighSideMosfet,
+  LowSideMosfet,
+  EscFirmware,
+  VehicleSerial,
+  BatteryTemperatureSensor,
+  EscOverheat,
+  /**
+   * Code missing from the list above
+   */
+  Other(u16),
+}
+
+impl EscError {
+  pub fn code(&self) -> u16 {
+    match self {
+      EscError::None                      => 0,
+      EscError::BleCommunication          => 10,
+      EscError::MotorPhaseA               => 11,
+      EscError::MotorPhaseB               => 12,
+      EscError::MotorPhaseC               => 13,
+      EscError::Throttle                  => 14,
+      EscError::Brake                     => 15,
+      EscError::MotorHall                 => 18,
+      EscError::BmsCommunication          => 21,
+      EscError::BmsPassword               => 22,
+      EscError::BmsSerial                 => 23,
+      EscError::SupplyVoltage             => 24,
+      EscError::FlashSave                 => 26,
+      EscError::HighSideMosfet            => 28,
+      EscError::LowSideMosfet             => 29,
+      EscError::EscFirmware               => 31,
+      EscError::VehicleSerial             => 35,
+      EscError::BatteryTemperatureSensor  => 39,
+      EscError::EscOverheat               => 40,
+      EscError::Other(code)               => *code,
+    }
+  }
+
+  pub fn description(&self) -> &'static str {
+    match self {
+      EscError::None                      => "No error",
+      EscError::BleCommunication          => "Communication between bluetooth board and ESC failed",
+      EscError::MotorPhaseA               => "Motor phase A current abnormal",
+      EscError::MotorPhaseB               => "Motor phase B current abnormal",
+      EscError::MotorPhaseC               => "Motor phase C current abnormal",
+      EscError::Throttle                  => "Throttle fault, not in zero position",
+      EscError::Brake                     => "Brake fault, lever not released",
+      EscError::MotorHall                 => "Motor hall sensor fault",
+      EscError::BmsCommunication          => "BMS communication fault",
+      EscError::BmsPassword               => "BMS password check failed",
+      EscError::BmsSerial                 => "BMS serial number is the factory default",
+      EscError::SupplyVoltage             => "Supply voltage out of range",
+      EscError::FlashSave                 => "ESC could not save settings to flash",
+      EscError::HighSideMosfet            => "High side MOSFET of the ESC damaged",
+      EscError::LowSideMosfet             => "Low side MOSFET of the ESC damaged",
+      EscError::EscFirmware               => "ESC firmware fault",
+      EscError::VehicleSerial             => "Scooter serial number invalid",
+      EscError::BatteryTemperatureSensor  => "Battery temperature sensor fault",
+      EscError::EscOverheat               => "ESC temperature too high",
+      EscError::Other(_)                  => "Unknown error",
+    }
+  }
+
+  pub fn is_fault(&self) -> bool {
+    *self != EscError::None
+  }
+}
+
+impl From<u16> for EscError {
+  fn from(code: u16) -> Self {
+    match code {
+      0   => EscError::None,
+      10  => EscError::BleCommunication,
+      11  => EscError::MotorPhaseA,
+      12  => EscError::MotorPhaseB,
+      13  => EscError::MotorPhaseC,
+      14  => EscError::Throttle,
+      15  => EscError::Brake,
+      18  => EscError::MotorHall,
+      21  => EscError::BmsCommunication,
+      22  => EscError::BmsPassword,
+      23  => EscError::BmsSerial,
+      24  => EscError::SupplyVoltage,
+      26  => EscError::FlashSave,
+      28  => EscError::HighSideMosfet,
+      29  => EscError::LowSideMosfet,
+      31  => EscError::EscFirmware,
+      35  => EscError::VehicleSerial,
+      39  => EscError::BatteryTemperatureSensor,
+      40  => EscError::EscOverheat,
+      code => EscError::Other(code),
+    }
+  }
+}
+
+impl From<EscError> for u16 {
+  fn from(error: EscError) -> Self {
+    error.code()
+  }
+}
+
+impl fmt::Display for EscError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{} ({})", self.description(), self.code())
+  }
+}
+
+/**
+ * Warning, ESC register 0xB1. Scooter keeps riding with it, codes are not documented yet so only
+ * presence is decoded. Published as its number.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "u16", into = "u16")]
+pub enum EscWarning {
+  None,
+  Warning(u16),
+}
+
+impl EscWarning {
+  pub fn code(&self) -> u16 {
+    match self {
+      EscWarning::None => 0,
+      EscWarning::Warning(code) => *code,
+    }
+  }
+
+  pub fn description(&self) -> &'static str {
+    match self {
+      EscWarning::None => "No warning",
+      EscWarning::Warning(_) => "Warning",
+    }
+  }
+
+  pub fn is_fault(&self) -> bool {
+    *self != EscWarning::None
+  }
+}
+
+impl From<u16> for EscWarning {
+  fn from(code: u16) -> Self {
+    match code {
+      0 => EscWarning::None,
+      code => EscWarning::Warning(code),
+    }
+  }
+}
+
+impl From<EscWarning> for u16 {
+  fn from(warning: EscWarning) -> Self {
+    warning.code()
+  }
+}
+
+impl fmt::Display for EscWarning {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{} ({})", self.description(), self.code())
+  }
+}
+
+/**
+ * First 8 bytes of the 0xB0 motor block (Var176-179 in protocol documentation)
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EscStatus {
+  pub error: EscError,
+  pub warning: EscWarning,
+  /**
+   * Raw status bits, meaning of single bits is not known yet
+   */
+  pub flags: u16,
+  /**
+   * Raw work mode, only 0 was seen in captures so values are not named yet
+   */
+  pub work_mode: u16,
+}
+
+impl Default for EscStatus {
+  fn default() -> Self {
+    Self { error: EscError::None, warning: EscWarning::None, flags: 0, work_mode: 0 }
+  }
+}
@@ -0,0 +1,144 @@
+use super::{MiSession, MotorInfo, BatteryInfo, TailLight, SupplementaryInfo, Direction, Payload};
+use crate::transport::{Transport, BleTransport};
+
+use crate::error::{M365Error, Result};
+use futures::future::BoxFuture;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+
+const QUEUE_SIZE : usize = 32;
+
+/**
+ * Order in which queued commands are sent to scooter. High priority commands (for example tail light
+ * switched remotely) go before any normal command waiting in queue, like routine telemetry polls.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Priority {
+  High,
+  Normal,
+}
+
+type Job<T> = Box<dyn for<'a> FnOnce(&'a mut MiSession<T>) -> BoxFuture<'a, ()> + Send>;
+
+/**
+ * Cloneable handle to MiSession owned by background actor task. Actor runs one command at a time,
+ * so every clone can be used from different task (mqtt commands, telemetry poller...) without
+ * mixing frames of two commands on the same uart channel.
+ *
+ * let (handle, actor) = SessionHandle::spawn(session);
+ * let motor_info = handle.motor_info().await?;
+ * handle.set_tail_light(TailLight::Always).await?;
+ * drop(handle); // actor stops when last handle is gone and gives session back
+ * let session = actor.await?;
+ */
+pub struct SessionHandle<T: Transport = BleTransport> {
+  high: mpsc::Sender<Job<T>>,
+  normal: mpsc::Sender<Job<T>>,
+}
+
+impl<T: Transport> Clone for SessionHandle<T> {
+  fn clone(&self) -> Self {
+    Self { high: self.high.clone(), normal: self.normal.clone() }
+  }
+}
+
+impl<T: Transport + 'static> SessionHandle<T> {
+  /**
+   * Move session into actor task. Task finishes when all handles are dropped and returns the session.
+   */
+  pub fn spawn(session: MiSession<T>) -> (Self, JoinHandle<MiSession<T>>) {
+    let (high, high_rx) = mpsc::channel(QUEUE_SIZE);
+    let (normal, normal_rx) = mpsc::channel(QUEUE_SIZE);
+
+    let actor = tokio::spawn(run_actor(session, high_rx, normal_rx));
+
+    (Self { high, normal }, actor)
+  }
+
+  /**
+   * Run any session operation inside actor and wait for its result
+   *
+   * let speed = handle.run(Priority::Normal, |session| Box::pin(session.speed())).await?;
+   */
+  pub async fn run<R, F>(&self, priority: Priority, operation: F) -> Result<R>
+    where R: Send + 'static,
+          F: for<'a> FnOnce(&'a mut MiSession<T>) -> BoxFuture<'a, Result<R>> + Send + 'static
+  {
+    let (tx, rx) = oneshot::channel();
+    let job : Job<T> = Box::new(move |session| Box::pin(async move {
+      let _ = tx.send(operation(session).await); // Caller might have given up waiting
+    }));
+
+    let queue = match priority {
+      Priority::High => &self.high,
+      Priority::Normal => &self.normal,
+    };
+
+    queue.send(job).await
+      .map_err(|_| M365Error::Disconnected("session actor stopped".to_owned()))?;
+
+    rx.await
+      .map_err(|_| M365Error::Disconnected("session actor dropped command".to_owned()))?
+  }
+
+  pub async fn motor_info(&self) -> Result<MotorInfo> {
+    self.run(Priority::Normal, |session| Box::pin(session.motor_info())).await
+  }
+
+  pub async fn battery_info(&self) -> Result<BatteryInfo> {
+    self.run(Priority::Normal, |session| Box::pin(session.battery_info())).await
+  }
+
+  pub async fn distance_left(&self) -> Result<f32> {
+    self.run(Priority::Normal, |session| Box::pin(session.distance_left())).await
+  }
+
+  pub async fn speed(&self) -> Result<f32> {
+    self.run(Priority::Normal, |session| Box::pin(session.speed())).await
+  }
+
+  pub async fn supplementary_info(&self) -> Result<SupplementaryInfo> {
+    self.run(Priority::Normal, |session| Box::pin(session.supplementary_info())).await
+  }
+
+  pub async fn tail_light(&self) -> Result<TailLight> {
+    self.run(Priority::Normal, |session| Box::pin(session.tail_light())).await
+  }
+
+  /**
+   * Settings are changed by rider, they skip the queue of polls
+   */
+  pub async fn set_tail_light(&self, mode: TailLight) -> Result<()> {
+    self.run(Priority::High, move |session| Box::pin(session.set_tail_light(mode))).await
+  }
+
+  pub async fn set_cruise(&self, on: bool) -> Result<()> {
+    self.run(Priority::High, move |session| Box::pin(session.set_cruise(on))).await
+  }
+
+  pub async fn read_register(&self, direction: Direction, addr: u8, len: u8) -> Result<Payload> {
+    self.run(Priority::Normal, move |session| Box::pin(session.read_register(direction, addr, len))).await
+  }
+
+  pub async fn write_register(&self, direction: Direction, addr: u8, bytes: Vec<u8>) -> Result<Payload> {
+    self.run(Priority::High, move |session| Box::pin(async move { session.write_register(direction, addr, &bytes).await })).await
+  }
+}
+
+async fn run_actor<T: Transport>(mut session: MiSession<T>, mut high: mpsc::Receiver<Job<T>>, mut normal: mpsc::Receiver<Job<T>>) -> MiSession<T> {
+  tracing::debug!("Session actor started");
+
+  loop {
+    let job = tokio::select! {
+      biased;
+      Some(job) = high.recv() => job,
+      Some(job) = normal.recv() => job,
+      else => break
+    };
+
+    job(&mut session).await;
+  }
+
+  tracing::debug!("Session actor stopped, all handles dropped");
+  session
+}
@@ -1,9 +1,13 @@
 use super::{MiSession, Payload};
+use super::faults::{EscError, EscStatus, EscWarning};
 use super::commands::{ScooterCommand, Direction, Attribute, ReadWrite};
+use crate::transport::Transport;
 
+use std::fmt;
+use std::str::FromStr;
 use std::time::Duration;
-use anyhow::Result;
-use serde::Serialize;
+use crate::error::{M365Error, Result};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 #[derive(Debug, Serialize)]
 pub struct GeneralInfo {
@@ -14,6 +18,10 @@ pub struct GeneralInfo {
 
 #[derive(Debug, Serialize)]
 pub struct MotorInfo {
+  /**
+   * Error, warning, flags and work mode
+   */
+  pub status: EscStatus,
   /**
    * Percent value between 0 and 100
    */
@@ -43,12 +51,17 @@ pub struct MotorInfo {
 }
 
 impl TryFrom<Payload> for MotorInfo {
-  type Error = anyhow::Error;
+  type Error = M365Error;
 
   fn try_from(payload: Payload) -> Result<Self, Self::Error> {
     let mut payload = payload;
     payload.pop_head()?;
-    payload.pad_bytes(8)?; // ---Var179=¿workmode?=0x0000
+    let status = EscStatus {
+      error: EscError::from(payload.pop_u16()?), // ---Var176=error=0x0000
+      warning: EscWarning::from(payload.pop_u16()?), // ---Var177=warning=0x0000
+      flags: payload.pop_u16()?, // ---Var178=¿flags?=0x0000
+      work_mode: payload.pop_u16()?, // ---Var179=¿workmode?=0x0000
+    };
 
     let battery_percent = payload.pop_u16()?; // ---Var180=%batt=0x003d=61%
     let speed_kmh = payload.pop_i16()? as f32 / 1000.0; // ---Var181=¿speed meters/h?=0x0000=0km/h
@@ -60,6 +73,7 @@ impl TryFrom<Payload> for MotorInfo {
 
     Ok(
       MotorInfo {
+        status,
         battery_percent,
         speed_kmh,
         speed_average_kmh,
@@ -72,7 +86,93 @@ impl TryFrom<Payload> for MotorInfo {
   }
 }
 
-impl MiSession {
+/**
+ * Firmware version, scooter stores it as nibbles of one word: 0x0134 is 1.3.4.
+ * Versions are ordered, so they can be compared when feature depends on firmware:
+ *
+ * if versions.esc >= FirmwareVersion::new(1, 5, 5) { ... }
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FirmwareVersion {
+  pub major: u8,
+  pub minor: u8,
+  pub patch: u8,
+}
+
+impl FirmwareVersion {
+  pub const fn new(major: u8, minor: u8, patch: u8) -> Self {
+    Self { major, minor, patch }
+  }
+}
+
+impl From<u16> for FirmwareVersion {
+  fn from(word: u16) -> Self {
+    Self {
+      major: (word >> 8) as u8,
+      minor: ((word >> 4) & 0x0f) as u8,
+      patch: (word & 0x0f) as u8,
+    }
+  }
+}
+
+impl fmt::Display for FirmwareVersion {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+  }
+}
+
+impl FromStr for FirmwareVersion {
+  type Err = M365Error;
+
+  fn from_str(version: &str) -> Result<Self> {
+    let parts : Vec<u8> = version.split('.')
+      .map(|part| part.parse::<u8>())
+      .collect::<std::result::Result<_, _>>()
+      .map_err(|e| M365Error::parse("firmware version", format!("{}: {}", version, e)))?;
+
+    match parts[..] {
+      [major, minor, patch] => Ok(Self::new(major, minor, patch)),
+      _ => Err(M365Error::parse("firmware version", format!("{} is not major.minor.patch", version)))
+    }
+  }
+}
+
+/**
+ * Published as "1.3.4" string
+ */
+impl Serialize for FirmwareVersion {
+  fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+    serializer.collect_str(self)
+  }
+}
+
+impl<'de> Deserialize<'de> for FirmwareVersion {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+    let version = String::deserialize(deserializer)?;
+    version.parse().map_err(serde::de::Error::custom)
+  }
+}
+
+/**
+ * Firmware of each module of the scooter, read the same way the app does at startup
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FirmwareVersions {
+  /**
+   * Motor controller, register 0x1a
+   */
+  pub esc: FirmwareVersion,
+  /**
+   * Battery management system, ESC register 0x67
+   */
+  pub bms: FirmwareVersion,
+  /**
+   * Bluetooth module, ESC register 0x68
+   */
+  pub ble: FirmwareVersion,
+}
+
+impl<T: Transport> MiSession<T> {
   pub async fn general_info(&mut self) -> Result<GeneralInfo> {
     tracing::debug!("Reading general information");
 
@@ -83,14 +183,14 @@ impl MiSession {
       payload: vec![0x16]
     };
 
-    self.send(&cmd).await?;
     //          [                      SERIAL                          ][          PIN         ][ VER  ]
     // payload: /x31/x36/x31/x33/x32/x2f/x30/x30/x30/x39/x35/x32/x39/x32/x30/x30/x30/x30/x30/x30/x38/x01
-    let mut payload = self.read(2).await?;
+    let mut payload = self.request(&cmd).await?;
 
     payload.pop_head()?;
 
-    let serial = payload.pop_string_utf8(11)?;
+    // Serial fills 7 words (0x10-0x16, 14 ascii characters), reading only 11 of them shifted pin and version
+    let serial = payload.pop_string_utf8(14)?;
     let pin = payload.pop_string_utf8(6)?;
     let version = payload.pop_string_utf8(2)?;
 
@@ -109,8 +209,7 @@ impl MiSession {
       payload: vec![0x0e]
     };
 
-    self.send(&cmd).await?;
-    let mut payload = self.read(2).await?;
+    let mut payload = self.request(&cmd).await?;
     payload.pop_head()?;
 
     let serial = payload.pop_string_utf8(14)?;
@@ -121,15 +220,40 @@ impl MiSession {
   pub async fn motor_info(&mut self) -> Result<MotorInfo> {
     tracing::debug!("Reading motor info");
 
-    self.send(&ScooterCommand {
+    let payload = self.request(&ScooterCommand {
       direction: Direction::MasterToMotor,
       read_write: ReadWrite::Read,
       attribute: Attribute::MotorInfo,
       payload: vec![0x20]
     }).await?;
 
-    let payload = self.read(3).await?;
-
     MotorInfo::try_from(payload)
   }
+
+  pub async fn firmware_versions(&mut self) -> Result<FirmwareVersions> {
+    tracing::debug!("Reading firmware versions");
+
+    let mut payload = self.request(&ScooterCommand {
+      direction: Direction::MasterToMotor,
+      read_write: ReadWrite::Read,
+      attribute: Attribute::FirmwareVersion,
+      payload: vec![0x02]
+    }).await?;
+    payload.pop_head()?;
+
+    let esc = FirmwareVersion::from(payload.pop_u16()?); // ---Var26=version=01.3.4
+
+    let mut payload = self.request(&ScooterCommand {
+      direction: Direction::MasterToMotor,
+      read_write: ReadWrite::Read,
+      attribute: Attribute::ModuleVersions,
+      payload: vec![0x04]
+    }).await?;
+    payload.pop_head()?;
+
+    let bms = FirmwareVersion::from(payload.pop_u16()?); // ---Var103=bms=01.1.5
+    let ble = FirmwareVersion::from(payload.pop_u16()?); // ---Var104=ble=0x71=0.7.1
+
+    Ok(FirmwareVersions { esc, bms, ble })
+  }
 }
@@ -1,41 +1,239 @@
 pub use super::payload::Payload;
-use super::commands::ScooterCommand;
+use super::commands::{ScooterCommand, Direction, ReadWrite, Attribute};
 use crate::protocol::MiProtocol;
-use crate::mi_crypto::{encrypt_uart, decrypt_uart, LoginKeychain};
+use crate::mi_crypto::{encrypt_uart, decrypt_uart_at, encode_uart, decode_uart, uart_counter, LoginKeychain, UartCounters, MiCryptoError, ENCRYPTED_OVERHEAD};
 use crate::consts::Registers;
+use crate::transport::{Transport, BleTransport};
+use crate::ninebot;
+use crate::login::LoginError;
+use pretty_hex::*;
 
-use anyhow::Result;
+use crate::error::{M365Error, Result};
 use btleplug::platform::Peripheral;
+use std::time::Duration;
+use tokio::time::{Instant, timeout_at};
 
-pub struct MiSession {
-  protocol: MiProtocol,
-  keys: LoginKeychain,
+/**
+ * How long to wait for reply matching sent command
+ */
+pub const REPLY_TIMEOUT : Duration = Duration::from_secs(5);
+
+/**
+ * Scooter that still knows our keys (or speaks plain ninebot frames) answers probe quickly,
+ * no need to wait full REPLY_TIMEOUT
+ */
+const RESUME_PROBE_TIMEOUT : Duration = Duration::from_secs(2);
+
+/**
+ * How commands are framed on the uart channel
+ */
+#[derive(Clone)]
+pub enum UartMode {
+  /**
+   * 55AB frames encrypted with keys obtained by LoginRequest
+   */
+  Encrypted(LoginKeychain),
+  /**
+   * Plain 55AA frames used by legacy firmware (ESC < 1.5), no login required
+   */
+  Legacy,
+  /**
+   * Plain 5AA5 frames with source and destination address, used by Ninebot/Segway scooters
+   */
+  Ninebot,
+}
+
+pub struct MiSession<T: Transport = BleTransport> {
+  protocol: MiProtocol<T>,
+  mode: UartMode,
+  counters: UartCounters,
+  reply_timeout: Duration,
 }
 
 impl MiSession {
   pub async fn new(device: &Peripheral, keys: &LoginKeychain) -> Result<Self> {
     let protocol = MiProtocol::new(device).await?;
-    let keys = keys.clone();
 
-    Ok(Self { protocol, keys })
+    Ok(Self::with_protocol(protocol, keys))
+  }
+}
+
+impl<T: Transport> MiSession<T> {
+  /**
+   * Create session on top of already logged in protocol
+   */
+  pub fn with_protocol(protocol: MiProtocol<T>, keys: &LoginKeychain) -> Self {
+    let mode = UartMode::Encrypted(keys.clone());
+
+    Self::with_mode(protocol, mode)
+  }
+
+  /**
+   * Create session for legacy firmware, that talks plain 55AA frames without login
+   */
+  pub fn legacy(protocol: MiProtocol<T>) -> Self {
+    Self::with_mode(protocol, UartMode::Legacy)
+  }
+
+  /**
+   * Create session for Ninebot/Segway scooter that talks plain 5AA5 frames, no login required
+   */
+  pub fn ninebot(protocol: MiProtocol<T>) -> Self {
+    Self::with_mode(protocol, UartMode::Ninebot)
+  }
+
+  /**
+   * Create Ninebot session and check that scooter answers plain 5AA5 frames. Newer firmware (Max G30
+   * included) encrypts them, which is not supported: instead of every later request timing out, LoginError::NinebotEncrypted
+   * is returned right away.
+   */
+  pub async fn start_ninebot(protocol: MiProtocol<T>) -> Result<Self> {
+    let mut session = Self::ninebot(protocol);
+
+    let probe = ScooterCommand {
+      direction: Direction::MasterToMotor,
+      read_write: ReadWrite::Read,
+      attribute: Attribute::FirmwareVersion,
+      payload: vec![0x02]
+    };
+
+    session.reply_timeout = RESUME_PROBE_TIMEOUT;
+    let reply = session.request(&probe).await;
+    session.reply_timeout = REPLY_TIMEOUT;
+
+    match reply {
+      Ok(_) => Ok(session),
+      Err(M365Error::NoReply { .. }) | Err(M365Error::Crypto(_)) => Err(LoginError::NinebotEncrypted.into()),
+      Err(error) => Err(error)
+    }
+  }
+
+  fn with_mode(protocol: MiProtocol<T>, mode: UartMode) -> Self {
+    Self { protocol, mode, counters: UartCounters::default(), reply_timeout: REPLY_TIMEOUT }
+  }
+
+  /**
+   * Change how long request waits for matching reply, default is REPLY_TIMEOUT
+   */
+  pub fn set_reply_timeout(&mut self, reply_timeout: Duration) {
+    self.reply_timeout = reply_timeout;
+  }
+
+  pub fn mode(&self) -> &UartMode {
+    &self.mode
+  }
+
+  /**
+   * Counters used by encrypted frames so far
+   */
+  pub fn counters(&self) -> UartCounters {
+    self.counters
+  }
+
+  /**
+   * Continue session over new connection after short bluetooth drop, keeping login keys and counters.
+   * Scooter is asked for firmware version to check it still accepts the keys, if it does not
+   * (reply never comes or can't be decrypted) error is returned and full login is needed.
+   */
+  pub async fn resume(&mut self, protocol: MiProtocol<T>) -> Result<()> {
+    tracing::debug!("Resuming session with counters {:?}", self.counters);
+    self.protocol = protocol;
+
+    let probe = ScooterCommand {
+      direction: Direction::MasterToMotor,
+      read_write: ReadWrite::Read,
+      attribute: Attribute::FirmwareVersion,
+      payload: vec![0x02]
+    };
+
+    let reply_timeout = std::mem::replace(&mut self.reply_timeout, RESUME_PROBE_TIMEOUT);
+    let reply = self.request(&probe).await;
+    self.reply_timeout = reply_timeout;
+
+    reply?;
+    Ok(())
   }
 
   /**
    * Serialize, encrypt and send command to scooter
    */
   pub async fn send(&mut self, cmd: &ScooterCommand) -> Result<bool> {
-    let bytes = encrypt_uart(&self.keys.app, &cmd.as_bytes(), 0, None); // encrypt bytes
+    let bytes = match &self.mode {
+      UartMode::Encrypted(keys) => {
+        let it = self.counters.next_tx()?;
+        encrypt_uart(&keys.app, &cmd.as_bytes(), it, None) // encrypt bytes
+      },
+      UartMode::Legacy => encode_uart(&cmd.as_bytes()),
+      UartMode::Ninebot => cmd.as_ninebot_frame().as_bytes()
+    };
+
     self.protocol.write_nb_parcel(&Registers::TX, &bytes).await?;
     Ok(true)
   }
 
   /**
-   * Wait for response from scooter. You can specify number of frames that you expect to receive
+   * Send command and wait for its reply. Replies to other commands (for example late answer
+   * to command that timed out before) are discarded.
+   */
+  pub async fn request(&mut self, cmd: &ScooterCommand) -> Result<Payload> {
+    self.send(cmd).await?;
+    self.read_reply(cmd).await
+  }
+
+  /**
+   * Read frames until one answers given command, or reply timeout passes
+   */
+  pub async fn read_reply(&mut self, cmd: &ScooterCommand) -> Result<Payload> {
+    let deadline = Instant::now() + self.reply_timeout;
+
+    loop {
+      let response = match timeout_at(deadline, self.read_message()).await {
+        Ok(Ok(response)) => response,
+        Ok(Err(M365Error::Crypto(error))) if error.is_ignorable() => {
+          tracing::warn!("Dropping frame while waiting for reply to {:?}: {}", cmd, error);
+          continue
+        },
+        Ok(Err(error)) => return Err(error),
+        Err(_) => {
+          self.protocol.clear_frames();
+          let (direction, attribute) = cmd.reply_header();
+          return Err(M365Error::NoReply { direction, attribute, timeout: self.reply_timeout })
+        }
+      };
+
+      if cmd.is_answered_by(&response) {
+        return Ok(Payload::from(response))
+      }
+
+      tracing::warn!("Discarding reply that does not match {:?}: {:?}", cmd, response.hex_dump());
+    }
+  }
+
+  /**
+   * Wait for response from scooter. Reply is read until its length byte is satisfied,
+   * it does not matter in how many notifications scooter splits it.
+   * This returns any frame that comes next, use request to get reply for specific command.
    */
-  pub async fn read(&mut self, frames: u8) -> Result<Payload> {
-    let data = self.protocol.read_nb_parcel(frames).await?;
-    let response = decrypt_uart(&self.keys.dev, &data)?;
-    let payload = Payload::from(response);
-    Ok(payload)
+  pub async fn read(&mut self) -> Result<Payload> {
+    let response = self.read_message().await?;
+    Ok(Payload::from(response))
+  }
+
+  async fn read_message(&mut self) -> Result<Vec<u8>> {
+    let data = self.protocol.read_frame().await?;
+
+    let response = match &self.mode {
+      UartMode::Encrypted(keys) => {
+        let received = uart_counter(&data).ok_or(MiCryptoError::Truncated { expected: ENCRYPTED_OVERHEAD, received: data.len() })?;
+        let it = self.counters.expected_rx(received)?;
+        let response = decrypt_uart_at(&keys.dev, &data, it)?;
+        self.counters.accept_rx(it); // Only authentic frames move counter forward
+        response
+      },
+      UartMode::Legacy => decode_uart(&data)?,
+      UartMode::Ninebot => ninebot::decode_frame(&data)?.to_uart_message()?
+    };
+
+    Ok(response)
   }
 }
@@ -1,12 +1,21 @@
 mod mi_session;
-mod commands;
+pub(crate) mod commands;
 mod info;
 mod travel;
 mod battery;
 mod payload;
 mod settings;
-pub use mi_session::MiSession;
+mod handle;
+mod raw;
+mod snapshot;
+mod faults;
+pub use mi_session::{MiSession, UartMode};
 pub use payload::Payload;
-pub use info::{GeneralInfo, MotorInfo};
-pub use settings::{TailLight, Kers};
-pub use battery::{BatteryInfo};
+pub use info::{FirmwareVersion, FirmwareVersions, GeneralInfo, MotorInfo};
+pub use settings::{TailLight, Kers, SupplementaryInfo};
+pub use handle::{SessionHandle, Priority};
+pub use battery::{BatteryInfo, BmsInfo};
+pub use commands::Direction;
+pub use travel::TripInfo;
+pub use faults::{EscError, EscStatus, EscWarning};
+pub use snapshot::{Controller, RegisterSnapshot, RegisterValue};
@@ -1,6 +1,6 @@
 use core::fmt::Debug;
 use pretty_hex::*;
-use anyhow::{Result, anyhow, Context};
+use crate::error::{M365Error, Result};
 
 /**
  * Represents decrypted payload received from the scooter. Payload also have methods which helps to read each value encoded in payload
@@ -21,25 +21,55 @@ impl Payload {
     if let Some(byte) = self.bytes.pop() {
       Ok(byte)
     } else {
-      Err(anyhow!("You are out of bytes to pop"))
+      Err(M365Error::parse("payload", "you are out of bytes to pop"))
     }
   }
 
   pub fn pad_bytes(&mut self, num : usize) -> Result<()> {
     for _ in 0..num {
-      self.pad_byte()
-        .with_context(|| "Could not pad byte")?;
+      self.pad_byte()?;
     }
 
     Ok(())
   }
 
+  /**
+   * Bytes left to pop
+   */
+  pub fn len(&self) -> usize {
+    self.bytes.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.bytes.is_empty()
+  }
+
+  /**
+   * Keep only first len bytes, drop the rest (like random bytes at the end of decrypted reply)
+   */
+  pub fn truncate(&mut self, len : usize) {
+    if self.bytes.len() > len {
+      self.bytes.drain(0..self.bytes.len() - len);
+    }
+  }
+
+  /**
+   * Pop num raw bytes, in the order scooter sent them
+   */
+  pub fn pop_bytes(&mut self, num : usize) -> Result<Vec<u8>> {
+    let mut bytes : Vec<u8> = Vec::new();
+    for _ in 0..num {
+      bytes.push(self.pad_byte()?);
+    }
+
+    Ok(bytes)
+  }
+
   /**
    * Remove head bytes. Every payload contains 3 bytes for additional header
    */
   pub fn pop_head(&mut self) -> Result<bool> {
-    self.pad_bytes(3)
-      .with_context(|| "Could not pop 3 bytes header")?;
+    self.pad_bytes(3)?; // direction, read/write, attribute
 
     Ok(true)
   }
@@ -61,8 +91,7 @@ impl Payload {
    * Pop unsigned short and checks if it is equal 1
    */
   pub fn pop_bool(&mut self) -> Result<bool> {
-    let val = self.pop_u16()
-      .with_context(|| "Could not read bool")?;
+    let val = self.pop_u16()?;
 
     Ok(val == 1)
   }
@@ -0,0 +1,58 @@
+use super::{MiSession, Payload};
+use super::commands::{ScooterCommand, Direction, Attribute, ReadWrite};
+use crate::transport::Transport;
+
+use crate::error::{M365Error, Result};
+
+/**
+ * Length byte of uart message counts payload plus direction and read/write bytes
+ */
+const MAX_REGISTER_WRITE : usize = u8::MAX as usize - 2;
+
+impl<T: Transport> MiSession<T> {
+  /**
+   * Read len bytes starting at register addr, for registers without typed getter.
+   * Direction is MasterToMotor for ESC registers and MasterToBattery for BMS ones.
+   * Returned payload holds only register bytes (at most len of them), header of the reply and
+   * random bytes that encrypted replies carry at the end are already removed.
+   *
+   * let mut payload = session.read_register(Direction::MasterToMotor, 0x3a, 4).await?;
+   * let trip_seconds = payload.pop_u16()?;
+   */
+  pub async fn read_register(&mut self, direction: Direction, addr: u8, len: u8) -> Result<Payload> {
+    tracing::debug!("Reading {} bytes of register 0x{:02x} ({:?})", len, addr, direction);
+
+    let mut payload = self.request(&ScooterCommand {
+      direction,
+      read_write: ReadWrite::Read,
+      attribute: Attribute::Register(addr),
+      payload: vec![len]
+    }).await?;
+    payload.pop_head()?;
+    payload.truncate(len as usize);
+
+    Ok(payload)
+  }
+
+  /**
+   * Write bytes starting at register addr. Scooter does not acknowledge writes, so the same
+   * number of bytes is read back and returned; compare them with what was written to be sure
+   * register accepted the value. Registers are words, so bytes are normally little endian u16s.
+   */
+  pub async fn write_register(&mut self, direction: Direction, addr: u8, bytes: &[u8]) -> Result<Payload> {
+    tracing::debug!("Writing register 0x{:02x} ({:?}): {:02x?}", addr, direction, bytes);
+
+    if bytes.len() > MAX_REGISTER_WRITE {
+      return Err(M365Error::parse("register write", format!("{} bytes do not fit in one message, at most {}", bytes.len(), MAX_REGISTER_WRITE)))
+    }
+
+    self.send(&ScooterCommand {
+      direction,
+      read_write: ReadWrite::Write,
+      attribute: Attribute::Register(addr),
+      payload: bytes.to_vec()
+    }).await?;
+
+    self.read_register(direction, addr, bytes.len() as u8).await
+  }
+}
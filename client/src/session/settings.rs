@@ -1,8 +1,9 @@
 use super::{MiSession, Payload};
 use super::commands::{ScooterCommand, Direction, Attribute, ReadWrite};
+use crate::transport::Transport;
 
-use anyhow::Result;
-use serde::Serialize;
+use crate::error::{M365Error, Result};
+use serde::{Deserialize, Serialize};
 
 /**
 * Manage scooter settings including:
@@ -19,11 +20,13 @@ pub enum Kers {
   Unknown
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all(deserialize = "snake_case"))]
 pub enum TailLight {
   Off,
   OnBrake,
   Always,
+  #[serde(skip_deserializing)]
   Unknown
 }
 
@@ -57,7 +60,7 @@ pub struct SupplementaryInfo {
 }
 
 impl TryFrom<Payload> for SupplementaryInfo {
-  type Error = anyhow::Error;
+  type Error = M365Error;
 
   fn try_from(payload: Payload) -> Result<Self, Self::Error> {
     let mut payload = payload;
@@ -73,33 +76,29 @@ impl TryFrom<Payload> for SupplementaryInfo {
   }
 }
 
-impl MiSession {
+impl<T: Transport> MiSession<T> {
   pub async fn supplementary_info(&mut self) -> Result<SupplementaryInfo> {
     tracing::debug!("Reading supplementary information");
 
-    self.send(&ScooterCommand {
+    let payload = self.request(&ScooterCommand {
       direction: Direction::MasterToBattery,
       read_write: ReadWrite::Read,
       attribute: Attribute::Supplementary,
       payload: vec![0x06]
     }).await?;
 
-    let payload = self.read(2).await?;
-
     Ok(SupplementaryInfo::try_from(payload)?)
   }
 
   pub async fn is_cruise_on(&mut self) -> Result<bool> {
     tracing::debug!("Reading cruise state");
 
-    self.send(&ScooterCommand {
+    let mut payload = self.request(&ScooterCommand {
       direction: Direction::MasterToMotor,
       read_write: ReadWrite::Read,
       attribute: Attribute::Cruise,
       payload: vec![0x02]
     }).await?;
-
-    let mut payload = self.read(2).await?;
     payload.pop_head()?;
 
     Ok(payload.pop_bool()?)
@@ -108,14 +107,12 @@ impl MiSession {
   pub async fn tail_light(&mut self) -> Result<TailLight> {
     tracing::debug!("Reading tail light state");
 
-    self.send(&ScooterCommand {
+    let mut payload = self.request(&ScooterCommand {
       direction: Direction::MasterToMotor,
       read_write: ReadWrite::Read,
       attribute: Attribute::TailLight,
       payload: vec![0x02]
     }).await?;
-
-    let mut payload = self.read(2).await?;
     payload.pop_head()?;
 
     Ok(
@@ -0,0 +1,240 @@
+use super::{FirmwareVersion, MiSession};
+use super::commands::Direction;
+use crate::transport::Transport;
+
+use crate::error::{M365Error, Result};
+use serde::{Deserialize, Serialize};
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+/**
+ * Registers read by one command, 0x20 bytes like the 0xB0 motor block
+ */
+const CHUNK_REGISTERS : usize = 0x10;
+
+/**
+ * Board that owns the registers
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Controller {
+  /**
+   * Motor controller, direction 0x20
+   */
+  Esc,
+  /**
+   * Battery management system, direction 0x22
+   */
+  Bms,
+}
+
+impl Controller {
+  pub fn direction(&self) -> Direction {
+    match self {
+      Controller::Esc => Direction::MasterToMotor,
+      Controller::Bms => Direction::MasterToBattery,
+    }
+  }
+}
+
+/**
+ * One 16 bit register as scooter returned it
+ */
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RegisterValue {
+  pub addr: u8,
+  /**
+   * Two bytes, little endian like every register
+   */
+  #[serde(with = "crate::transport::hex_bytes")]
+  pub raw: Vec<u8>,
+  /**
+   * Meaning of the register, when it is known
+   */
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub name: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub value: Option<String>,
+}
+
+impl RegisterValue {
+  pub fn new(controller: Controller, addr: u8, raw: Vec<u8>) -> Self {
+    let mut register = Self { addr, raw, name: None, value: None };
+
+    let known = KNOWN_REGISTERS.iter()
+      .find(|known| known.controller == controller && known.addrs.contains(&addr));
+    if let Some(known) = known {
+      register.name = Some(known.name.to_owned());
+      register.value = Some((known.decode)(register.word()));
+    }
+
+    register
+  }
+
+  pub fn word(&self) -> u16 {
+    u16::from_le_bytes([*self.raw.first().unwrap_or(&0), *self.raw.get(1).unwrap_or(&0)])
+  }
+}
+
+/**
+ * Every register of ESC and BMS that answered, for troubleshooting. Saved as pretty json with
+ * one register per block, so two snapshots (before and after some change) can be compared with diff.
+ *
+ * let snapshot = session.register_snapshot().await?;
+ * snapshot.save("before.json")?;
+ */
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RegisterSnapshot {
+  pub esc: Vec<RegisterValue>,
+  pub bms: Vec<RegisterValue>,
+}
+
+impl RegisterSnapshot {
+  pub fn registers(&self, controller: Controller) -> &[RegisterValue] {
+    match controller {
+      Controller::Esc => &self.esc,
+      Controller::Bms => &self.bms,
+    }
+  }
+
+  pub fn get(&self, controller: Controller, addr: u8) -> Option<&RegisterValue> {
+    self.registers(controller).iter().find(|register| register.addr == addr)
+  }
+
+  pub fn to_json(&self) -> Result<String> {
+    serde_json::to_string_pretty(self).map_err(|e| M365Error::parse("register snapshot", e))
+  }
+
+  pub fn from_json(json: &str) -> Result<Self> {
+    serde_json::from_str(json).map_err(|e| M365Error::parse("register snapshot", e))
+  }
+
+  pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+    std::fs::write(path, self.to_json()?)?;
+    Ok(())
+  }
+
+  pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+    Self::from_json(&std::fs::read_to_string(path)?)
+  }
+}
+
+impl<T: Transport> MiSession<T> {
+  /**
+   * Read whole address space (0x00 - 0xff) of ESC and BMS in chunks. Ranges the scooter does not
+   * answer are left out of the snapshot, it takes a reply timeout for each of them.
+   */
+  pub async fn register_snapshot(&mut self) -> Result<RegisterSnapshot> {
+    tracing::debug!("Reading register snapshot");
+
+    Ok(RegisterSnapshot {
+      esc: self.read_controller(Controller::Esc).await?,
+      bms: self.read_controller(Controller::Bms).await?,
+    })
+  }
+
+  async fn read_controller(&mut self, controller: Controller) -> Result<Vec<RegisterValue>> {
+    let mut registers = Vec::new();
+
+    for start in (0..=u8::MAX).step_by(CHUNK_REGISTERS) {
+      let mut payload = match self.read_register(controller.direction(), start, (CHUNK_REGISTERS * 2) as u8).await {
+        Ok(payload) => payload,
+        Err(error) if error.is_transient() => {
+          tracing::warn!("Skipping {:?} registers from 0x{:02x}: {}", controller, start, error);
+          continue
+        },
+        Err(error) => return Err(error)
+      };
+
+      let mut addr = start;
+      while payload.len() >= 2 {
+        registers.push(RegisterValue::new(controller, addr, payload.pop_bytes(2)?));
+        addr = addr.wrapping_add(1);
+      }
+    }
+
+    Ok(registers)
+  }
+}
+
+struct KnownRegister {
+  controller: Controller,
+  addrs: RangeInclusive<u8>,
+  name: &'static str,
+  decode: fn(u16) -> String,
+}
+
+const fn esc(addrs: RangeInclusive<u8>, name: &'static str, decode: fn(u16) -> String) -> KnownRegister {
+  KnownRegister { controller: Controller::Esc, addrs, name, decode }
+}
+
+const fn bms(addrs: RangeInclusive<u8>, name: &'static str, decode: fn(u16) -> String) -> KnownRegister {
+  KnownRegister { controller: Controller::Bms, addrs, name, decode }
+}
+
+/**
+ * Registers documented in docs/protocol.md
+ */
+const KNOWN_REGISTERS : &[KnownRegister] = &[
+  esc(0x10..=0x16, "serial", ascii),
+  esc(0x17..=0x19, "pin", ascii),
+  esc(0x1a..=0x1a, "esc_version", version),
+  esc(0x25..=0x25, "distance_left", |word| format!("{:.2} km", word as f32 / 100.0)),
+  esc(0x3a..=0x3a, "trip_time", |word| format!("{} s", word)),
+  esc(0x3b..=0x3b, "trip_distance", |word| format!("{} m", word)),
+  esc(0x3e..=0x3e, "frame_temperature", celsius_tenths),
+  esc(0x67..=0x67, "bms_version", version),
+  esc(0x68..=0x68, "ble_version", version),
+  esc(0x7b..=0x7b, "kers", number),
+  esc(0x7c..=0x7c, "cruise", number),
+  esc(0x7d..=0x7d, "tail_light", number),
+  esc(0xb0..=0xb0, "error", number),
+  esc(0xb1..=0xb1, "warning", number),
+  esc(0xb2..=0xb2, "flags", |word| format!("{:#06x}", word)),
+  esc(0xb3..=0xb3, "work_mode", number),
+  esc(0xb4..=0xb4, "battery_percent", |word| format!("{} %", word)),
+  esc(0xb5..=0xb5, "speed", |word| format!("{:.3} km/h", word as i16 as f32 / 1000.0)),
+  esc(0xb6..=0xb6, "average_speed", |word| format!("{:.3} km/h", word as f32 / 1000.0)),
+  esc(0xb7..=0xb7, "total_distance_low", number),
+  esc(0xb8..=0xb8, "total_distance_high", number),
+  esc(0xb9..=0xb9, "trip_distance", |word| format!("{} m", word)),
+  esc(0xba..=0xba, "uptime", |word| format!("{} s", word)),
+  esc(0xbb..=0xbb, "frame_temperature", celsius_tenths),
+
+  bms(0x10..=0x16, "serial", ascii),
+  bms(0x17..=0x17, "bms_version", version),
+  bms(0x18..=0x18, "design_capacity", |word| format!("{} mAh", word)),
+  bms(0x1b..=0x1b, "cycles", number),
+  bms(0x1c..=0x1c, "charges", number),
+  bms(0x1e..=0x1e, "manufacture_date", |word| format!("{}-{:02}-{:02}", 2000 + (word >> 9), (word >> 5) & 0x0f, word & 0x1f)),
+  bms(0x20..=0x20, "recharged_low", number),
+  bms(0x21..=0x21, "recharged_high", number),
+  bms(0x31..=0x31, "capacity", |word| format!("{} mAh", word)),
+  bms(0x32..=0x32, "percent", |word| format!("{} %", word)),
+  bms(0x33..=0x33, "current", |word| format!("{:.2} A", word as i16 as f32 / 100.0)),
+  bms(0x34..=0x34, "voltage", |word| format!("{:.2} V", word as f32 / 100.0)),
+  bms(0x35..=0x35, "temperatures", |word| format!("{} / {}", word & 0xff, word >> 8)),
+  bms(0x40..=0x49, "cell_voltage", |word| format!("{:.3} V", word as f32 / 1000.0)),
+];
+
+fn number(word: u16) -> String {
+  word.to_string()
+}
+
+/**
+ * Two characters of text register, little endian
+ */
+fn ascii(word: u16) -> String {
+  word.to_le_bytes().iter()
+    .filter(|byte| **byte != 0)
+    .map(|byte| if byte.is_ascii_graphic() { *byte as char } else { '.' })
+    .collect()
+}
+
+fn version(word: u16) -> String {
+  FirmwareVersion::from(word).to_string()
+}
+
+fn celsius_tenths(word: u16) -> String {
+  format!("{:.1} °C", word as i16 as f32 / 10.0)
+}
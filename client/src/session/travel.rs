@@ -1,9 +1,40 @@
 use super::MiSession;
 use super::commands::{ScooterCommand, Direction, Attribute, ReadWrite};
+use crate::transport::Transport;
 
-use anyhow::Result;
+use crate::error::Result;
+use serde::Serialize;
+use std::time::Duration;
 
-impl MiSession {
+/**
+ * Current ride, counted by scooter since it was switched on
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct TripInfo {
+  /**
+   * Time spent riding
+   */
+  pub time: Duration,
+  /**
+   * Distance ridden, in meters
+   */
+  pub distance_m: u16,
+}
+
+impl TripInfo {
+  /**
+   * Average speed of the ride in kilometers per hour, 0 before ride starts
+   */
+  pub fn average_speed_kmh(&self) -> f32 {
+    if self.time.is_zero() {
+      return 0.0
+    }
+
+    self.distance_m as f32 / self.time.as_secs_f32() * 3.6
+  }
+}
+
+impl<T: Transport> MiSession<T> {
   /**
    * Get travel distance left in kilometers
    */
@@ -17,9 +48,7 @@ impl MiSession {
       payload: vec![0x02]
     };
 
-    self.send(&cmd).await?;
-
-    let mut payload = self.read(2).await?;
+    let mut payload = self.request(&cmd).await?;
     payload.pop_head()?;
 
     let distance_left = payload.pop_u16()?;
@@ -42,9 +71,7 @@ impl MiSession {
       payload: vec![0x02]
     };
 
-    self.send(&cmd).await?;
-
-    let mut payload = self.read(2).await?;
+    let mut payload = self.request(&cmd).await?;
     payload.pop_head()?;
 
     let speed = payload.pop_i16()?;
@@ -67,13 +94,33 @@ impl MiSession {
       payload: vec![0x02]
     };
 
-    self.send(&cmd).await?;
-
-    let mut payload = self.read(3).await?;
+    let mut payload = self.request(&cmd).await?;
     payload.pop_head()?;
 
     let trip_distance = payload.pop_u16()?;
 
     Ok(trip_distance)
   }
+
+  /**
+   * Ride time and distance of the current trip
+   */
+  pub async fn trip_info(&mut self) -> Result<TripInfo> {
+    tracing::debug!("Reading trip info");
+
+    let cmd = ScooterCommand {
+      direction: Direction::MasterToMotor,
+      read_write: ReadWrite::Read,
+      attribute: Attribute::TripInfo,
+      payload: vec![0x04]
+    };
+
+    let mut payload = self.request(&cmd).await?;
+    payload.pop_head()?;
+
+    let seconds = payload.pop_u16()?; // ---Var58=seconds this trip=0x027b=635=10min 35s
+    let distance_m = payload.pop_u16()?; // ---Var59=meters this trip=0x000a=10m
+
+    Ok(TripInfo { time: Duration::from_secs(seconds as u64), distance_m })
+  }
 }
@@ -0,0 +1,406 @@
+use crate::adapter::AdapterSelector;
+use crate::connection::ConnectionHelper;
+use crate::error::{M365Error, Result};
+use crate::login::LoginRequest;
+use crate::mi_crypto::AuthToken;
+use crate::model::ScooterModel;
+use crate::protocol::MiProtocol;
+use crate::scanner::ScooterScanner;
+use crate::session::MiSession;
+use crate::transport::{Transport, BleTransport, Recorder, RecordingTransport};
+
+use btleplug::api::BDAddr;
+use btleplug::platform::Peripheral;
+use rand_core::{OsRng, RngCore};
+use std::future::Future;
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::time::{sleep, Instant};
+use tokio_util::sync::CancellationToken;
+
+/**
+ * How long to look for scooter before counting it as failed attempt
+ */
+const SCAN_TIMEOUT : Duration = Duration::from_secs(30);
+
+/**
+ * Where supervisor is on the way to usable session. Published on watch channel, see Supervisor::subscribe
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub enum SupervisorState {
+  Scanning,
+  Connecting,
+  LoggingIn,
+  Ready,
+  /**
+   * Last attempt failed, next one starts after delay
+   */
+  Backoff { attempt: u32, delay: Duration },
+  /**
+   * Too many attempts failed in a row, scooter is probably switched off. Scan again after retry_in
+   */
+  Parked { retry_in: Duration },
+  /**
+   * Link was closed on purpose to let other scooter use the adapter, see Supervisor::release
+   */
+  Released,
+}
+
+/**
+ * Delays between failed attempts: initial * multiplier^(attempt - 1), capped at max and randomized by
+ * +-jitter fraction, so several clients don't hammer the adapter at the same moment.
+ */
+#[derive(Debug, Clone)]
+pub struct Backoff {
+  pub initial: Duration,
+  pub max: Duration,
+  pub multiplier: f64,
+  /**
+   * Between 0 and 1, 0.2 means delay is randomly 20% shorter or longer
+   */
+  pub jitter: f64,
+  /**
+   * Number of failed attempts in a row before supervisor parks
+   */
+  pub park_after: u32,
+  pub park_for: Duration,
+}
+
+impl Default for Backoff {
+  fn default() -> Self {
+    Self {
+      initial: Duration::from_secs(1),
+      max: Duration::from_secs(60),
+      multiplier: 2.0,
+      jitter: 0.2,
+      park_after: 8,
+      park_for: Duration::from_secs(300),
+    }
+  }
+}
+
+impl Backoff {
+  /**
+   * Delay before next attempt, attempt counts from 1
+   */
+  pub fn delay(&self, attempt: u32) -> Duration {
+    let exponent = attempt.saturating_sub(1).min(i32::MAX as u32) as i32;
+    let delay = (self.initial.as_secs_f64() * self.multiplier.powi(exponent)).min(self.max.as_secs_f64());
+
+    // Uniform random value between -1 and 1
+    let random = OsRng.next_u32() as f64 / u32::MAX as f64 * 2.0 - 1.0;
+    let delay = delay * (1.0 + self.jitter.clamp(0.0, 1.0) * random);
+
+    // Hand-built policies may hold nan or inf, fall back to max delay instead of panicking
+    match delay.is_finite() {
+      true => Duration::from_secs_f64(delay.max(0.0)),
+      false => self.max,
+    }
+  }
+}
+
+/**
+ * Steps needed to get logged in session. BleConnector does it over bluetooth, tests can use ChannelTransport
+ */
+pub trait Connector: Send {
+  type Transport: Transport;
+
+  /**
+   * Find scooter. Called before first connection and again after supervisor was parked
+   */
+  fn scan(&mut self) -> impl Future<Output = Result<()>> + Send;
+
+  /**
+   * Connect to scooter found by scan
+   */
+  fn connect(&mut self) -> impl Future<Output = Result<()>> + Send;
+
+  /**
+   * Login into connected scooter
+   */
+  fn login(&mut self) -> impl Future<Output = Result<MiSession<Self::Transport>>> + Send;
+
+  /**
+   * Open protocol over connected scooter without login, old session is resumed on top of it
+   */
+  fn reattach(&mut self) -> impl Future<Output = Result<MiProtocol<Self::Transport>>> + Send;
+
+  /**
+   * Close link to scooter. Session keys are kept, so session can be resumed after next connect
+   */
+  fn disconnect(&mut self) -> impl Future<Output = Result<()>> + Send {
+    async { Ok(()) }
+  }
+}
+
+/**
+ * Keeps scooter session alive. Instead of giving up when scooter is unreachable, it retries with
+ * exponential backoff and parks for a while after too many failures, so the client can run for days
+ * while the scooter is switched off.
+ *
+ * let mut supervisor = Supervisor::new(BleConnector::new(mac, token, model), Backoff::default());
+ * let mut session = supervisor.establish().await;
+ * loop {
+ *   if let Err(error) = session.motor_info().await {
+ *     session = supervisor.recover(session, &error).await;
+ *   }
+ * }
+ */
+pub struct Supervisor<C: Connector> {
+  connector: C,
+  backoff: Backoff,
+  state: watch::Sender<SupervisorState>,
+  scanned: bool,
+}
+
+impl<C: Connector> Supervisor<C> {
+  pub fn new(connector: C, backoff: Backoff) -> Self {
+    let (state, _) = watch::channel(SupervisorState::Scanning);
+
+    Self { connector, backoff, state, scanned: false }
+  }
+
+  /**
+   * Receive every state change
+   */
+  pub fn subscribe(&self) -> watch::Receiver<SupervisorState> {
+    self.state.subscribe()
+  }
+
+  pub fn state(&self) -> SupervisorState {
+    self.state.borrow().clone()
+  }
+
+  pub fn connector(&self) -> &C {
+    &self.connector
+  }
+
+  /**
+   * Go through scanning, connecting and login until session is ready. Never fails, waits instead.
+   */
+  pub async fn establish(&mut self) -> MiSession<C::Transport> {
+    let mut attempt = 0;
+
+    loop {
+      let error = match self.try_establish().await {
+        Ok(session) => {
+          self.set_state(SupervisorState::Ready);
+          return session
+        },
+        Err(error) => error
+      };
+
+      attempt += 1;
+      tracing::warn!("Attempt {} to reach scooter failed: {}", attempt, error);
+
+      if attempt >= self.backoff.park_after {
+        let retry_in = self.backoff.park_for;
+        tracing::info!("Scooter seems to be switched off, parking for {:?}", retry_in);
+        self.set_state(SupervisorState::Parked { retry_in });
+        sleep(retry_in).await;
+
+        attempt = 0;
+        self.scanned = false; // Scooter might come back with different peripheral id
+        continue;
+      }
+
+      let delay = self.backoff.delay(attempt);
+      self.set_state(SupervisorState::Backoff { attempt, delay });
+      sleep(delay).await;
+    }
+  }
+
+  /**
+   * Ready session failed with given error. After short link drop session is resumed with its keys and
+   * counters, otherwise (or when scooter forgot the keys) supervisor logs in again from scratch.
+   */
+  pub async fn recover(&mut self, session: MiSession<C::Transport>, error: &M365Error) -> MiSession<C::Transport> {
+    tracing::warn!("Session lost: {}", error);
+
+    if matches!(error, M365Error::Scanner(_)) {
+      self.scanned = false;
+    }
+
+    if !keeps_keys(error) {
+      return self.establish().await
+    }
+
+    self.resume(session).await
+  }
+
+  /**
+   * Connect again and continue session closed by release (or dropped link), login from scratch
+   * when scooter doesn't accept its keys anymore
+   */
+  pub async fn resume(&mut self, session: MiSession<C::Transport>) -> MiSession<C::Transport> {
+    if self.scanned {
+      let mut session = session;
+      match self.try_resume(&mut session).await {
+        Ok(()) => {
+          tracing::info!("Session resumed without login");
+          self.set_state(SupervisorState::Ready);
+          return session
+        },
+        Err(error) => tracing::info!("Could not resume session, logging in again: {}", error)
+      }
+    }
+
+    self.establish().await
+  }
+
+  /**
+   * Close the link, for example to let other scooter use the adapter. Keep the session to resume it later.
+   */
+  pub async fn release(&mut self) {
+    if let Err(error) = self.connector.disconnect().await {
+      tracing::warn!("Could not disconnect scooter: {}", error);
+    }
+
+    self.set_state(SupervisorState::Released);
+  }
+
+  async fn try_resume(&mut self, session: &mut MiSession<C::Transport>) -> Result<()> {
+    self.set_state(SupervisorState::Connecting);
+    self.connector.connect().await?;
+
+    self.set_state(SupervisorState::LoggingIn);
+    let protocol = self.connector.reattach().await?;
+    session.resume(protocol).await
+  }
+
+  async fn try_establish(&mut self) -> Result<MiSession<C::Transport>> {
+    if !self.scanned {
+      self.set_state(SupervisorState::Scanning);
+      self.connector.scan().await?;
+      self.scanned = true;
+    }
+
+    self.set_state(SupervisorState::Connecting);
+    self.connector.connect().await?;
+
+    self.set_state(SupervisorState::LoggingIn);
+    self.connector.login().await
+  }
+
+  fn set_state(&self, state: SupervisorState) {
+    tracing::debug!("Supervisor state: {:?}", state);
+    self.state.send_replace(state);
+  }
+}
+
+/**
+ * Link dropped but nothing says scooter rejected our keys, so session is worth resuming
+ */
+fn keeps_keys(error: &M365Error) -> bool {
+  match error {
+    M365Error::Login(_) => false,
+    M365Error::Crypto(error) => error.is_ignorable(),
+    _ => true
+  }
+}
+
+/**
+ * Connector for real scooter: scans for mac address, connects over bluetooth and logs in
+ * (ninebot scooters don't need login, xiaomi firmware is auto-detected)
+ */
+pub struct BleConnector {
+  mac: BDAddr,
+  token: AuthToken,
+  model: ScooterModel,
+  adapter: AdapterSelector,
+  recorder: Option<Recorder>,
+  scanner: Option<ScooterScanner>,
+  device: Option<Peripheral>,
+  cancel: CancellationToken,
+}
+
+impl BleConnector {
+  pub fn new(mac: BDAddr, token: AuthToken, model: ScooterModel) -> Self {
+    Self { mac, token, model, adapter: AdapterSelector::First, recorder: None, scanner: None, device: None, cancel: CancellationToken::new() }
+  }
+
+  /**
+   * Scan with chosen bluetooth adapter instead of the first one
+   */
+  pub fn with_adapter(mut self, adapter: AdapterSelector) -> Self {
+    self.adapter = adapter;
+    self
+  }
+
+  /**
+   * Record raw traffic of every connection, to replay it later with ReplayTransport
+   */
+  pub fn with_recorder(mut self, recorder: Recorder) -> Self {
+    self.recorder = Some(recorder);
+    self
+  }
+
+  /**
+   * Cancel it to abort running scan, for example when daemon shuts down
+   */
+  pub fn cancellation(&self) -> CancellationToken {
+    self.cancel.clone()
+  }
+
+  /**
+   * Scooter found by last scan
+   */
+  pub fn device(&self) -> Option<&Peripheral> {
+    self.device.as_ref()
+  }
+
+  fn found_device(&self) -> Result<&Peripheral> {
+    self.device.as_ref().ok_or_else(|| M365Error::Disconnected("scooter was not found yet".to_owned()))
+  }
+
+  async fn protocol(&self) -> Result<MiProtocol<RecordingTransport<BleTransport>>> {
+    let transport = BleTransport::new(self.found_device()?).await?;
+    Ok(MiProtocol::with_transport(RecordingTransport::optional(transport, self.recorder.clone())))
+  }
+}
+
+impl Connector for BleConnector {
+  type Transport = RecordingTransport<BleTransport>;
+
+  async fn scan(&mut self) -> Result<()> {
+    let scanner = match self.scanner.as_mut() {
+      Some(scanner) => scanner,
+      None => self.scanner.insert(ScooterScanner::with_adapter(&self.adapter).await?)
+    };
+
+    tracing::info!("Searching scooter with address: {}", self.mac);
+    let scooter = scanner.wait_for(&self.mac, Instant::now() + SCAN_TIMEOUT, &self.cancel).await?;
+
+    self.device = Some(scanner.peripheral(&scooter).await?);
+    Ok(())
+  }
+
+  async fn connect(&mut self) -> Result<()> {
+    let connection = ConnectionHelper::new(self.found_device()?);
+    if !connection.connect().await? {
+      tracing::info!("Already connected!");
+    }
+
+    Ok(())
+  }
+
+  async fn login(&mut self) -> Result<MiSession<Self::Transport>> {
+    let protocol = self.protocol().await?;
+
+    if self.model.is_ninebot() {
+      return MiSession::start_ninebot(protocol).await;
+    }
+
+    let request = LoginRequest::with_protocol(protocol, &self.token);
+    request.start_auto().await
+  }
+
+  async fn reattach(&mut self) -> Result<MiProtocol<Self::Transport>> {
+    self.protocol().await
+  }
+
+  async fn disconnect(&mut self) -> Result<()> {
+    ConnectionHelper::new(self.found_device()?).disconnect().await?;
+    Ok(())
+  }
+}
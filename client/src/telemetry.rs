@@ -1,16 +1,24 @@
-use anyhow::Result;
+use crate::error::Result;
 use chrono::Local;
 use serde::{Deserialize, Serialize};
-use serialport::SerialPort;
 
 use crate::gps_location::GPSInfo;
-use crate::session::BatteryInfo;
+use crate::supervisor::Backoff;
+use crate::session::{BatteryInfo, BmsInfo, EscError, EscStatus, EscWarning, FirmwareVersions};
+use std::time::{Duration, Instant};
+use crate::transport::Transport;
 use crate::MiSession;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Telemetry {
     pub timestamp: String,
 
+    /**
+     * Name of the scooter in the fleet, see Telemetry::with_scooter
+     */
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scooter: Option<String>,
+
     /**
      * Speed in kilometers per hour. Obtained by scooter
      */
@@ -23,46 +31,260 @@ pub struct Telemetry {
     /**
      * Distance (meters). Current trip distance
      */
-    pub trip_distance_m: i16,
+    pub trip_distance_m: u16,
+    /**
+     * Time (seconds) spent riding in current trip. With trip_distance_m gives average speed of the ride
+     */
+    pub trip_time_sec: u64,
     /**
      * Estimated Distance left (kilometers).
      */
     pub trip_distance_left_km: f32,
-    pub uptime_sec: f32,
     /**
      * Frame temperature (Celsius).
      */
     pub frame_temp: f32,
+    /**
+     * Error and warning shown on the dashboard, published as codes. See FaultTracker for transitions.
+     */
+    pub esc_status: EscStatus,
     pub battery_info: BatteryInfo,
+    /**
+     * Battery identity and lifetime counters. Read at low rate, see Telemetry::with_bms_info
+     */
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bms_info: Option<BmsInfo>,
 
     pub gpsinfo: GPSInfo,
 }
 
 impl Telemetry {
-    pub async fn pull_scooter(session: &mut MiSession, port: &mut dyn SerialPort) -> Result<Self> {
+    /**
+     * Read scooter and combine it with GPS fix read by caller. GPS port is shared by the whole
+     * fleet, so it should be locked only for GPSInfo::get_gps_position, not for the scooter poll.
+     */
+    pub async fn pull_scooter<T: Transport>(session: &mut MiSession<T>, gps: GPSInfo) -> Result<Self> {
         //Pull the necessary data
         let motorinfo = session.motor_info().await?;
 
+        let trip = session.trip_info().await?;
         let battery_info = session.battery_info().await?;
         let distance_left = session.distance_left().await?;
 
-        //Pull GPS data
-        let gps = GPSInfo::get_gps_position(port)?;
-
         let time = Local::now().to_rfc3339();
 
         let telemetry: Telemetry = Telemetry {
             timestamp: time,
+            scooter: None,
             speed_kmh: motorinfo.speed_kmh,
             total_distance_m: motorinfo.total_distance_m,
-            trip_distance_m: motorinfo.trip_distance_m,
+            trip_distance_m: trip.distance_m,
+            trip_time_sec: trip.time.as_secs(),
             trip_distance_left_km: distance_left,
-            uptime_sec: motorinfo.uptime.as_secs_f32(),
             frame_temp: motorinfo.frame_temperature,
+            esc_status: motorinfo.status,
             battery_info,
+            bms_info: None,
             gpsinfo: gps,
         };
 
         Ok(telemetry)
     }
+
+    /**
+     * Tag telemetry with scooter identity, so readings of the whole fleet can share one topic
+     */
+    pub fn with_scooter(mut self, name: &str) -> Self {
+        self.scooter = Some(name.to_owned());
+        self
+    }
+
+    pub fn with_bms_info(mut self, bms_info: Option<BmsInfo>) -> Self {
+        self.bms_info = bms_info;
+        self
+    }
+}
+
+/**
+ What is running on the scooter. Published retained after every login, so the server always knows
+ which firmware each scooter of the fleet has, even when it connects later.
+ */
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeviceInfo {
+    pub timestamp: String,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scooter: Option<String>,
+
+    pub serial: String,
+    pub firmware: FirmwareVersions,
+}
+
+impl DeviceInfo {
+    pub async fn pull_scooter<T: Transport>(session: &mut MiSession<T>) -> Result<Self> {
+        let serial = session.serial_number().await?;
+        let firmware = session.firmware_versions().await?;
+
+        Ok(DeviceInfo {
+            timestamp: Local::now().to_rfc3339(),
+            scooter: None,
+            serial: serial.trim_end_matches('\0').to_owned(),
+            firmware,
+        })
+    }
+
+    pub fn with_scooter(mut self, name: &str) -> Self {
+        self.scooter = Some(name.to_owned());
+        self
+    }
+}
+
+/**
+ Error or warning that appeared or went away
+ */
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FaultEvent {
+    pub timestamp: String,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scooter: Option<String>,
+
+    pub kind: FaultKind,
+    /**
+     * True when fault appeared, false when it was cleared
+     */
+    pub active: bool,
+    pub code: u16,
+    pub description: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FaultKind {
+    Error,
+    Warning,
+}
+
+/**
+ Remembers last error and warning of one scooter and turns telemetry into fault events, so only
+ changes are published. Fault that is present when tracking starts is reported as appeared.
+ */
+#[derive(Debug, Clone, Default)]
+pub struct FaultTracker {
+    last: EscStatus,
+}
+
+impl FaultTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn update(&mut self, status: &EscStatus) -> Vec<FaultEvent> {
+        let mut events = Vec::new();
+
+        if status.error != self.last.error {
+            events.extend(error_events(self.last.error, status.error));
+        }
+        if status.warning != self.last.warning {
+            events.extend(warning_events(self.last.warning, status.warning));
+        }
+
+        self.last = *status;
+        events
+    }
+}
+
+fn error_events(previous: EscError, current: EscError) -> Vec<FaultEvent> {
+    [(previous, false), (current, true)]
+        .into_iter()
+        .filter(|(error, _)| error.is_fault())
+        .map(|(error, active)| FaultEvent::new(FaultKind::Error, active, error.code(), error.description()))
+        .collect()
+}
+
+fn warning_events(previous: EscWarning, current: EscWarning) -> Vec<FaultEvent> {
+    [(previous, false), (current, true)]
+        .into_iter()
+        .filter(|(warning, _)| warning.is_fault())
+        .map(|(warning, active)| FaultEvent::new(FaultKind::Warning, active, warning.code(), warning.description()))
+        .collect()
+}
+
+impl FaultEvent {
+    fn new(kind: FaultKind, active: bool, code: u16, description: &str) -> Self {
+        Self {
+            timestamp: Local::now().to_rfc3339(),
+            scooter: None,
+            kind,
+            active,
+            code,
+            description: description.to_owned(),
+        }
+    }
+
+    pub fn with_scooter(mut self, name: &str) -> Self {
+        self.scooter = Some(name.to_owned());
+        self
+    }
+}
+
+/**
+ Decides when slow changing data (like BmsInfo) is due again. It stays due until mark_done is
+ called, so a failed read is retried with the next poll.
+ */
+#[derive(Debug, Clone)]
+pub struct LowRate {
+    interval: Duration,
+    last: Option<Instant>,
+}
+
+impl LowRate {
+    pub fn new(interval: Duration) -> Self {
+        Self { interval, last: None }
+    }
+
+    /**
+     True when nothing was read yet, or the last read is older than interval
+     */
+    pub fn is_due(&self) -> bool {
+        self.last.is_none_or(|last| last.elapsed() >= self.interval)
+    }
+
+    /**
+     Remember successful read, next one is due after interval
+     */
+    pub fn mark_done(&mut self) {
+        self.last = Some(Instant::now());
+    }
+}
+
+/**
+ Schedules retries of something that failed (like DeviceInfo), waiting longer after each failure
+ so a scooter that keeps refusing is not asked with every poll.
+ */
+#[derive(Debug, Clone)]
+pub struct Retry {
+    backoff: Backoff,
+    failures: u32,
+    next: Option<Instant>,
+}
+
+impl Retry {
+    pub fn new(backoff: Backoff) -> Self {
+        Self { backoff, failures: 0, next: None }
+    }
+
+    pub fn is_due(&self) -> bool {
+        self.next.is_none_or(|next| Instant::now() >= next)
+    }
+
+    pub fn failed(&mut self) {
+        self.failures += 1;
+        self.next = Some(Instant::now() + self.backoff.delay(self.failures));
+    }
+
+    pub fn succeeded(&mut self) {
+        self.failures = 0;
+        self.next = None;
+    }
 }
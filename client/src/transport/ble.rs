@@ -0,0 +1,140 @@
+use super::Transport;
+use crate::consts::Registers;
+
+use uuid::Uuid;
+use futures::Stream;
+use futures::stream::StreamExt;
+use std::pin::Pin;
+use btleplug::platform::Peripheral;
+use btleplug::api::{Peripheral as _, Characteristic, WriteType, ValueNotification};
+use crate::error::{BluetoothContext, M365Error, Result};
+
+/**
+ * Transport backed by real bluetooth peripheral (btleplug). Discovers all mi characteristics and
+ * subscribes to notifications for AVDTP, UPNP and RX when created.
+ * Legacy firmware may come without auth service, then only uart channels are available.
+ */
+pub struct BleTransport {
+  device: Peripheral,
+  avdtp: Option<Characteristic>,
+  upnp: Option<Characteristic>,
+  tx: Characteristic,
+  rx: Characteristic,
+  stream: Pin<Box<dyn Stream<Item = ValueNotification> + Send>>, //Notifications from scooter
+}
+
+impl BleTransport {
+  pub async fn new(device: &Peripheral) -> Result<Self> {
+    let (avdtp, upnp, tx, rx) = setup_channels(device).await?;
+    let stream = device.notifications().await
+      .with_context(|| "Could not load notifications stream")?;
+
+    Ok(Self {
+      device: device.clone(),
+      stream,
+      avdtp,
+      upnp,
+      tx,
+      rx
+    })
+  }
+
+  /**
+   * Map register type to Bluetooth Characteristic channel
+   */
+  fn reg_to_channel(&self, reg : &Registers) -> Result<&Characteristic> {
+    match reg {
+      Registers::RX => Ok(&self.rx),
+      Registers::TX => Ok(&self.tx),
+      Registers::AVDTP => self.avdtp.as_ref().ok_or_else(|| M365Error::MissingCharacteristic("auth service".to_owned())),
+      Registers::UPNP => self.upnp.as_ref().ok_or_else(|| M365Error::MissingCharacteristic("auth service".to_owned())),
+      _ => Err(M365Error::MissingCharacteristic(format!("characteristic for register {:?}", reg)))
+    }
+  }
+}
+
+impl Transport for BleTransport {
+  async fn write(&mut self, reg: &Registers, data: &[u8]) -> Result<()> {
+    let channel = self.reg_to_channel(reg)?;
+
+    self.device.write(channel, data, WriteType::WithoutResponse).await
+      .with_context(|| format!("Could not write to {:?}", reg))?;
+
+    Ok(())
+  }
+
+  async fn next(&mut self) -> Option<ValueNotification> {
+    self.stream.next().await
+  }
+
+  async fn subscribe(&mut self, reg: &Registers) -> Result<()> {
+    let channel = self.reg_to_channel(reg)?;
+
+    self.device.subscribe(channel).await
+      .with_context(|| format!("Could not subscribe to scooter {:?} notifications", reg))?;
+
+    Ok(())
+  }
+
+  async fn unsubscribe(&mut self, reg: &Registers) -> Result<()> {
+    let channel = match (reg, &self.avdtp) {
+      (Registers::AVDTP | Registers::UPNP, None) => return Ok(()), // Nothing was subscribed
+      _ => self.reg_to_channel(reg)?
+    };
+    self.device.unsubscribe(channel).await
+      .with_context(|| format!("Could not unsubscribe from scooter {:?} notifications", reg))?;
+
+    Ok(())
+  }
+}
+
+async fn find_characteristic(device : &Peripheral, service_uuid: Uuid, char_uuid: Uuid) -> Result<Characteristic> {
+  device.discover_services().await
+    .with_context(|| "Could not enable discovering devices")?;
+
+  for ch in device.characteristics() {
+    if ch.uuid == char_uuid && ch.service_uuid == service_uuid {
+      tracing::debug!("Found Characteristic: {:?}", ch);
+      return Ok(ch)
+    } else {
+      tracing::debug!("Skipped Characteristic: {:?}", ch);
+    }
+  }
+
+  Err(M365Error::MissingCharacteristic(format!("characteristic {}", char_uuid)))
+}
+
+type Channels = (Option<Characteristic>, Option<Characteristic>, Characteristic, Characteristic);
+
+async fn setup_channels(device : &Peripheral) -> Result<Channels> {
+  device.discover_services().await
+    .with_context(|| "Could not discover services")?;
+
+  // Auth channels
+  tracing::debug!("Setting up AUTH channels");
+  let avdtp = find_characteristic(device, Registers::AUTH.to_uuid(), Registers::AVDTP.to_uuid()).await.ok();
+  let upnp = find_characteristic(device, Registers::AUTH.to_uuid(), Registers::UPNP.to_uuid()).await.ok();
+
+  // UART channels
+  tracing::debug!("Setting up UART channels");
+  let tx = find_characteristic(device, Registers::UART.to_uuid(), Registers::TX.to_uuid()).await?;
+  let rx = find_characteristic(device, Registers::UART.to_uuid(), Registers::RX.to_uuid()).await?;
+
+  if let (Some(avdtp), Some(upnp)) = (&avdtp, &upnp) {
+    tracing::debug!("Enabling notify for AVDTP");
+    device.subscribe(avdtp).await
+      .with_context(|| "Could not subscribe to scooter AVDTP notifications")?;
+
+    tracing::debug!("Enabling notify for UPNP");
+    device.subscribe(upnp).await
+      .with_context(|| "Could not subscribe to scooter UPNP notifications")?;
+  } else {
+    tracing::warn!("Scooter has no auth service, only legacy firmware will work");
+  }
+
+  tracing::debug!("Enabling notify for RX");
+  device.subscribe(&rx).await
+    .with_context(|| "Could not subscribe to scooter RX notifications")?;
+
+  Ok((avdtp, upnp, tx, rx))
+}
@@ -0,0 +1,100 @@
+use super::Transport;
+use crate::consts::Registers;
+
+use uuid::Uuid;
+use std::collections::HashSet;
+use tokio::sync::mpsc;
+use btleplug::api::ValueNotification;
+use crate::error::{M365Error, Result};
+
+/**
+ * In-memory transport. Everything written by MiProtocol ends in the ChannelPeer and everything
+ * the peer notifies is read back by MiProtocol. Useful for tests and machines without bluetooth.
+ */
+pub struct ChannelTransport {
+  writes: mpsc::UnboundedSender<ValueNotification>,
+  notifications: mpsc::UnboundedReceiver<ValueNotification>,
+  subscribed: HashSet<Uuid>,
+}
+
+/**
+ * Other side of ChannelTransport, plays the role of the scooter
+ */
+pub struct ChannelPeer {
+  writes: mpsc::UnboundedReceiver<ValueNotification>,
+  notifications: mpsc::UnboundedSender<ValueNotification>,
+}
+
+impl ChannelTransport {
+  /**
+   * Create connected transport and peer. Same as BleTransport, AVDTP, UPNP and RX start subscribed
+   */
+  pub fn pair() -> (ChannelTransport, ChannelPeer) {
+    let (writes_tx, writes_rx) = mpsc::unbounded_channel();
+    let (notifications_tx, notifications_rx) = mpsc::unbounded_channel();
+
+    let subscribed = [Registers::AVDTP, Registers::UPNP, Registers::RX]
+      .iter()
+      .map(|reg| reg.to_uuid())
+      .collect();
+
+    let transport = ChannelTransport {
+      writes: writes_tx,
+      notifications: notifications_rx,
+      subscribed
+    };
+
+    let peer = ChannelPeer {
+      writes: writes_rx,
+      notifications: notifications_tx
+    };
+
+    (transport, peer)
+  }
+}
+
+impl Transport for ChannelTransport {
+  async fn write(&mut self, reg: &Registers, data: &[u8]) -> Result<()> {
+    self.writes.send(ValueNotification { uuid: reg.to_uuid(), value: data.to_vec() })
+      .map_err(|_| M365Error::Disconnected(format!("channel peer is gone, could not write to {:?}", reg)))
+  }
+
+  async fn next(&mut self) -> Option<ValueNotification> {
+    while let Some(notification) = self.notifications.recv().await {
+      if self.subscribed.contains(&notification.uuid) {
+        return Some(notification)
+      }
+
+      tracing::debug!("Dropping notification for unsubscribed characteristic: {}", notification.uuid);
+    }
+
+    None
+  }
+
+  async fn subscribe(&mut self, reg: &Registers) -> Result<()> {
+    self.subscribed.insert(reg.to_uuid());
+    Ok(())
+  }
+
+  async fn unsubscribe(&mut self, reg: &Registers) -> Result<()> {
+    self.subscribed.remove(&reg.to_uuid());
+    Ok(())
+  }
+}
+
+impl ChannelPeer {
+  /**
+   * Send notification from register, as scooter would do
+   */
+  pub fn notify(&self, reg: &Registers, data: &[u8]) -> Result<()> {
+    self.notifications.send(ValueNotification { uuid: reg.to_uuid(), value: data.to_vec() })
+      .map_err(|_| M365Error::Disconnected(format!("channel transport is gone, could not notify {:?}", reg)))
+  }
+
+  /**
+   * Wait for next write done by MiProtocol. Notification uuid is the register that was written
+   */
+  pub async fn recv(&mut self) -> Option<ValueNotification> {
+    self.writes.recv().await
+  }
+}
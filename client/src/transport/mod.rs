@@ -0,0 +1,43 @@
+mod ble;
+mod channel;
+mod record;
+mod replay;
+
+use crate::consts::Registers;
+
+use std::future::Future;
+use crate::error::Result;
+use btleplug::api::ValueNotification;
+
+pub use ble::BleTransport;
+pub use channel::{ChannelTransport, ChannelPeer};
+pub use record::{load_recording, read_recording, RecordedFrame, Recorder, RecordingTransport, TrafficDirection};
+pub use replay::{recorded_rand_key, ReplayTransport};
+pub(crate) use record::hex_bytes;
+
+/**
+ * Raw link between MiProtocol and the scooter. It only knows how to write bytes into a register,
+ * hand back notifications and toggle notifications for a register. Everything else (mi parcels,
+ * uart encryption...) is built on top of it by MiProtocol and MiSession.
+ */
+pub trait Transport: Send {
+  /**
+   * Write raw bytes into register, without waiting for response
+   */
+  fn write(&mut self, reg: &Registers, data: &[u8]) -> impl Future<Output = Result<()>> + Send;
+
+  /**
+   * Next notification sent by scooter. None means that the notification stream is closed
+   */
+  fn next(&mut self) -> impl Future<Output = Option<ValueNotification>> + Send;
+
+  /**
+   * Enable notifications for register
+   */
+  fn subscribe(&mut self, reg: &Registers) -> impl Future<Output = Result<()>> + Send;
+
+  /**
+   * Disable notifications for register
+   */
+  fn unsubscribe(&mut self, reg: &Registers) -> impl Future<Output = Result<()>> + Send;
+}
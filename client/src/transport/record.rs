@@ -0,0 +1,201 @@
+use super::Transport;
+use crate::consts::Registers;
+
+use uuid::Uuid;
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use btleplug::api::ValueNotification;
+use crate::error::{M365Error, Result};
+
+/**
+ * Who sent recorded bytes
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TrafficDirection {
+  /**
+   * Written by MiProtocol into scooter register
+   */
+  Write,
+  /**
+   * Notified by scooter
+   */
+  Notification,
+}
+
+/**
+ * One write or notification, stored as single json line:
+ * {"at_ms":12,"direction":"notification","uuid":"00000010-0000-1000-8000-00805f9b34fb","data":"00000101"}
+ */
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedFrame {
+  /**
+   * Milliseconds since recorder was created
+   */
+  pub at_ms: u64,
+  pub direction: TrafficDirection,
+  pub uuid: Uuid,
+  #[serde(with = "hex_bytes")]
+  pub data: Vec<u8>,
+}
+
+/**
+ * Appends raw traffic to file (or any writer) as json lines. Clones write into the same file, so one
+ * recorder can follow scooter through reconnects.
+ */
+#[derive(Clone)]
+pub struct Recorder {
+  sink: Arc<Mutex<Box<dyn Write + Send>>>,
+  started: Instant,
+}
+
+impl Recorder {
+  pub fn new(sink: impl Write + Send + 'static) -> Self {
+    Self { sink: Arc::new(Mutex::new(Box::new(sink))), started: Instant::now() }
+  }
+
+  /**
+   * Append to file at path, file is created when missing
+   */
+  pub fn create(path: impl AsRef<Path>) -> Result<Self> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    Ok(Self::new(file))
+  }
+
+  pub fn record(&self, direction: TrafficDirection, uuid: Uuid, data: &[u8]) {
+    let frame = RecordedFrame {
+      at_ms: self.started.elapsed().as_millis() as u64,
+      direction,
+      uuid,
+      data: data.to_vec()
+    };
+
+    // Recording is only a debugging aid, broken file must not break the link
+    let line = match serde_json::to_string(&frame) {
+      Ok(line) => line,
+      Err(e) => {
+        tracing::warn!("Could not encode recorded frame: {}", e);
+        return
+      }
+    };
+
+    let mut sink = self.sink.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    if let Err(e) = writeln!(sink, "{}", line).and_then(|_| sink.flush()) {
+      tracing::warn!("Could not record frame: {}", e);
+    }
+  }
+}
+
+/**
+ * Load frames written by Recorder
+ */
+pub fn load_recording(path: impl AsRef<Path>) -> Result<Vec<RecordedFrame>> {
+  read_recording(BufReader::new(File::open(path)?))
+}
+
+pub fn read_recording(reader: impl BufRead) -> Result<Vec<RecordedFrame>> {
+  let mut frames = Vec::new();
+
+  for line in reader.lines() {
+    let line = line?;
+    if line.trim().is_empty() {
+      continue;
+    }
+
+    let frame = serde_json::from_str(&line)
+      .map_err(|e| M365Error::parse("recorded frame", format!("{}: {}", e, line)))?;
+    frames.push(frame);
+  }
+
+  Ok(frames)
+}
+
+/**
+ * Transport which records everything that goes through wrapped transport. Use it to capture what
+ * MiProtocol actually saw, then feed the file back with ReplayTransport.
+ *
+ * let transport = RecordingTransport::new(BleTransport::new(&device).await?, Recorder::create("scooter.jsonl")?);
+ * let protocol = MiProtocol::with_transport(transport);
+ */
+pub struct RecordingTransport<T: Transport> {
+  inner: T,
+  recorder: Option<Recorder>,
+}
+
+impl<T: Transport> RecordingTransport<T> {
+  pub fn new(inner: T, recorder: Recorder) -> Self {
+    Self { inner, recorder: Some(recorder) }
+  }
+
+  /**
+   * Record only when recorder is given, otherwise it passes everything as is
+   */
+  pub fn optional(inner: T, recorder: Option<Recorder>) -> Self {
+    Self { inner, recorder }
+  }
+
+  pub fn into_inner(self) -> T {
+    self.inner
+  }
+}
+
+impl<T: Transport> Transport for RecordingTransport<T> {
+  async fn write(&mut self, reg: &Registers, data: &[u8]) -> Result<()> {
+    if let Some(recorder) = &self.recorder {
+      recorder.record(TrafficDirection::Write, reg.to_uuid(), data);
+    }
+
+    self.inner.write(reg, data).await
+  }
+
+  async fn next(&mut self) -> Option<ValueNotification> {
+    let notification = self.inner.next().await?;
+
+    if let Some(recorder) = &self.recorder {
+      recorder.record(TrafficDirection::Notification, notification.uuid, &notification.value);
+    }
+
+    Some(notification)
+  }
+
+  async fn subscribe(&mut self, reg: &Registers) -> Result<()> {
+    self.inner.subscribe(reg).await
+  }
+
+  async fn unsubscribe(&mut self, reg: &Registers) -> Result<()> {
+    self.inner.unsubscribe(reg).await
+  }
+}
+
+/**
+ * Bytes as lowercase hex string, easier to compare with protocol docs than json arrays
+ */
+pub(crate) mod hex_bytes {
+  use serde::{de, Deserialize, Deserializer, Serializer};
+
+  pub fn serialize<S: Serializer>(data: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&encode(data))
+  }
+
+  pub fn encode(data: &[u8]) -> String {
+    data.iter().map(|byte| format!("{:02x}", byte)).collect()
+  }
+
+  pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+    let hex = String::deserialize(deserializer)?;
+    if hex.len() % 2 != 0 {
+      return Err(de::Error::custom("hex string with odd length"))
+    }
+
+    (0..hex.len()).step_by(2)
+      .map(|i| {
+        let byte = hex.get(i..i + 2).ok_or_else(|| de::Error::custom("hex string is not ascii"))?;
+        u8::from_str_radix(byte, 16).map_err(de::Error::custom)
+      })
+      .collect()
+  }
+}
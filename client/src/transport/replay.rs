@@ -0,0 +1,100 @@
+use super::Transport;
+use super::record::{RecordedFrame, TrafficDirection};
+use crate::consts::{MiCommands, Registers};
+use crate::mi_crypto::RandKey;
+
+use std::collections::VecDeque;
+use btleplug::api::ValueNotification;
+use pretty_hex::*;
+use crate::error::Result;
+
+/**
+ * Plays recorded notifications back to MiProtocol, so a bug seen on the field can be reproduced in
+ * test without the scooter. Notifications are returned in recorded order and as fast as they are read.
+ * Writes are matched against recorded ones only for logging: encrypted frames never repeat byte by byte.
+ * When recording ends the notification stream is closed, like after disconnect.
+ *
+ * Encrypted session keys depend on random key sent during login, replay the login with the recorded one:
+ *
+ * let frames = load_recording("field-bug.jsonl")?;
+ * let rand_key = recorded_rand_key(&frames).unwrap();
+ * let protocol = MiProtocol::with_transport(ReplayTransport::new(frames));
+ * let session = LoginRequest::with_protocol(protocol, &token).with_rand_key(rand_key).start().await?;
+ */
+pub struct ReplayTransport {
+  frames: VecDeque<RecordedFrame>,
+}
+
+impl ReplayTransport {
+  pub fn new(frames: Vec<RecordedFrame>) -> Self {
+    Self { frames: frames.into() }
+  }
+
+  /**
+   * Recorded frames not replayed yet
+   */
+  pub fn remaining(&self) -> usize {
+    self.frames.len()
+  }
+}
+
+impl Transport for ReplayTransport {
+  async fn write(&mut self, reg: &Registers, data: &[u8]) -> Result<()> {
+    let uuid = reg.to_uuid();
+
+    match self.frames.front() {
+      Some(frame) if frame.direction == TrafficDirection::Write => {
+        if frame.uuid != uuid || frame.data != data {
+          tracing::debug!("Write to {:?} differs from recorded one: {:?}", reg, data.hex_dump());
+        }
+        self.frames.pop_front();
+      },
+      _ => tracing::warn!("Write to {:?} was not recorded at this point: {:?}", reg, data.hex_dump())
+    }
+
+    Ok(())
+  }
+
+  async fn next(&mut self) -> Option<ValueNotification> {
+    while let Some(frame) = self.frames.pop_front() {
+      match frame.direction {
+        TrafficDirection::Notification => {
+          return Some(ValueNotification { uuid: frame.uuid, value: frame.data })
+        },
+        TrafficDirection::Write => {
+          tracing::warn!("Skipping recorded write which was not made: {:?}", frame.data.hex_dump());
+        }
+      }
+    }
+
+    None
+  }
+
+  async fn subscribe(&mut self, _reg: &Registers) -> Result<()> {
+    Ok(())
+  }
+
+  async fn unsubscribe(&mut self, _reg: &Registers) -> Result<()> {
+    Ok(())
+  }
+}
+
+/**
+ * Random key written to AVDTP as mi parcel after CMD_SEND_KEY, at the start of recorded login
+ */
+pub fn recorded_rand_key(frames: &[RecordedFrame]) -> Option<RandKey> {
+  let avdtp = Registers::AVDTP.to_uuid();
+  let mut writes = frames.iter()
+    .filter(|frame| frame.direction == TrafficDirection::Write && frame.uuid == avdtp)
+    .skip_while(|frame| frame.data != MiCommands::CMD_SEND_KEY.to_bytes())
+    .skip(1);
+
+  // Mi parcel chunks start with 2 bytes of chunk index
+  let mut key = Vec::new();
+  while key.len() < 16 {
+    let chunk = writes.next()?;
+    key.extend_from_slice(chunk.data.get(2..)?);
+  }
+
+  key.get(..16)?.try_into().ok()
+}
@@ -0,0 +1,61 @@
+use btleplug::api::BDAddr;
+use serde::Deserialize;
+
+use m365::adapter::{AdapterInfo, AdapterSelector};
+use m365::ScannerError;
+
+fn adapter(id: &str, name: &str, last_byte: u8) -> AdapterInfo {
+  AdapterInfo {
+    id: id.to_owned(),
+    address: BDAddr::from([0xB8, 0x27, 0xEB, 0x00, 0x00, last_byte]),
+    name: name.to_owned(),
+    powered: true,
+  }
+}
+
+fn adapters() -> Vec<AdapterInfo> {
+  vec![adapter("hci0", "raspberrypi", 0x01), adapter("hci1", "long-range", 0x02)]
+}
+
+#[test]
+fn it_selects_first_adapter_by_default() {
+  let adapters = adapters();
+
+  assert_eq!(AdapterSelector::default().select(&adapters).unwrap().id, "hci0");
+  assert_eq!(AdapterSelector::default().select(&[]), None);
+}
+
+#[test]
+fn it_selects_adapter_by_index_interface_or_alias() {
+  let adapters = adapters();
+
+  assert_eq!(AdapterSelector::Index(1).select(&adapters).unwrap().id, "hci1");
+  assert_eq!(AdapterSelector::Name("hci1".to_owned()).select(&adapters).unwrap().id, "hci1");
+  assert_eq!(AdapterSelector::Name("long-range".to_owned()).select(&adapters).unwrap().id, "hci1");
+  assert_eq!(AdapterSelector::Index(2).select(&adapters), None);
+}
+
+#[test]
+fn it_reads_adapter_from_config() {
+  #[derive(Deserialize)]
+  struct Bluetooth {
+    #[serde(default)]
+    adapter: AdapterSelector,
+  }
+
+  let parse = |config: &str| toml::from_str::<Bluetooth>(config).unwrap().adapter;
+
+  assert_eq!(parse(""), AdapterSelector::First);
+  assert_eq!(parse("adapter = 1"), AdapterSelector::Index(1));
+  assert_eq!(parse("adapter = \"hci1\""), AdapterSelector::Name("hci1".to_owned()));
+}
+
+#[test]
+fn it_lists_available_adapters_when_requested_is_missing() {
+  let error = ScannerError::AdapterNotFound { requested: AdapterSelector::Index(2), available: adapters() };
+
+  assert_eq!(
+    error.to_string(),
+    "Could not find bluetooth adapter hci2, available adapters: hci0 B8:27:EB:00:00:01 \"raspberrypi\" (on), hci1 B8:27:EB:00:00:02 \"long-range\" (on)"
+  );
+}
@@ -0,0 +1,237 @@
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+use m365::LoginRequest;
+use m365::btsnoop::{read_btsnoop, AttExtractor, DecodedFrame, SessionDecoder};
+use m365::consts::Registers;
+use m365::emulator::{ScooterEmulator, ScooterState};
+use m365::mi_crypto::encrypt_uart;
+use m365::protocol::MiProtocol;
+use m365::transport::{read_recording, ChannelTransport, RecordedFrame, Recorder, RecordingTransport, TrafficDirection};
+
+const TOKEN : [u8; 12] = [0x2a; 12];
+const CONNECTION : u16 = 0x0040;
+
+#[derive(Clone, Default)]
+struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Buffer {
+  fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+    self.0.lock().unwrap().write(data)
+  }
+
+  fn flush(&mut self) -> std::io::Result<()> {
+    Ok(())
+  }
+}
+
+/**
+ * Traffic of login and one motor info poll, as seen by the app
+ */
+async fn record_session() -> Vec<RecordedFrame> {
+  let buffer = Buffer::default();
+  let (transport, peer) = ChannelTransport::pair();
+  let emulator = tokio::spawn(ScooterEmulator::new(peer, ScooterState::default()).with_token(&TOKEN).run());
+
+  let transport = RecordingTransport::new(transport, Recorder::new(buffer.clone()));
+  let mut session = LoginRequest::with_protocol(MiProtocol::with_transport(transport), &TOKEN).start().await.unwrap();
+  session.motor_info().await.unwrap();
+
+  drop(session);
+  emulator.await.unwrap().unwrap();
+
+  let recording = buffer.0.lock().unwrap().clone();
+  read_recording(recording.as_slice()).unwrap()
+}
+
+fn handle(register: Registers) -> u16 {
+  match register {
+    Registers::UPNP => 0x0011,
+    Registers::AVDTP => 0x0014,
+    Registers::TX => 0x001b,
+    Registers::RX => 0x001d,
+    _ => unreachable!()
+  }
+}
+
+/**
+ * Android style btsnoop log (H4 datalink) with service discovery followed by recorded traffic
+ */
+struct BtsnoopWriter {
+  bytes: Vec<u8>,
+  timestamp_us: i64,
+}
+
+impl BtsnoopWriter {
+  fn new() -> Self {
+    let mut bytes = b"btsnoop\0".to_vec();
+    bytes.extend_from_slice(&1u32.to_be_bytes());
+    bytes.extend_from_slice(&1002u32.to_be_bytes());
+    Self { bytes, timestamp_us: 63_800_000_000_000_000 }
+  }
+
+  fn packet(&mut self, received: bool, packet: &[u8]) {
+    self.bytes.extend_from_slice(&(packet.len() as u32).to_be_bytes());
+    self.bytes.extend_from_slice(&(packet.len() as u32).to_be_bytes());
+    self.bytes.extend_from_slice(&(received as u32).to_be_bytes());
+    self.bytes.extend_from_slice(&0u32.to_be_bytes());
+    self.bytes.extend_from_slice(&self.timestamp_us.to_be_bytes());
+    self.bytes.extend_from_slice(packet);
+    self.timestamp_us += 1500;
+  }
+
+  /**
+   * ATT pdu in L2CAP frame, sent in ACL fragments of given size
+   */
+  fn att(&mut self, received: bool, pdu: &[u8], fragment_size: usize) {
+    let mut l2cap = (pdu.len() as u16).to_le_bytes().to_vec();
+    l2cap.extend_from_slice(&4u16.to_le_bytes());
+    l2cap.extend_from_slice(pdu);
+
+    for (index, fragment) in l2cap.chunks(fragment_size).enumerate() {
+      let flags : u16 = if index == 0 { 0x2000 } else { 0x1000 };
+      let mut acl = vec![0x02];
+      acl.extend_from_slice(&(CONNECTION | flags).to_le_bytes());
+      acl.extend_from_slice(&(fragment.len() as u16).to_le_bytes());
+      acl.extend_from_slice(fragment);
+      self.packet(received, &acl);
+    }
+  }
+
+  fn discover(&mut self, registers: &[Registers]) {
+    self.att(false, &[0x08, 0x01, 0x00, 0xff, 0xff, 0x03, 0x28], 27);
+
+    let short = registers[0] == Registers::UPNP;
+    let mut response = vec![0x09, if short { 7 } else { 21 }];
+    for register in registers {
+      let handle = handle(*register);
+      response.extend_from_slice(&(handle - 1).to_le_bytes());
+      response.push(0x1a);
+      response.extend_from_slice(&handle.to_le_bytes());
+      let mut uuid = register.to_uuid().as_bytes().to_vec();
+      uuid.reverse();
+      response.extend_from_slice(if short { &uuid[12..14] } else { &uuid });
+    }
+    self.att(true, &response, 27);
+  }
+
+  fn frame(&mut self, frame: &RecordedFrame) {
+    let register = Registers::from_uuid(&frame.uuid).unwrap();
+    let (received, opcode) = match frame.direction {
+      TrafficDirection::Write => (false, 0x52),
+      TrafficDirection::Notification => (true, 0x1b),
+    };
+
+    let mut pdu = vec![opcode];
+    pdu.extend_from_slice(&handle(register).to_le_bytes());
+    pdu.extend_from_slice(&frame.data);
+    self.att(received, &pdu, 16); // Small fragments, so longer notifications need reassembly
+  }
+}
+
+async fn btsnoop_log() -> (Vec<u8>, Vec<RecordedFrame>) {
+  let frames = record_session().await;
+
+  let mut writer = BtsnoopWriter::new();
+  writer.packet(true, &[0x04, 0x0e, 0x04, 0x01, 0x03, 0x0c, 0x00]); // HCI event, not ACL
+  writer.discover(&[Registers::UPNP, Registers::AVDTP]);
+  writer.discover(&[Registers::TX, Registers::RX]);
+  for frame in &frames {
+    writer.frame(frame);
+  }
+
+  (writer.bytes, frames)
+}
+
+fn decode(frames: &[RecordedFrame], token: Option<[u8; 12]>) -> (Vec<DecodedFrame>, SessionDecoder) {
+  let mut decoder = SessionDecoder::new(token);
+  let decoded = frames.iter().filter_map(|frame| decoder.push(frame)).collect();
+  (decoded, decoder)
+}
+
+#[tokio::test]
+async fn it_extracts_mi_traffic_from_btsnoop_log() {
+  let (log, recorded) = btsnoop_log().await;
+
+  let records = read_btsnoop(&log).unwrap();
+  let extracted = AttExtractor::new().extract(&records);
+
+  assert_eq!(extracted.len(), recorded.len());
+  for (extracted, recorded) in extracted.iter().zip(&recorded) {
+    assert_eq!(extracted.direction, recorded.direction);
+    assert_eq!(extracted.uuid, recorded.uuid);
+    assert_eq!(extracted.data, recorded.data);
+  }
+}
+
+#[tokio::test]
+async fn it_decrypts_uart_frames_with_token() {
+  let (log, _) = btsnoop_log().await;
+  let frames = AttExtractor::new().extract(&read_btsnoop(&log).unwrap());
+
+  let (decoded, decoder) = decode(&frames, Some(TOKEN));
+  assert!(decoder.keys().is_some());
+
+  let messages : Vec<_> = decoded.iter().filter_map(|frame| frame.message.as_ref()).collect();
+  assert_eq!((messages[0].direction, messages[0].read_write, messages[0].attribute), (0x20, 0x01, 0xb0));
+  assert_eq!((messages[1].direction, messages[1].attribute), (0x23, 0xb0));
+  assert_eq!(messages[1].data.len(), 32);
+
+  let printed : Vec<String> = decoded.iter().map(|frame| frame.to_string()).collect();
+  assert!(printed.iter().any(|line| line.contains("token matches, session keys recovered")));
+  assert!(printed.iter().any(|line| line.contains("MasterToMotor Read MotorInfo (0xb0)")));
+}
+
+#[tokio::test]
+async fn it_keeps_uart_encrypted_without_token() {
+  let (log, _) = btsnoop_log().await;
+  let frames = AttExtractor::new().extract(&read_btsnoop(&log).unwrap());
+
+  let (decoded, decoder) = decode(&frames, None);
+
+  assert!(decoder.keys().is_none());
+  assert!(decoded.iter().all(|frame| frame.message.is_none()));
+  assert!(decoded.iter().any(|frame| frame.note.as_deref().is_some_and(|note| note.ends_with("encrypted, keys unknown"))));
+}
+
+#[tokio::test]
+async fn it_uses_given_handles_when_discovery_is_missing() {
+  let recorded = record_session().await;
+  let mut writer = BtsnoopWriter::new();
+  for frame in &recorded {
+    writer.frame(frame);
+  }
+  let records = read_btsnoop(&writer.bytes).unwrap();
+
+  assert!(AttExtractor::new().extract(&records).is_empty());
+
+  let extractor = [Registers::UPNP, Registers::AVDTP, Registers::TX, Registers::RX].into_iter()
+    .fold(AttExtractor::new(), |extractor, register| extractor.with_handle(handle(register), register));
+  assert_eq!(extractor.extract(&records).len(), recorded.len());
+}
+
+#[tokio::test]
+async fn it_decrypts_after_counter_wraps_around() {
+  let recorded = record_session().await;
+  let (_, mut decoder) = decode(&recorded, Some(TOKEN));
+  let key = decoder.keys().unwrap().app.clone();
+
+  // Steps below half of 16 bit range, last frame carries counter 0x0001 of 0x10001
+  let messages : Vec<_> = [0x4000, 0x8000, 0xc000, 0x10001].into_iter()
+    .filter_map(|it| decoder.push(&RecordedFrame {
+      at_ms: 0,
+      direction: TrafficDirection::Write,
+      uuid: Registers::TX.to_uuid(),
+      data: encrypt_uart(&key, &[0x03, 0x20, 0x01, 0xb0, 0x20], it, None),
+    }))
+    .map(|frame| frame.message.expect("frame should decrypt"))
+    .collect();
+
+  assert_eq!(messages.len(), 4);
+  assert!(messages.iter().all(|message| (message.direction, message.attribute) == (0x20, 0xb0)));
+}
+
+#[test]
+fn it_rejects_other_files() {
+  assert!(read_btsnoop(b"not a btsnoop log").is_err());
+}
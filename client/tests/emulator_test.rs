@@ -0,0 +1,358 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use m365::{Controller, Direction, EscError, FirmwareVersion, LoginError, LoginRequest, RegisterSnapshot, RegistrationRequest, MiSession, TailLight, UartMode};
+use m365::emulator::{ScooterEmulator, ScooterState};
+use m365::error::M365Error;
+use m365::protocol::MiProtocol;
+use m365::transport::ChannelTransport;
+
+const TOKEN : [u8; 12] = [0x2a; 12];
+
+/**
+ * Start emulator already registered with TOKEN and login into it
+ */
+async fn logged_in(state: ScooterState) -> (MiSession<ChannelTransport>, Arc<Mutex<ScooterState>>) {
+  let (transport, peer) = ChannelTransport::pair();
+  let emulator = ScooterEmulator::new(peer, state).with_token(&TOKEN);
+  let state = emulator.state();
+  tokio::spawn(emulator.run());
+
+  let request = LoginRequest::with_protocol(MiProtocol::with_transport(transport), &TOKEN);
+  let session = request.start().await.unwrap();
+
+  (session, state)
+}
+
+#[tokio::test]
+async fn it_registers_with_emulator() {
+  let (transport, peer) = ChannelTransport::pair();
+  let emulator = tokio::spawn(ScooterEmulator::new(peer, ScooterState::default()).run());
+
+  let mut request = RegistrationRequest::with_protocol(MiProtocol::with_transport(transport));
+  let token = request.start().await.unwrap();
+  drop(request);
+
+  let emulator = emulator.await.unwrap().unwrap();
+  assert_eq!(emulator.token(), Some(token));
+}
+
+#[tokio::test]
+async fn it_rejects_login_with_wrong_token() {
+  let (transport, peer) = ChannelTransport::pair();
+  tokio::spawn(ScooterEmulator::new(peer, ScooterState::default()).with_token(&TOKEN).run());
+
+  let request = LoginRequest::with_protocol(MiProtocol::with_transport(transport), &[0x01; 12]);
+
+  assert!(request.start().await.is_err());
+}
+
+#[tokio::test]
+async fn it_reads_motor_info() {
+  let (mut session, _) = logged_in(ScooterState::default()).await;
+
+  let motor_info = session.motor_info().await.unwrap();
+
+  assert_eq!(motor_info.battery_percent, 80);
+  assert_eq!(motor_info.speed_kmh, 0.0);
+  assert_eq!(motor_info.total_distance_m, 1306083);
+  assert_eq!(motor_info.frame_temperature, 25.0);
+}
+
+#[tokio::test]
+async fn it_reads_general_info_and_serial() {
+  let (mut session, _) = logged_in(ScooterState::default()).await;
+
+  let general_info = serde_json::to_value(session.general_info().await.unwrap()).unwrap();
+  assert_eq!(general_info["serial"], "26354/00467353");
+  assert_eq!(general_info["pin"], "000000");
+
+  let serial = session.serial_number().await.unwrap();
+  assert_eq!(serial, "26354/00467353");
+}
+
+#[tokio::test]
+async fn it_reads_battery() {
+  let (mut session, _) = logged_in(ScooterState::default()).await;
+
+  let battery_info = session.battery_info().await.unwrap();
+  assert_eq!(battery_info.capacity, 6240);
+  assert_eq!(battery_info.percent, 80);
+  assert_eq!(battery_info.temperature_1, 45);
+
+  let cells = session.battery_cell_voltages().await.unwrap();
+  assert_eq!(cells.len(), 10);
+  assert!(cells.iter().all(|cell| *cell > 0.0));
+}
+
+#[tokio::test]
+async fn it_reads_bms_info() {
+  let (mut session, _) = logged_in(ScooterState::default()).await;
+
+  let bms_info = session.bms_info().await.unwrap();
+
+  assert_eq!(bms_info.serial, "3LABATTDECAMIL");
+  assert_eq!(bms_info.design_capacity, 7800);
+  assert_eq!(bms_info.cycles, 12);
+  assert_eq!(bms_info.charges, 34);
+  assert_eq!(bms_info.recharged, 8866);
+  assert_eq!(bms_info.manufacture_date.as_deref(), Some("2021-05-17"));
+}
+
+#[tokio::test]
+async fn it_skips_invalid_bms_manufacture_date() {
+  let mut state = ScooterState::default();
+  state.manufacture_date = 0;
+  let (mut session, _) = logged_in(state).await;
+
+  assert_eq!(session.bms_info().await.unwrap().manufacture_date, None);
+}
+
+#[tokio::test]
+async fn it_reads_firmware_versions() {
+  let (mut session, _) = logged_in(ScooterState::default()).await;
+
+  let versions = session.firmware_versions().await.unwrap();
+
+  assert_eq!(versions.esc, FirmwareVersion::new(1, 3, 4));
+  assert_eq!(versions.bms, FirmwareVersion::new(1, 1, 5));
+  assert_eq!(versions.ble, FirmwareVersion::new(0, 7, 1));
+}
+
+#[tokio::test]
+async fn it_reads_esc_faults() {
+  let mut state = ScooterState::default();
+  state.error_code = 14;
+  let (mut session, _) = logged_in(state).await;
+
+  let motor_info = session.motor_info().await.unwrap();
+
+  assert_eq!(motor_info.status.error, EscError::Throttle);
+  assert!(!motor_info.status.warning.is_fault());
+}
+
+#[tokio::test]
+async fn it_follows_simulated_ride() {
+  let (mut session, state) = logged_in(ScooterState::default()).await;
+
+  {
+    let mut state = state.lock().unwrap();
+    state.speed_kmh = 18.0;
+    state.ride(Duration::from_secs(600));
+  }
+
+  let motor_info = session.motor_info().await.unwrap();
+  assert_eq!(motor_info.speed_kmh, 18.0);
+  assert_eq!(motor_info.trip_distance_m, 3000);
+  assert_eq!(motor_info.total_distance_m, 1309083);
+  assert_eq!(motor_info.uptime, Duration::from_secs(600));
+
+  let battery_info = session.battery_info().await.unwrap();
+  assert!(battery_info.capacity < 6240);
+  assert!(battery_info.current > 0.0);
+}
+
+#[tokio::test]
+async fn it_reads_trip_info() {
+  let (mut session, state) = logged_in(ScooterState::default()).await;
+
+  {
+    let mut state = state.lock().unwrap();
+    state.speed_kmh = 18.0;
+    state.ride(Duration::from_secs(600));
+  }
+
+  let trip = session.trip_info().await.unwrap();
+  assert_eq!(trip.time, Duration::from_secs(600));
+  assert_eq!(trip.distance_m, 3000);
+  assert_eq!(trip.average_speed_kmh(), 18.0);
+}
+
+#[tokio::test]
+async fn it_writes_settings() {
+  let (mut session, state) = logged_in(ScooterState::default()).await;
+
+  session.set_tail_light(TailLight::Always).await.unwrap();
+  session.set_cruise(true).await.unwrap();
+
+  // Writes are not acknowledged by scooter, read something to be sure they were processed
+  session.motor_info().await.unwrap();
+
+  let state = state.lock().unwrap();
+  assert_eq!(state.tail_light, 2);
+  assert!(state.cruise);
+}
+
+#[tokio::test]
+async fn it_reads_undocumented_registers() {
+  let mut state = ScooterState::default();
+  state.esc_version = 0x0156;
+  state.bms_version = 0x0133;
+  state.trip_time = Duration::from_secs(754);
+  state.trip_distance_m = 1200.0;
+  let (mut session, _) = logged_in(state).await;
+
+  let mut version = session.read_register(Direction::MasterToMotor, 0x1a, 2).await.unwrap();
+  assert_eq!(version.pop_u16().unwrap(), 0x0156);
+
+  let mut version = session.read_register(Direction::MasterToMotor, 0x67, 2).await.unwrap();
+  assert_eq!(version.pop_u16().unwrap(), 0x0133);
+
+  let mut trip = session.read_register(Direction::MasterToMotor, 0x3a, 4).await.unwrap();
+  assert_eq!(trip.pop_u16().unwrap(), 754);
+  assert_eq!(trip.pop_u16().unwrap(), 1200);
+}
+
+#[tokio::test]
+async fn it_reads_same_register_length_with_any_firmware() {
+  let (mut encrypted, _) = logged_in(ScooterState::default()).await;
+
+  let (transport, peer) = ChannelTransport::pair();
+  tokio::spawn(ScooterEmulator::new(peer, ScooterState::default()).with_legacy_firmware().run());
+  let mut legacy = LoginRequest::with_protocol(MiProtocol::with_transport(transport), &TOKEN).start_auto().await.unwrap();
+
+  for len in [2, 4, 0x20] {
+    let encrypted = encrypted.read_register(Direction::MasterToMotor, 0x10, len).await.unwrap();
+    let legacy = legacy.read_register(Direction::MasterToMotor, 0x10, len).await.unwrap();
+    assert_eq!(encrypted.len(), len as usize);
+    assert_eq!(legacy.len(), len as usize);
+  }
+}
+
+#[tokio::test]
+async fn it_writes_registers_and_reads_them_back() {
+  let (mut session, state) = logged_in(ScooterState::default()).await;
+
+  let mut written = session.write_register(Direction::MasterToMotor, 0x7d, &[0x02, 0x00]).await.unwrap();
+  assert_eq!(written.pop_u16().unwrap(), 2);
+  assert_eq!(state.lock().unwrap().tail_light, 2);
+
+  let mut written = session.write_register(Direction::MasterToBattery, 0x90, &[0x34, 0x12]).await.unwrap();
+  assert_eq!(written.pop_u16().unwrap(), 0x1234);
+
+  assert!(session.write_register(Direction::MasterToMotor, 0x7d, &[0x00; 300]).await.is_err());
+}
+
+#[tokio::test]
+async fn it_takes_register_snapshot() {
+  let mut state = ScooterState::default();
+  state.esc_version = 0x0134;
+  state.trip_time = Duration::from_secs(635);
+  let (mut session, _) = logged_in(state).await;
+
+  let snapshot = session.register_snapshot().await.unwrap();
+  assert_eq!(snapshot.esc.len(), 256);
+  assert_eq!(snapshot.bms.len(), 256);
+
+  let version = snapshot.get(Controller::Esc, 0x1a).unwrap();
+  assert_eq!(version.raw, vec![0x34, 0x01]);
+  assert_eq!(version.name.as_deref(), Some("esc_version"));
+  assert_eq!(version.value.as_deref(), Some("1.3.4"));
+
+  let trip = snapshot.get(Controller::Esc, 0x3a).unwrap();
+  assert_eq!(trip.word(), 635);
+  assert_eq!(trip.value.as_deref(), Some("635 s"));
+
+  let unknown = snapshot.get(Controller::Bms, 0x99).unwrap();
+  assert_eq!(unknown.name, None);
+
+  let path = std::env::temp_dir().join(format!("m365-snapshot-{}.json", std::process::id()));
+  snapshot.save(&path).unwrap();
+  let loaded = RegisterSnapshot::load(&path).unwrap();
+  std::fs::remove_file(&path).unwrap();
+  assert_eq!(loaded, snapshot);
+}
+
+#[tokio::test]
+async fn it_detects_legacy_firmware() {
+  let (transport, peer) = ChannelTransport::pair();
+  tokio::spawn(ScooterEmulator::new(peer, ScooterState::default()).with_legacy_firmware().run());
+
+  let request = LoginRequest::with_protocol(MiProtocol::with_transport(transport), &TOKEN);
+  let mut session = request.start_auto().await.unwrap();
+  assert!(matches!(session.mode(), UartMode::Legacy));
+
+  let motor_info = session.motor_info().await.unwrap();
+  assert_eq!(motor_info.battery_percent, 80);
+  assert_eq!(motor_info.total_distance_m, 1306083);
+
+  let battery_info = session.battery_info().await.unwrap();
+  assert_eq!(battery_info.capacity, 6240);
+
+  let serial = session.serial_number().await.unwrap();
+  assert_eq!(serial, "26354/00467353");
+}
+
+#[tokio::test]
+async fn it_falls_back_to_login_for_encrypted_firmware() {
+  let (transport, peer) = ChannelTransport::pair();
+  tokio::spawn(ScooterEmulator::new(peer, ScooterState::default()).with_token(&TOKEN).run());
+
+  let request = LoginRequest::with_protocol(MiProtocol::with_transport(transport), &TOKEN);
+  let mut session = request.start_auto().await.unwrap();
+  assert!(matches!(session.mode(), UartMode::Encrypted(_)));
+
+  let motor_info = session.motor_info().await.unwrap();
+  assert_eq!(motor_info.battery_percent, 80);
+}
+
+#[tokio::test]
+async fn it_talks_to_ninebot_scooter() {
+  let (transport, peer) = ChannelTransport::pair();
+  let emulator = ScooterEmulator::new(peer, ScooterState::default()).with_ninebot_firmware();
+  let state = emulator.state();
+  tokio::spawn(emulator.run());
+
+  let mut session = MiSession::ninebot(MiProtocol::with_transport(transport));
+
+  let motor_info = session.motor_info().await.unwrap();
+  assert_eq!(motor_info.battery_percent, 80);
+  assert_eq!(motor_info.total_distance_m, 1306083);
+
+  let battery_info = session.battery_info().await.unwrap();
+  assert_eq!(battery_info.capacity, 6240);
+
+  session.set_cruise(true).await.unwrap();
+  session.distance_left().await.unwrap();
+  assert!(state.lock().unwrap().cruise);
+}
+
+#[tokio::test]
+async fn it_starts_ninebot_session_after_probe() {
+  let (transport, peer) = ChannelTransport::pair();
+  tokio::spawn(ScooterEmulator::new(peer, ScooterState::default()).with_ninebot_firmware().run());
+
+  let mut session = MiSession::start_ninebot(MiProtocol::with_transport(transport)).await.unwrap();
+
+  assert_eq!(session.motor_info().await.unwrap().battery_percent, 80);
+}
+
+#[tokio::test]
+async fn it_rejects_ninebot_that_does_not_answer_plain_frames() {
+  let (transport, peer) = ChannelTransport::pair();
+  // Scooter that only understands encrypted frames ignores plain 5AA5 probe
+  tokio::spawn(ScooterEmulator::new(peer, ScooterState::default()).with_token(&TOKEN).run());
+
+  let error = MiSession::start_ninebot(MiProtocol::with_transport(transport)).await.err().unwrap();
+
+  assert!(matches!(error, M365Error::Login(LoginError::NinebotEncrypted)));
+}
+
+#[tokio::test]
+async fn it_reads_replies_split_in_any_way() {
+  for size in [1, 7, 20, 64] {
+    let (transport, peer) = ChannelTransport::pair();
+    let emulator = ScooterEmulator::new(peer, ScooterState::default())
+      .with_token(&TOKEN)
+      .with_notification_size(size);
+    tokio::spawn(emulator.run());
+
+    let request = LoginRequest::with_protocol(MiProtocol::with_transport(transport), &TOKEN);
+    let mut session = request.start().await.unwrap();
+
+    assert_eq!(session.distance_left().await.unwrap(), 24.0);
+    assert_eq!(session.speed().await.unwrap(), 0.0);
+    assert_eq!(session.battery_percentage().await.unwrap(), 80.0);
+    assert_eq!(session.motor_info().await.unwrap().total_distance_m, 1306083);
+  }
+}
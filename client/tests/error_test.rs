@@ -0,0 +1,56 @@
+use std::time::Duration;
+
+use m365::error::M365Error;
+use m365::LoginError;
+use m365::mi_crypto::MiCryptoError;
+
+#[test]
+fn it_keeps_session_after_corrupted_frame() {
+  let error = M365Error::from(MiCryptoError::BadChecksum);
+
+  assert!(error.is_transient());
+  assert!(!error.needs_reconnect());
+}
+
+#[test]
+fn it_ignores_replayed_counter() {
+  let error = M365Error::from(MiCryptoError::ReplayedCounter { received: 3, last: 3 });
+
+  assert!(!error.needs_reconnect());
+  assert!(error.is_transient());
+}
+
+#[test]
+fn it_tells_timeout_from_disconnect() {
+  let timeout = M365Error::Timeout(Duration::from_secs(5));
+  let disconnect = M365Error::Disconnected("notification stream ended".to_string());
+
+  assert!(timeout.is_transient());
+  assert!(!timeout.needs_reconnect());
+  assert!(disconnect.needs_reconnect());
+  assert!(!disconnect.is_transient());
+}
+
+#[test]
+fn it_reconnects_after_failed_login() {
+  let error = M365Error::from(LoginError::InvalidDid);
+
+  assert!(error.needs_reconnect());
+  assert_eq!(error.to_string(), "Scooter sent invalid remote key");
+}
+
+#[test]
+fn it_reports_remote_info_of_wrong_length() {
+  let error = M365Error::from(LoginError::InvalidRemoteInfo { received: 16 });
+
+  assert!(error.needs_reconnect());
+  assert_eq!(error.to_string(), "Scooter sent remote info of 16 bytes, expected 32");
+}
+
+#[test]
+fn it_does_not_reconnect_on_gps_errors() {
+  let error = M365Error::Gps("Command output mismatch GPS!".to_string());
+
+  assert!(error.is_transient());
+  assert!(!error.needs_reconnect());
+}
@@ -0,0 +1,53 @@
+use m365::telemetry::{FaultKind, FaultTracker};
+use m365::{EscError, EscStatus, EscWarning};
+
+fn status(error: u16, warning: u16) -> EscStatus {
+  EscStatus { error: EscError::from(error), warning: EscWarning::from(warning), ..EscStatus::default() }
+}
+
+#[test]
+fn it_reports_only_fault_transitions() {
+  let mut tracker = FaultTracker::new();
+
+  assert!(tracker.update(&status(0, 0)).is_empty());
+
+  let events = tracker.update(&status(14, 0));
+  assert_eq!(events.len(), 1);
+  assert_eq!(events[0].kind, FaultKind::Error);
+  assert!(events[0].active);
+  assert_eq!(events[0].code, 14);
+  assert_eq!(events[0].description, EscError::Throttle.description());
+
+  assert!(tracker.update(&status(14, 0)).is_empty());
+
+  let events = tracker.update(&status(0, 0));
+  assert_eq!(events.len(), 1);
+  assert!(!events[0].active);
+  assert_eq!(events[0].code, 14);
+}
+
+#[test]
+fn it_clears_old_fault_when_code_changes() {
+  let mut tracker = FaultTracker::new();
+  tracker.update(&status(14, 0));
+
+  let events = tracker.update(&status(21, 3));
+  let summary : Vec<(FaultKind, bool, u16)> = events.iter().map(|event| (event.kind, event.active, event.code)).collect();
+
+  assert_eq!(summary, vec![
+    (FaultKind::Error, false, 14),
+    (FaultKind::Error, true, 21),
+    (FaultKind::Warning, true, 3),
+  ]);
+}
+
+#[test]
+fn it_reports_fault_present_when_tracking_starts() {
+  let mut tracker = FaultTracker::new();
+
+  let events = tracker.update(&status(0, 7)).into_iter().map(|event| event.with_scooter("depot-1")).collect::<Vec<_>>();
+
+  assert_eq!(events.len(), 1);
+  assert_eq!(events[0].kind, FaultKind::Warning);
+  assert_eq!(events[0].scooter.as_deref(), Some("depot-1"));
+}
@@ -0,0 +1,234 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use m365::{LoginRequest, MiSession, TailLight};
+use m365::command::Command;
+use m365::config::Config;
+use m365::emulator::{ScooterEmulator, ScooterState};
+use m365::error::Result;
+use m365::fleet::{monitor, AdapterShare};
+use m365::protocol::MiProtocol;
+use m365::supervisor::{Backoff, Connector, Supervisor};
+use m365::transport::ChannelTransport;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+const TOKEN : [u8; 12] = [0x2a; 12];
+
+const CONFIG : &str = r#"
+[mqtt]
+broker = "tcp://localhost:1883"
+client = "martinete"
+topic = "vehicle/1/realtime"
+keep_alive = 20
+reconnect_min = 1
+reconnect_max = 30
+send_interval = 5
+
+[serial]
+serial_port = "/dev/ttyUSB2"
+baudrate = 115200
+"#;
+
+/**
+ * Emulated scooter sharing one adapter, counts links open at the same time
+ */
+struct SharedAdapterConnector {
+  connected: Arc<AtomicUsize>,
+  most_connected: Arc<AtomicUsize>,
+  logins: u32,
+  emulator: Option<JoinHandle<anyhow::Result<ScooterEmulator>>>,
+}
+
+impl SharedAdapterConnector {
+  fn new(connected: &Arc<AtomicUsize>, most_connected: &Arc<AtomicUsize>) -> Self {
+    Self { connected: connected.clone(), most_connected: most_connected.clone(), logins: 0, emulator: None }
+  }
+}
+
+impl Connector for SharedAdapterConnector {
+  type Transport = ChannelTransport;
+
+  async fn scan(&mut self) -> Result<()> {
+    Ok(())
+  }
+
+  async fn connect(&mut self) -> Result<()> {
+    let connected = self.connected.fetch_add(1, Ordering::SeqCst) + 1;
+    self.most_connected.fetch_max(connected, Ordering::SeqCst);
+    Ok(())
+  }
+
+  async fn login(&mut self) -> Result<MiSession<ChannelTransport>> {
+    self.logins += 1;
+    let (transport, peer) = ChannelTransport::pair();
+    self.emulator = Some(tokio::spawn(ScooterEmulator::new(peer, ScooterState::default()).with_token(&TOKEN).run()));
+
+    LoginRequest::with_protocol(MiProtocol::with_transport(transport), &TOKEN).start().await
+  }
+
+  async fn reattach(&mut self) -> Result<MiProtocol<ChannelTransport>> {
+    let (transport, peer) = ChannelTransport::pair();
+    let previous = self.emulator.take().unwrap();
+
+    self.emulator = Some(tokio::spawn(async move {
+      let emulator = previous.await.unwrap()?;
+      emulator.reconnect(peer).run().await
+    }));
+
+    Ok(MiProtocol::with_transport(transport))
+  }
+
+  async fn disconnect(&mut self) -> Result<()> {
+    self.connected.fetch_sub(1, Ordering::SeqCst);
+    Ok(())
+  }
+}
+
+fn fast_backoff() -> Backoff {
+  Backoff { initial: Duration::from_millis(1), max: Duration::from_millis(4), jitter: 0.0, ..Backoff::default() }
+}
+
+#[test]
+fn it_reads_single_scooter_as_fleet_of_one() {
+  let config = Config::parse(&format!("{}{}", CONFIG, r#"
+[scooter]
+mac = "D5:12:34:56:78:9A"
+token_file_path = ".mi-token"
+"#)).unwrap();
+
+  let fleet = config.fleet();
+  assert_eq!(fleet.len(), 1);
+  assert_eq!(fleet[0].name(), "D5:12:34:56:78:9A");
+  assert_eq!(fleet[0].topic(&config.mqtt), "vehicle/1/realtime");
+  assert_eq!(fleet[0].poll_interval(&config.mqtt), Duration::from_secs(5));
+  assert_eq!(fleet[0].command_topic(&config.mqtt), "vehicle/1/realtime/command/D5:12:34:56:78:9A");
+  assert!(!config.bluetooth.share().is_limited());
+}
+
+#[test]
+fn it_reads_fleet_with_own_topics_and_intervals() {
+  let config = Config::parse(&format!("{}{}", CONFIG, r#"
+[bluetooth]
+max_connections = 2
+
+[[scooters]]
+name = "depot-1"
+mac = "D5:12:34:56:78:9A"
+token_file_path = ".mi-token-1"
+
+[[scooters]]
+name = "depot-2"
+mac = "D5:12:34:56:78:9B"
+token_file_path = ".mi-token-2"
+model = "pro2"
+topic = "vehicle/2/realtime"
+poll_interval = 30
+"#)).unwrap();
+
+  let fleet = config.fleet();
+  assert_eq!(fleet.iter().map(|scooter| scooter.name()).collect::<Vec<_>>(), ["depot-1", "depot-2"]);
+  assert_eq!(fleet[0].topic(&config.mqtt), "vehicle/1/realtime");
+  assert_eq!(fleet[1].topic(&config.mqtt), "vehicle/2/realtime");
+  assert_eq!(fleet[1].poll_interval(&config.mqtt), Duration::from_secs(30));
+  assert_eq!(config.bluetooth.share_slot, 60);
+  assert!(config.bluetooth.share().is_limited());
+}
+
+#[test]
+fn it_refuses_config_without_scooters() {
+  assert!(Config::parse(CONFIG).is_err());
+}
+
+#[test]
+fn it_refuses_reconnect_delays_that_are_not_durations() {
+  for reconnect in ["initial_delay = -1.0", "max_delay = nan", "park_for = inf", "multiplier = 0.5", "jitter = 2.0"] {
+    let config = Config::parse(&format!("{}{}\n[reconnect]\n{}\n", CONFIG, r#"
+[scooter]
+mac = "D5:12:34:56:78:9A"
+token_file_path = ".mi-token"
+"#, reconnect));
+
+    assert!(config.is_err(), "{}", reconnect);
+  }
+}
+
+#[test]
+fn it_reads_remote_commands() {
+  assert!(matches!(Command::parse(br#"{"tail_light": "on_brake"}"#).unwrap(), Command::TailLight(TailLight::OnBrake)));
+  assert!(matches!(Command::parse(br#"{"cruise": true}"#).unwrap(), Command::Cruise(true)));
+  assert!(Command::parse(br#"{"tail_light": "unknown"}"#).is_err());
+  assert!(Command::parse(b"reboot").is_err());
+}
+
+#[tokio::test]
+async fn it_takes_turns_on_shared_adapter() {
+  let connected = Arc::new(AtomicUsize::new(0));
+  let most_connected = Arc::new(AtomicUsize::new(0));
+  let adapter = AdapterShare::new(1, Duration::from_millis(100));
+  let stop = CancellationToken::new();
+
+  let monitors : Vec<_> = (0..2).map(|_| {
+    let connector = SharedAdapterConnector::new(&connected, &most_connected);
+    let (adapter, stop) = (adapter.clone(), stop.clone());
+    let polls = Arc::new(AtomicUsize::new(0));
+
+    let task_polls = polls.clone();
+    let task = tokio::spawn(async move {
+      let mut supervisor = Supervisor::new(connector, fast_backoff());
+      let (current, _) = watch::channel(None);
+      monitor(&mut supervisor, &adapter, Duration::from_millis(10), &stop, &current, |session| {
+        let polls = task_polls.clone();
+        Box::pin(async move {
+          assert_eq!(session.motor_info().await?.battery_percent, 80);
+          polls.fetch_add(1, Ordering::SeqCst);
+          Ok(())
+        })
+      }).await;
+      supervisor
+    });
+
+    (task, polls)
+  }).collect();
+
+  tokio::time::sleep(Duration::from_millis(700)).await;
+  stop.cancel();
+
+  for (task, polls) in monitors {
+    let supervisor = task.await.unwrap();
+    assert!(polls.load(Ordering::SeqCst) > 0);
+    assert_eq!(supervisor.connector().logins, 1, "Session should be resumed on next turn");
+  }
+  assert_eq!(most_connected.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn it_shares_session_with_other_tasks_while_polling() {
+  let connected = Arc::new(AtomicUsize::new(0));
+  let most_connected = Arc::new(AtomicUsize::new(0));
+  let connector = SharedAdapterConnector::new(&connected, &most_connected);
+  let stop = CancellationToken::new();
+  let (current, mut shared) = watch::channel(None);
+
+  let task_stop = stop.clone();
+  let task = tokio::spawn(async move {
+    let mut supervisor = Supervisor::new(connector, fast_backoff());
+    monitor(&mut supervisor, &AdapterShare::unlimited(), Duration::from_millis(10), &task_stop, &current, |session| {
+      Box::pin(async move {
+        session.motor_info().await?;
+        Ok(())
+      })
+    }).await
+  });
+
+  let handle = shared.wait_for(|handle| handle.is_some()).await.unwrap().clone().unwrap();
+  handle.set_tail_light(TailLight::Always).await.unwrap();
+  assert!(matches!(handle.tail_light().await.unwrap(), TailLight::Always));
+  drop(handle);
+
+  stop.cancel();
+  assert!(task.await.unwrap().is_some(), "Session should be given back after remote command");
+  assert!(shared.borrow().is_none());
+}
@@ -0,0 +1,83 @@
+use hex_literal::hex;
+
+use m365::frame::{FrameDecoder, FrameKind};
+
+#[test]
+fn it_waits_for_whole_frame() {
+  let mut decoder = FrameDecoder::new();
+
+  decoder.push(&hex!("55aa0423"));
+  assert_eq!(decoder.next_frame(), None);
+
+  decoder.push(&hex!("011a3401"));
+  assert_eq!(decoder.next_frame(), None);
+
+  decoder.push(&hex!("88ff"));
+  assert_eq!(decoder.next_frame(), Some(hex!("55aa0423011a340188ff").to_vec()));
+  assert_eq!(decoder.pending(), 0);
+}
+
+#[test]
+fn it_splits_frames_received_together() {
+  let mut decoder = FrameDecoder::new();
+
+  decoder.push(&hex!("55aa032001100ebdff 5aa5013e20011a0283ff 55"));
+
+  assert_eq!(decoder.next_frame(), Some(hex!("55aa032001100ebdff").to_vec()));
+  assert_eq!(decoder.next_frame(), Some(hex!("5aa5013e20011a0283ff").to_vec()));
+  assert_eq!(decoder.next_frame(), None);
+  assert_eq!(decoder.pending(), 1);
+}
+
+#[test]
+fn it_resyncs_on_garbage() {
+  let mut decoder = FrameDecoder::new();
+
+  decoder.push(&hex!("00 01 55 02 aa 55"));
+  assert_eq!(decoder.next_frame(), None);
+  assert_eq!(decoder.pending(), 1); // might be start of the header
+
+  decoder.push(&hex!("aa032001100ebdff"));
+  assert_eq!(decoder.next_frame(), Some(hex!("55aa032001100ebdff").to_vec()));
+}
+
+#[test]
+fn it_knows_frame_length_for_every_header() {
+  assert_eq!(FrameKind::from_header(&hex!("55ab")), Some(FrameKind::Encrypted));
+  assert_eq!(FrameKind::from_header(&hex!("55aa")), Some(FrameKind::Legacy));
+  assert_eq!(FrameKind::from_header(&hex!("5aa5")), Some(FrameKind::Ninebot));
+  assert_eq!(FrameKind::from_header(&hex!("55ac")), None);
+
+  let mut decoder = FrameDecoder::new();
+  let encrypted = [&hex!("55ab 04 0000")[..], &[0u8; 13], &hex!("fbff")].concat();
+  decoder.push(&encrypted);
+  assert_eq!(decoder.next_frame().map(|frame| frame.len()), Some(20));
+}
+
+#[test]
+fn it_drops_frame_with_bad_checksum() {
+  let mut decoder = FrameDecoder::new();
+
+  decoder.push(&hex!("55aa032001100e0000 55aa032001100ebdff"));
+  assert_eq!(decoder.next_frame(), Some(hex!("55aa032001100ebdff").to_vec()));
+  assert_eq!(decoder.pending(), 0);
+}
+
+#[test]
+fn it_does_not_stall_on_false_header_with_big_length() {
+  let mut decoder = FrameDecoder::new();
+
+  decoder.push(&hex!("55aaff 55aa032001100ebdff"));
+  assert_eq!(decoder.next_frame(), Some(hex!("55aa032001100ebdff").to_vec()));
+  assert_eq!(decoder.next_frame(), None);
+}
+
+#[test]
+fn it_forgets_unfinished_frame_on_clear() {
+  let mut decoder = FrameDecoder::new();
+
+  decoder.push(&hex!("55aa0423011a"));
+  decoder.clear();
+  decoder.push(&hex!("55aa032001100ebdff"));
+  assert_eq!(decoder.next_frame(), Some(hex!("55aa032001100ebdff").to_vec()));
+}
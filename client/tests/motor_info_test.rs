@@ -4,7 +4,9 @@ use std::time::Duration;
 use m365::{
   Payload,
   MotorInfo,
-  BatteryInfo
+  BatteryInfo,
+  EscError,
+  EscWarning
 };
 
 #[test]
@@ -13,6 +15,10 @@ fn it_transform_payload_into_motor_info() {
   let payload = Payload::from(&bytes[0..]);
   let motor_info = MotorInfo::try_from(payload).unwrap();
 
+  assert_eq!(motor_info.status.error, EscError::None);
+  assert_eq!(motor_info.status.warning, EscWarning::None);
+  assert_eq!(motor_info.status.flags, 0x0800);
+  assert_eq!(motor_info.status.work_mode, 0);
   assert_eq!(motor_info.battery_percent, 64);
   assert_eq!(motor_info.speed_kmh, 0.0);
   assert_eq!(motor_info.speed_average_kmh, 0.0);
@@ -35,3 +41,17 @@ fn it_transform_payload_into_battery_info() {
   assert_eq!(battery.temperature_1, 45);
   assert_eq!(battery.temperature_2, 45);
 }
+
+#[test]
+fn it_decodes_esc_error_and_warning() {
+  let bytes = hex!("2301b0150002000000080040000000000000000000000000000000000000");
+  let payload = Payload::from(&bytes[0..]);
+  let motor_info = MotorInfo::try_from(payload).unwrap();
+
+  assert_eq!(motor_info.status.error, EscError::BmsCommunication);
+  assert_eq!(motor_info.status.error.code(), 21);
+  assert_eq!(motor_info.status.warning, EscWarning::Warning(2));
+  assert_eq!(motor_info.status.work_mode, 8);
+  assert_eq!(EscError::from(14), EscError::Throttle);
+  assert_eq!(EscError::from(99), EscError::Other(99));
+}
@@ -0,0 +1,48 @@
+use hex_literal::hex;
+
+use m365::mi_crypto::MiCryptoError;
+use m365::ninebot::{self, NinebotFrame, decode_frame};
+
+#[test]
+fn it_encodes_ninebot_frame() {
+  let frame = NinebotFrame {
+    src: ninebot::APP,
+    dst: ninebot::ESC,
+    cmd: ninebot::CMD_READ,
+    arg: 0x1a,
+    payload: vec![0x02]
+  };
+
+  assert_eq!(frame.as_bytes(), hex!("5aa5013e20011a0283ff"));
+}
+
+#[test]
+fn it_decodes_ninebot_reply() {
+  let frame = decode_frame(&hex!("5aa502203e041a34014cff")).unwrap();
+
+  assert_eq!(frame.src, ninebot::ESC);
+  assert_eq!(frame.dst, ninebot::APP);
+  assert_eq!(frame.cmd, ninebot::CMD_READ_REPLY);
+  assert_eq!(frame.payload, hex!("3401"));
+  assert_eq!(frame.to_uart_message().unwrap(), hex!("23011a3401"));
+}
+
+#[test]
+fn it_rejects_ninebot_frame_with_bad_checksum() {
+  let result = decode_frame(&hex!("5aa502203e041a34014dff"));
+
+  assert!(matches!(result, Err(MiCryptoError::BadChecksum)));
+}
+
+#[test]
+fn it_rejects_reply_from_address_without_direction() {
+  let frame = NinebotFrame {
+    src: 0xfe,
+    dst: ninebot::APP,
+    cmd: ninebot::CMD_READ_REPLY,
+    arg: 0x1a,
+    payload: vec![0x34, 0x01]
+  };
+
+  assert!(frame.to_uart_message().is_err());
+}
@@ -0,0 +1,93 @@
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+use m365::{LoginRequest, MiSession};
+use m365::consts::Registers;
+use m365::emulator::{ScooterEmulator, ScooterState};
+use m365::protocol::MiProtocol;
+use m365::transport::{
+  read_recording, recorded_rand_key, ChannelTransport, RecordedFrame, Recorder, RecordingTransport,
+  ReplayTransport, TrafficDirection
+};
+
+const TOKEN : [u8; 12] = [0x2a; 12];
+
+/**
+ * In-memory file, shared between recorder and test
+ */
+#[derive(Clone, Default)]
+struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Buffer {
+  fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+    self.0.lock().unwrap().write(data)
+  }
+
+  fn flush(&mut self) -> std::io::Result<()> {
+    Ok(())
+  }
+}
+
+/**
+ * Login into emulator and read motor info, while recording everything
+ */
+async fn record_session() -> Vec<RecordedFrame> {
+  let buffer = Buffer::default();
+  let (transport, peer) = ChannelTransport::pair();
+  let emulator = tokio::spawn(ScooterEmulator::new(peer, ScooterState::default()).with_token(&TOKEN).run());
+
+  let transport = RecordingTransport::new(transport, Recorder::new(buffer.clone()));
+  let mut session = LoginRequest::with_protocol(MiProtocol::with_transport(transport), &TOKEN).start().await.unwrap();
+  assert_eq!(session.motor_info().await.unwrap().battery_percent, 80);
+
+  drop(session);
+  emulator.await.unwrap().unwrap();
+
+  let recording = buffer.0.lock().unwrap().clone();
+  read_recording(recording.as_slice()).unwrap()
+}
+
+#[tokio::test]
+async fn it_records_writes_and_notifications() {
+  let frames = record_session().await;
+
+  let login = &frames[0];
+  assert_eq!(login.direction, TrafficDirection::Write);
+  assert_eq!(login.uuid, Registers::UPNP.to_uuid());
+  assert_eq!(login.data, [0x24, 0x00, 0x00, 0x00]);
+
+  assert!(frames.iter().any(|frame| frame.direction == TrafficDirection::Notification && frame.uuid == Registers::RX.to_uuid()));
+  assert!(frames.windows(2).all(|pair| pair[0].at_ms <= pair[1].at_ms));
+}
+
+#[tokio::test]
+async fn it_replays_recorded_session_without_scooter() {
+  let frames = record_session().await;
+  let rand_key = recorded_rand_key(&frames).expect("Login should be recorded");
+
+  let protocol = MiProtocol::with_transport(ReplayTransport::new(frames));
+  let mut session : MiSession<ReplayTransport> = LoginRequest::with_protocol(protocol, &TOKEN)
+    .with_rand_key(rand_key)
+    .start().await.unwrap();
+
+  let motor_info = session.motor_info().await.unwrap();
+  assert_eq!(motor_info.battery_percent, 80);
+
+  // Recording is over, as if scooter disconnected
+  assert!(session.motor_info().await.is_err());
+}
+
+#[test]
+fn it_reads_frames_as_json_lines() {
+  let recording = br#"
+{"at_ms":0,"direction":"write","uuid":"00000010-0000-1000-8000-00805f9b34fb","data":"24000000"}
+{"at_ms":12,"direction":"notification","uuid":"00000010-0000-1000-8000-00805f9b34fb","data":"00000101"}
+"#;
+
+  let frames = read_recording(&recording[..]).unwrap();
+
+  assert_eq!(frames.len(), 2);
+  assert_eq!(frames[1].direction, TrafficDirection::Notification);
+  assert_eq!(frames[1].data, [0x00, 0x00, 0x01, 0x01]);
+  assert!(read_recording(&br#"{"at_ms":0,"direction":"write","uuid":"00000010-0000-1000-8000-00805f9b34fb","data":"2"}"#[..]).is_err());
+}
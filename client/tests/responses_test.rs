@@ -7,3 +7,18 @@ fn it_guess_what_distance_is_left() {
   let distance_left_meters = u16::from_le_bytes(distance_bytes);
   assert_eq!(distance_left_meters, 2610);
 }
+
+#[test]
+fn it_decodes_firmware_versions() {
+  use m365::FirmwareVersion;
+
+  let version = FirmwareVersion::from(0x0134);
+  assert_eq!(version, FirmwareVersion::new(1, 3, 4));
+  assert_eq!(version.to_string(), "1.3.4");
+  assert_eq!("1.3.4".parse::<FirmwareVersion>().unwrap(), version);
+  assert!("1.3".parse::<FirmwareVersion>().is_err());
+
+  assert!(FirmwareVersion::from(0x0155) > version);
+  assert!(FirmwareVersion::from(0x0071) < version);
+  assert_eq!(serde_json::to_string(&version).unwrap(), "\"1.3.4\"");
+}
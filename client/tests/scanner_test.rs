@@ -0,0 +1,134 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use btleplug::api::BDAddr;
+use btleplug::platform::PeripheralId;
+use hex_literal::hex;
+use m365::consts::Registers;
+use m365::{DeviceTracker, ScannerError, ScannerEvent, ScooterModel, TrackedDevice, SCOOTER_MANUFACTURER_ID};
+
+fn device(addr: &str, name: Option<&str>, rssi: Option<i16>, last_seen: Instant) -> TrackedDevice {
+  let addr = BDAddr::from_str_delim(addr).unwrap();
+  let path = format!("/org/bluez/hci0/dev_{}", addr.to_string().replace(':', "_"));
+  let id : PeripheralId = serde_json::from_value(serde_json::json!({ "object_path": path })).unwrap();
+
+  TrackedDevice {
+    id,
+    addr,
+    name: name.map(|name| name.to_owned()),
+    rssi,
+    tx_power: None,
+    last_seen,
+    manufacturer_data: HashMap::new(),
+    services: Vec::new(),
+  }
+}
+
+#[test]
+fn it_discovers_and_updates_scooter() {
+  let now = Instant::now();
+  let mut tracker = DeviceTracker::new(Duration::from_secs(30));
+
+  let discovered = tracker.seen(device("D5:12:34:56:78:9A", Some("MIScooter1234"), Some(-80), now));
+  assert!(matches!(discovered, Some(ScannerEvent::DiscoveredScooter(ref scooter)) if scooter.rssi == Some(-80)));
+
+  // Update without name keeps the old one, rssi is refreshed
+  let updated = tracker.seen(device("D5:12:34:56:78:9A", None, Some(-62), now + Duration::from_secs(1)));
+  match updated {
+    Some(ScannerEvent::Updated(scooter)) => {
+      assert_eq!(scooter.name.as_deref(), Some("MIScooter1234"));
+      assert_eq!(scooter.rssi, Some(-62));
+      assert_eq!(scooter.last_seen, now + Duration::from_secs(1));
+    },
+    other => panic!("Expected update, got {:?}", other)
+  }
+}
+
+#[test]
+fn it_tracks_other_devices_silently() {
+  let mut tracker = DeviceTracker::new(Duration::from_secs(30));
+
+  assert!(tracker.seen(device("11:22:33:44:55:66", Some("Headphones"), Some(-50), Instant::now())).is_none());
+  assert_eq!(tracker.devices().count(), 1);
+}
+
+#[test]
+fn it_loses_silent_scooters() {
+  let now = Instant::now();
+  let mut tracker = DeviceTracker::new(Duration::from_secs(30));
+  tracker.seen(device("D5:12:34:56:78:9A", Some("MIScooter1234"), Some(-80), now));
+  tracker.seen(device("D5:12:34:56:78:9B", Some("MIScooter5678"), Some(-70), now + Duration::from_secs(20)));
+  tracker.seen(device("11:22:33:44:55:66", Some("Headphones"), None, now));
+
+  assert!(tracker.expire(now + Duration::from_secs(30)).is_empty());
+
+  let lost = tracker.expire(now + Duration::from_secs(31));
+  assert_eq!(lost.len(), 1);
+  assert!(matches!(&lost[0], ScannerEvent::Lost(scooter) if scooter.addr == BDAddr::from_str_delim("D5:12:34:56:78:9A").unwrap()));
+  assert_eq!(tracker.devices().count(), 1);
+
+  // Scooter coming back in range is discovered again
+  let back = tracker.seen(device("D5:12:34:56:78:9A", Some("MIScooter1234"), Some(-90), now + Duration::from_secs(40)));
+  assert!(matches!(back, Some(ScannerEvent::DiscoveredScooter(_))));
+}
+
+#[test]
+fn it_reports_scan_duration_and_other_scooters() {
+  let now = Instant::now();
+  let error = ScannerError::WaitForScooterFailed {
+    addr: BDAddr::from_str_delim("D5:12:34:56:78:9A").unwrap(),
+    scanned_for: Duration::from_secs(30),
+    seen: vec![
+      device("D5:12:34:56:78:9B", Some("MIScooter5678"), Some(-70), now),
+      device("D5:12:34:56:78:9C", Some("MIScooter9012"), Some(-75), now),
+    ]
+  };
+
+  assert_eq!(
+    error.to_string(),
+    "Could not find scooter with addr: D5:12:34:56:78:9A in 30s, other scooters seen: D5:12:34:56:78:9B, D5:12:34:56:78:9C"
+  );
+
+  let nothing = ScannerError::WaitForScooterFailed { addr: BDAddr::default(), scanned_for: Duration::from_millis(1500), seen: vec![] };
+  assert!(nothing.to_string().ends_with("in 1.5s, other scooters seen: none"));
+}
+
+#[test]
+fn it_identifies_renamed_scooter_by_manufacturer_data() {
+  let mut scooter = device("D5:12:34:56:78:9A", Some("My scooter"), Some(-60), Instant::now());
+  assert!(!scooter.is_scooter());
+
+  scooter.manufacturer_data.insert(SCOOTER_MANUFACTURER_ID, hex!("20 00000000 df").to_vec());
+  assert!(scooter.is_scooter());
+  assert_eq!(scooter.model(), Some(ScooterModel::M365));
+}
+
+#[test]
+fn it_does_not_take_unnamed_uart_device_for_scooter() {
+  let mut device = device("D5:12:34:56:78:9A", None, Some(-60), Instant::now());
+  device.services.push(Registers::UART.to_uuid());
+  assert!(!device.is_scooter());
+
+  // Same device tells its name
+  device.name = Some("MIScooter1234".to_owned());
+  assert!(device.is_scooter());
+  assert_eq!(device.model(), None);
+}
+
+#[test]
+fn it_discovers_scooter_when_advertisement_reveals_it() {
+  let now = Instant::now();
+  let mut tracker = DeviceTracker::new(Duration::from_secs(30));
+  assert!(tracker.seen(device("D5:12:34:56:78:9A", Some("My scooter"), Some(-80), now)).is_none());
+
+  let mut advertisement = device("D5:12:34:56:78:9A", None, Some(-78), now);
+  advertisement.manufacturer_data.insert(SCOOTER_MANUFACTURER_ID, hex!("20 00000000 df").to_vec());
+
+  match tracker.seen(advertisement) {
+    Some(ScannerEvent::DiscoveredScooter(scooter)) => {
+      assert_eq!(scooter.name.as_deref(), Some("My scooter"));
+      assert_eq!(scooter.model(), Some(ScooterModel::M365));
+    },
+    other => panic!("Expected discovered scooter, got {:?}", other)
+  }
+}
@@ -0,0 +1,97 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use m365::{LoginRequest, MiSession, Priority, SessionHandle, TailLight};
+use m365::emulator::{ScooterEmulator, ScooterState};
+use m365::protocol::MiProtocol;
+use m365::transport::ChannelTransport;
+
+const TOKEN : [u8; 12] = [0x2a; 12];
+
+async fn logged_in(state: ScooterState) -> (MiSession<ChannelTransport>, Arc<Mutex<ScooterState>>) {
+  let (transport, peer) = ChannelTransport::pair();
+  let emulator = ScooterEmulator::new(peer, state).with_token(&TOKEN);
+  let state = emulator.state();
+  tokio::spawn(emulator.run());
+
+  let request = LoginRequest::with_protocol(MiProtocol::with_transport(transport), &TOKEN);
+  let session = request.start().await.unwrap();
+
+  (session, state)
+}
+
+#[tokio::test]
+async fn it_shares_session_between_tasks() {
+  let (session, state) = logged_in(ScooterState::default()).await;
+  let (handle, actor) = SessionHandle::spawn(session);
+
+  let poller = handle.clone();
+  let polls = tokio::spawn(async move {
+    for _ in 0..5 {
+      assert_eq!(poller.motor_info().await.unwrap().battery_percent, 80);
+      assert_eq!(poller.battery_info().await.unwrap().capacity, 6240);
+    }
+  });
+
+  let commands = handle.clone();
+  let switch = tokio::spawn(async move {
+    commands.set_tail_light(TailLight::Always).await.unwrap();
+  });
+
+  polls.await.unwrap();
+  switch.await.unwrap();
+  assert!(matches!(handle.tail_light().await.unwrap(), TailLight::Always));
+  assert_eq!(state.lock().unwrap().tail_light, 2);
+
+  drop(handle);
+  let session = actor.await.unwrap();
+  assert!(session.counters().tx > 10);
+}
+
+#[tokio::test]
+async fn it_runs_high_priority_commands_first() {
+  let (session, _) = logged_in(ScooterState::default()).await;
+  let (handle, _actor) = SessionHandle::spawn(session);
+  let order = Arc::new(Mutex::new(Vec::new()));
+
+  // Keep actor busy, so next commands wait in queue
+  let busy = handle.clone();
+  let blocker = tokio::spawn(async move {
+    busy.run(Priority::Normal, |_| Box::pin(async {
+      tokio::time::sleep(Duration::from_millis(100)).await;
+      Ok(())
+    })).await
+  });
+  tokio::time::sleep(Duration::from_millis(10)).await;
+
+  let mut queued = Vec::new();
+  for (name, priority) in [("poll", Priority::Normal), ("tail light", Priority::High)] {
+    let handle = handle.clone();
+    let order = order.clone();
+    queued.push(tokio::spawn(async move {
+      handle.run(priority, move |session| Box::pin(async move {
+        order.lock().unwrap().push(name);
+        session.speed().await
+      })).await
+    }));
+    tokio::time::sleep(Duration::from_millis(10)).await;
+  }
+
+  blocker.await.unwrap().unwrap();
+  for command in queued {
+    command.await.unwrap().unwrap();
+  }
+
+  assert_eq!(*order.lock().unwrap(), vec!["tail light", "poll"]);
+}
+
+#[tokio::test]
+async fn it_fails_after_actor_stopped() {
+  let (session, _) = logged_in(ScooterState::default()).await;
+  let (handle, actor) = SessionHandle::spawn(session);
+
+  actor.abort();
+  let _ = actor.await;
+
+  assert!(handle.motor_info().await.unwrap_err().needs_reconnect());
+}
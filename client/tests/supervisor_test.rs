@@ -0,0 +1,184 @@
+use std::time::Duration;
+
+use m365::{LoginRequest, MiSession};
+use m365::emulator::{ScooterEmulator, ScooterState};
+use tokio::task::JoinHandle;
+use m365::error::{M365Error, Result};
+use m365::mi_crypto::MiCryptoError;
+use m365::protocol::MiProtocol;
+use m365::supervisor::{Backoff, Connector, Supervisor, SupervisorState};
+use m365::transport::ChannelTransport;
+
+const TOKEN : [u8; 12] = [0x2a; 12];
+
+type EmulatorTask = JoinHandle<anyhow::Result<ScooterEmulator>>;
+
+/**
+ * Scooter that can't be found for first scans and refuses first connections
+ */
+#[derive(Default)]
+struct FlakyConnector {
+  failing_scans: u32,
+  failing_connects: u32,
+  forgets_keys: bool, // Scooter loses login keys when link drops
+  scans: u32,
+  connects: u32,
+  logins: u32,
+  emulator: Option<EmulatorTask>,
+}
+
+impl Connector for FlakyConnector {
+  type Transport = ChannelTransport;
+
+  async fn scan(&mut self) -> Result<()> {
+    self.scans += 1;
+    if self.scans <= self.failing_scans {
+      return Err(M365Error::Timeout(Duration::from_secs(30)))
+    }
+    Ok(())
+  }
+
+  async fn connect(&mut self) -> Result<()> {
+    self.connects += 1;
+    if self.connects <= self.failing_connects {
+      return Err(M365Error::Disconnected("scooter is off".to_owned()))
+    }
+    Ok(())
+  }
+
+  async fn login(&mut self) -> Result<MiSession<ChannelTransport>> {
+    self.logins += 1;
+    let (transport, peer) = ChannelTransport::pair();
+    self.emulator = Some(tokio::spawn(ScooterEmulator::new(peer, ScooterState::default()).with_token(&TOKEN).run()));
+
+    LoginRequest::with_protocol(MiProtocol::with_transport(transport), &TOKEN).start().await
+  }
+
+  async fn reattach(&mut self) -> Result<MiProtocol<ChannelTransport>> {
+    let (transport, peer) = ChannelTransport::pair();
+    let previous = self.emulator.take().unwrap();
+    let forgets_keys = self.forgets_keys;
+
+    // Same scooter serves new link once old one is closed
+    self.emulator = Some(tokio::spawn(async move {
+      let emulator = previous.await.unwrap()?;
+      let emulator = match forgets_keys {
+        true => ScooterEmulator::new(peer, ScooterState::default()).with_token(&TOKEN),
+        false => emulator.reconnect(peer)
+      };
+      emulator.run().await
+    }));
+
+    Ok(MiProtocol::with_transport(transport))
+  }
+}
+
+fn fast_backoff() -> Backoff {
+  Backoff {
+    initial: Duration::from_millis(1),
+    max: Duration::from_millis(4),
+    multiplier: 2.0,
+    jitter: 0.0,
+    park_after: 3,
+    park_for: Duration::from_millis(50),
+  }
+}
+
+#[test]
+fn it_grows_backoff_up_to_max() {
+  let backoff = Backoff { jitter: 0.0, ..Backoff::default() };
+
+  assert_eq!(backoff.delay(1), Duration::from_secs(1));
+  assert_eq!(backoff.delay(2), Duration::from_secs(2));
+  assert_eq!(backoff.delay(4), Duration::from_secs(8));
+  assert_eq!(backoff.delay(10), Duration::from_secs(60));
+  assert_eq!(backoff.delay(u32::MAX), Duration::from_secs(60));
+}
+
+#[test]
+fn it_falls_back_to_max_delay_on_nan_backoff() {
+  let backoff = Backoff { multiplier: f64::NAN, jitter: f64::NAN, ..Backoff::default() };
+
+  assert_eq!(backoff.delay(3), Duration::from_secs(60));
+}
+
+#[test]
+fn it_randomizes_backoff_within_jitter() {
+  let backoff = Backoff { jitter: 0.5, ..Backoff::default() };
+
+  for _ in 0..100 {
+    let delay = backoff.delay(3);
+    assert!(delay >= Duration::from_secs(2) && delay <= Duration::from_secs(6), "{:?}", delay);
+  }
+}
+
+#[tokio::test]
+async fn it_retries_until_ready() {
+  let connector = FlakyConnector { failing_connects: 2, ..Default::default() };
+  let mut supervisor = Supervisor::new(connector, fast_backoff());
+
+  let mut session = supervisor.establish().await;
+
+  assert_eq!(supervisor.state(), SupervisorState::Ready);
+  assert_eq!(supervisor.connector().scans, 1);
+  assert_eq!(supervisor.connector().connects, 3);
+  assert_eq!(session.motor_info().await.unwrap().battery_percent, 80);
+}
+
+#[tokio::test]
+async fn it_parks_when_scooter_is_off() {
+  let connector = FlakyConnector { failing_scans: 4, ..Default::default() };
+  let mut supervisor = Supervisor::new(connector, fast_backoff());
+
+  let mut states = supervisor.subscribe();
+  let parked = tokio::spawn(async move {
+    states.wait_for(|state| matches!(state, SupervisorState::Parked { .. })).await.unwrap().clone()
+  });
+
+  supervisor.establish().await;
+
+  assert_eq!(parked.await.unwrap(), SupervisorState::Parked { retry_in: Duration::from_millis(50) });
+  assert_eq!(supervisor.connector().scans, 5);
+  assert_eq!(supervisor.state(), SupervisorState::Ready);
+}
+
+#[tokio::test]
+async fn it_resumes_session_after_link_drop() {
+  let mut supervisor = Supervisor::new(FlakyConnector::default(), fast_backoff());
+  let mut session = supervisor.establish().await;
+  session.motor_info().await.unwrap();
+
+  let mut session = supervisor.recover(session, &M365Error::Disconnected("link lost".to_owned())).await;
+
+  assert_eq!(supervisor.state(), SupervisorState::Ready);
+  assert_eq!(supervisor.connector().scans, 1);
+  assert_eq!(supervisor.connector().connects, 2);
+  assert_eq!(supervisor.connector().logins, 1);
+  assert!(session.counters().tx > 1);
+  assert!(session.battery_info().await.is_ok());
+}
+
+#[tokio::test]
+async fn it_logs_in_again_when_scooter_forgot_keys() {
+  let connector = FlakyConnector { forgets_keys: true, ..Default::default() };
+  let mut supervisor = Supervisor::new(connector, fast_backoff());
+  let session = supervisor.establish().await;
+
+  let mut session = supervisor.recover(session, &M365Error::Disconnected("link lost".to_owned())).await;
+
+  assert_eq!(supervisor.connector().logins, 2);
+  assert_eq!(session.counters().tx, 0);
+  assert!(session.battery_info().await.is_ok());
+}
+
+#[tokio::test]
+async fn it_does_not_resume_after_rejected_keys() {
+  let mut supervisor = Supervisor::new(FlakyConnector::default(), fast_backoff());
+  let session = supervisor.establish().await;
+
+  let error = M365Error::from(MiCryptoError::CounterExhausted);
+  let mut session = supervisor.recover(session, &error).await;
+
+  assert_eq!(supervisor.connector().logins, 2);
+  assert!(session.battery_info().await.is_ok());
+}
@@ -0,0 +1,162 @@
+use hex_literal::hex;
+use std::time::Duration;
+
+use m365::error::M365Error;
+use m365::MiSession;
+use m365::consts::{MiCommands, Registers};
+use m365::mi_crypto::{EncryptionKey, LoginKeychain, encrypt_uart, decrypt_uart, uart_counter};
+use m365::protocol::MiProtocol;
+use m365::transport::ChannelTransport;
+
+fn keychain() -> LoginKeychain {
+  LoginKeychain {
+    dev: EncryptionKey {
+      key: hex!("462f3fcc74200ca5f77ee2a581c42af0"),
+      iv: hex!("f8901a05")
+    },
+    app: EncryptionKey {
+      key: hex!("5066d82368375a1f6a0a3eba1317b525"),
+      iv: hex!("28cee53e")
+    }
+  }
+}
+
+#[tokio::test]
+async fn it_rejects_mi_parcel_frame_without_id() {
+  let (transport, peer) = ChannelTransport::pair();
+  let mut protocol = MiProtocol::with_transport(transport);
+
+  peer.notify(&Registers::AVDTP, &hex!("000000000100")).unwrap(); // one frame is coming
+  peer.notify(&Registers::AVDTP, &[0x01]).unwrap();
+
+  let error = protocol.read_mi_parcel(&Registers::AVDTP).await.unwrap_err();
+  assert!(matches!(error, M365Error::Parse { .. }));
+}
+
+#[tokio::test]
+async fn it_writes_mi_parcel_in_chunks() {
+  let (transport, mut peer) = ChannelTransport::pair();
+  let mut protocol = MiProtocol::with_transport(transport);
+
+  let data : Vec<u8> = (0..20).collect();
+  protocol.write_mi_parcel(&Registers::AVDTP, &data).await.unwrap();
+
+  let first = peer.recv().await.unwrap();
+  assert_eq!(first.uuid, Registers::AVDTP.to_uuid());
+  assert_eq!(first.value[0..2], [0x01, 0x00]);
+  assert_eq!(first.value[2..], data[0..18]);
+
+  let second = peer.recv().await.unwrap();
+  assert_eq!(second.value, [0x02, 0x00, 18, 19]);
+}
+
+#[tokio::test]
+async fn it_reads_mi_response_from_peer() {
+  let (transport, peer) = ChannelTransport::pair();
+  let mut protocol = MiProtocol::with_transport(transport);
+
+  peer.notify(&Registers::AVDTP, &MiCommands::RCV_RDY.to_bytes()).unwrap();
+
+  assert!(protocol.wait_for_scooter_to_receive_data().await.unwrap());
+}
+
+#[tokio::test]
+async fn it_drops_notifications_after_unsubscribe() {
+  let (transport, peer) = ChannelTransport::pair();
+  let mut protocol = MiProtocol::with_transport(transport);
+  protocol.dispose().await.unwrap();
+
+  peer.notify(&Registers::RX, &[0x01]).unwrap();
+  drop(peer);
+
+  assert!(protocol.next().await.is_none());
+}
+
+#[tokio::test]
+async fn it_runs_session_over_channel() {
+  let keys = keychain();
+  let (transport, mut peer) = ChannelTransport::pair();
+  let mut session = MiSession::with_protocol(MiProtocol::with_transport(transport), &keys);
+
+  let scooter = tokio::spawn(async move {
+    let request = peer.recv().await.unwrap();
+    assert_eq!(request.uuid, Registers::TX.to_uuid());
+
+    let command = decrypt_uart(&keys.app, &request.value).unwrap();
+    assert_eq!(command[0..4], hex!("20012502"));
+
+    let response = encrypt_uart(&keys.dev, &hex!("04230125320a"), 0, None);
+    let (head, tail) = response.split_at(10);
+    peer.notify(&Registers::RX, head).unwrap();
+    peer.notify(&Registers::RX, tail).unwrap();
+  });
+
+  let distance_left = session.distance_left().await.unwrap();
+  scooter.await.unwrap();
+
+  assert_eq!(distance_left, 26.1);
+}
+
+#[tokio::test]
+async fn it_drops_replayed_reply() {
+  let keys = keychain();
+  let (transport, mut peer) = ChannelTransport::pair();
+  let mut session = MiSession::with_protocol(MiProtocol::with_transport(transport), &keys);
+
+  let scooter = tokio::spawn(async move {
+    let response = encrypt_uart(&keys.dev, &hex!("04230125320a"), 3, None);
+
+    for expected in 0..2 {
+      let request = peer.recv().await.unwrap();
+      assert_eq!(uart_counter(&request.value), Some(expected));
+
+      peer.notify(&Registers::RX, &response).unwrap(); // same frame twice
+    }
+
+    // Fresh reply after the replayed one
+    peer.notify(&Registers::RX, &encrypt_uart(&keys.dev, &hex!("04230125e803"), 4, None)).unwrap();
+  });
+
+  assert_eq!(session.distance_left().await.unwrap(), 26.1);
+  assert_eq!(session.distance_left().await.unwrap(), 10.0);
+  assert_eq!(session.counters().tx, 2);
+  assert_eq!(session.counters().rx, Some(4));
+
+  scooter.await.unwrap();
+}
+
+#[tokio::test]
+async fn it_discards_reply_to_other_command() {
+  let keys = keychain();
+  let (transport, mut peer) = ChannelTransport::pair();
+  let mut session = MiSession::with_protocol(MiProtocol::with_transport(transport), &keys);
+
+  let scooter = tokio::spawn(async move {
+    peer.recv().await.unwrap();
+
+    // Late battery voltage reply, then the answer for distance left
+    peer.notify(&Registers::RX, &encrypt_uart(&keys.dev, &hex!("042501341010"), 0, None)).unwrap();
+    peer.notify(&Registers::RX, &encrypt_uart(&keys.dev, &hex!("04230125320a"), 1, None)).unwrap();
+  });
+
+  assert_eq!(session.distance_left().await.unwrap(), 26.1);
+  assert_eq!(session.counters().rx, Some(1));
+
+  scooter.await.unwrap();
+}
+
+#[tokio::test]
+async fn it_fails_without_matching_reply() {
+  let keys = keychain();
+  let (transport, mut peer) = ChannelTransport::pair();
+  let mut session = MiSession::with_protocol(MiProtocol::with_transport(transport), &keys);
+  session.set_reply_timeout(Duration::from_millis(100));
+
+  peer.notify(&Registers::RX, &encrypt_uart(&keys.dev, &hex!("042301b01010"), 0, None)).unwrap();
+
+  let error = session.distance_left().await.unwrap_err();
+  assert!(matches!(error, M365Error::NoReply { direction: 0x23, attribute: 0x25, .. }));
+  assert!(error.is_transient());
+
+  assert!(peer.recv().await.is_some()); // command was sent
+}
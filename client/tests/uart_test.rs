@@ -1,7 +1,7 @@
 use hex_literal::hex;
 use tracing::Level;
 use tracing_subscriber::fmt::format::FmtSpan;
-use m365::mi_crypto::{EncryptionKey, encrypt_uart, crc16, decrypt_uart};
+use m365::mi_crypto::{EncryptionKey, MiCryptoError, UartCounters, encrypt_uart, crc16, decrypt_uart, decrypt_uart_at, encode_uart, decode_uart, uart_counter};
 
 #[test]
 fn it_crc16() {
@@ -47,3 +47,104 @@ fn it_decrypt_uart() {
 
   assert_eq!("26354/00467353", text)
 }
+
+#[test]
+fn it_encodes_legacy_uart() {
+  let frame = encode_uart(&hex!("032001100e"));
+
+  assert_eq!(frame, hex!("55aa032001100ebdff"));
+}
+
+#[test]
+fn it_decodes_legacy_uart() {
+  let data = decode_uart(&hex!("55aa0423011a340188ff")).unwrap();
+
+  assert_eq!(data, hex!("23011a3401"));
+}
+
+#[test]
+fn it_rejects_legacy_uart_with_bad_checksum() {
+  let result = decode_uart(&hex!("55aa0423011a340189ff"));
+
+  assert!(matches!(result, Err(MiCryptoError::BadChecksum)));
+}
+
+#[test]
+fn it_uses_full_counter_in_nonce() {
+  let encryption_key = EncryptionKey {
+    key: hex!("5066d82368375a1f6a0a3eba1317b525"),
+    iv: hex!("28cee53e")
+  };
+
+  let ct = encrypt_uart(&encryption_key, &hex!("032001100e"), 0x0001_0002, None);
+  assert_eq!(uart_counter(&ct), Some(0x0002));
+
+  let decrypted = decrypt_uart_at(&encryption_key, &ct, 0x0001_0002).unwrap();
+  assert_eq!(decrypted[0..4], hex!("2001100e"));
+
+  assert!(decrypt_uart(&encryption_key, &ct).is_err()); // upper bits are part of nonce too
+}
+
+#[test]
+fn it_counts_frames_sent() {
+  let mut counters = UartCounters::default();
+
+  assert_eq!(counters.next_tx().unwrap(), 0);
+  assert_eq!(counters.next_tx().unwrap(), 1);
+  assert_eq!(counters.tx, 2);
+
+  counters.tx = u32::MAX;
+  assert!(matches!(counters.next_tx(), Err(MiCryptoError::CounterExhausted)));
+}
+
+#[test]
+fn it_rejects_replayed_counters() {
+  let mut counters = UartCounters::default();
+
+  let it = counters.expected_rx(5).unwrap();
+  counters.accept_rx(it);
+
+  assert_eq!(counters.expected_rx(9).unwrap(), 9);
+  assert!(matches!(counters.expected_rx(5), Err(MiCryptoError::ReplayedCounter { received: 5, last: 5 })));
+  assert!(matches!(counters.expected_rx(2), Err(MiCryptoError::ReplayedCounter { .. })));
+}
+
+#[test]
+fn it_handles_counter_wrap() {
+  let mut counters = UartCounters::default();
+  counters.accept_rx(0x0000_fffe);
+
+  assert_eq!(counters.expected_rx(0xffff).unwrap(), 0x0000_ffff);
+  assert_eq!(counters.expected_rx(0x0001).unwrap(), 0x0001_0001);
+
+  counters.accept_rx(0x0001_0001);
+  assert_eq!(counters.expected_rx(0x0002).unwrap(), 0x0001_0002);
+  assert!(counters.expected_rx(0xfffe).is_err());
+}
+
+#[test]
+fn it_crc16_long_frames_without_overflow() {
+  let bytes = [0xff; 300]; // sum is 0x12ad4, more than fits in 16 bits
+
+  assert_eq!(crc16(&bytes), hex!("2bd5"));
+}
+
+#[test]
+fn it_rejects_truncated_encrypted_frame() {
+  let encryption_key = EncryptionKey {
+    key: hex!("462f3fcc74200ca5f77ee2a581c42af0"),
+    iv: hex!("f8901a05")
+  };
+
+  let encrypted = hex!("55ab1001009a70888f3a27d8378bb07f7d8ce4cce88ab54a50595ad6c019c7f2");
+
+  assert!(matches!(decrypt_uart(&encryption_key, &encrypted[0..2]), Err(MiCryptoError::Truncated { .. })));
+  assert!(matches!(decrypt_uart(&encryption_key, &encrypted[0..20]), Err(MiCryptoError::Truncated { expected: 32, received: 20 })));
+  assert!(matches!(decrypt_uart(&encryption_key, &[&encrypted[..], &[0x00]].concat()), Err(MiCryptoError::BadLength { expected: 32, received: 33 })));
+
+  let mut corrupted = encrypted;
+  corrupted[31] ^= 0x01;
+  let error = decrypt_uart(&encryption_key, &corrupted).unwrap_err();
+  assert!(matches!(error, MiCryptoError::BadChecksum));
+  assert!(error.is_corrupted_frame());
+}
@@ -170,9 +170,10 @@ async def global_data(
     query = (
         select(
             GeneralInfoModel.time,
+            GeneralInfoModel.scooter,
             GeneralInfoModel.speed_kmh,
             GeneralInfoModel.trip_distance_m,
-            GeneralInfoModel.uptime_sec,
+            GeneralInfoModel.trip_time_sec,
             GeneralInfoModel.total_distance_m,
             GeneralInfoModel.est_distance_left_km,
             GeneralInfoModel.frame_temp,
@@ -187,8 +188,16 @@ async def global_data(
             LocationInfoModel.altitude,
             LocationInfoModel.gps_speed,
         )
-        .join(BatteryInfoModel, GeneralInfoModel.time == BatteryInfoModel.time)
-        .join(LocationInfoModel, GeneralInfoModel.time == LocationInfoModel.time)
+        .join(
+            BatteryInfoModel,
+            (GeneralInfoModel.time == BatteryInfoModel.time)
+            & GeneralInfoModel.scooter.is_not_distinct_from(BatteryInfoModel.scooter),
+        )
+        .join(
+            LocationInfoModel,
+            (GeneralInfoModel.time == LocationInfoModel.time)
+            & GeneralInfoModel.scooter.is_not_distinct_from(LocationInfoModel.scooter),
+        )
     )
 
     if start_time:
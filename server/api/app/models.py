@@ -1,6 +1,7 @@
 from sqlalchemy import (
     Column,
     Integer,
+    Text,
     TIMESTAMP,
     DECIMAL,
     SmallInteger,
@@ -17,9 +18,10 @@ class GeneralInfo(Base):
     __tablename__ = "general_info"
 
     time = Column(TIMESTAMP(timezone=True), primary_key=True, nullable=False)
+    scooter = Column(Text)
     speed_kmh = Column(DECIMAL(4, 2), nullable=False)
     trip_distance_m = Column(Integer, nullable=False)
-    uptime_sec = Column(Integer, nullable=False)
+    trip_time_sec = Column(Integer, nullable=False)
     total_distance_m = Column(Integer, nullable=False)
     est_distance_left_km = Column(DECIMAL(5, 2), nullable=False)
     frame_temp = Column(DECIMAL(4, 2), nullable=False)
@@ -29,6 +31,7 @@ class BatteryInfo(Base):
     __tablename__ = "battery_info"
 
     time = Column(TIMESTAMP(timezone=True), primary_key=True, nullable=False)
+    scooter = Column(Text)
     capacity = Column(SmallInteger, nullable=False)
     percent = Column(SmallInteger, nullable=False)
     voltage = Column(DECIMAL(4, 2), nullable=False)
@@ -42,6 +45,7 @@ class LocationInfo(Base):
     __tablename__ = "location_info"
 
     time = Column(TIMESTAMP(timezone=True), primary_key=True, nullable=False)
+    scooter = Column(Text)
     location = Column(
         Geography(geometry_type="POINT", srid=4326), nullable=False
     )  # GeoAlchemy2 https://geoalchemy-2.readthedocs.io/en/latest/index.html
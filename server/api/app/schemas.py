@@ -1,9 +1,9 @@
 # Pydantic models to serialize data into Python
-from pydantic import BaseModel, field_validator, PositiveInt, ConfigDict
+from pydantic import BaseModel, field_validator, NonNegativeInt, ConfigDict
 from datetime import datetime
 import json
 from enum import Enum
-from typing import Union, Literal
+from typing import Optional, Union, Literal
 from models import RelayPowerModes
 
 
@@ -30,9 +30,10 @@ class PowerResponse(BaseModel):
 
 class GeneralInfo(BaseModel):
     time: datetime
+    scooter: Optional[str] = None
     speed_kmh: float
     trip_distance_m: float
-    uptime_sec: PositiveInt
+    trip_time_sec: NonNegativeInt
     total_distance_m: float
     est_distance_left_km: float
     frame_temp: float
@@ -42,6 +43,7 @@ class GeneralInfo(BaseModel):
 
 class BatteryInfo(BaseModel):
     time: datetime
+    scooter: Optional[str] = None
     capacity: float
     percent: int
     voltage: float
@@ -55,6 +57,7 @@ class BatteryInfo(BaseModel):
 
 class LocationInfoGeoJSON(BaseModel):
     time: datetime
+    scooter: Optional[str] = None
     geojson: dict  # Here return a GeoJSON for simplicity
     altitude: float
     gps_speed: float
@@ -72,9 +75,10 @@ class LocationInfoGeoJSON(BaseModel):
 
 class UnifiedGlobalData(BaseModel):
     time: datetime
+    scooter: Optional[str] = None
     speed_kmh: float
     trip_distance_m: float
-    uptime_sec: PositiveInt
+    trip_time_sec: NonNegativeInt
     total_distance_m: float
     est_distance_left_km: float
     frame_temp: float
@@ -30,6 +30,8 @@ broker = config["mqtt"]["broker"]
 mqtt_port = config["mqtt"]["port"]
 client_id = config["mqtt"]["client"]
 topic = config["mqtt"]["topic"]
+# Client publishes faults of every scooter to "<topic>/events/<name>" by default
+events_topic = config["mqtt"].get("events_topic", f"{topic}/events/+")
 template = "postgresql://{user}:{password}@{hostname}:{port}/{dbname}"
 connection_str = template.format(
     user=config["database"]["user"],
@@ -47,6 +49,30 @@ def on_connect(client, userdata, flags, reason_code, properties):
         logging.exception("Failed to connect, return code %d\n", reason_code)
 
 
+def insert_fault_event(data):
+    try:
+        with conn.cursor() as cur:
+            logging.info("Executing fault_events query")
+            cur.execute(
+                """
+                INSERT INTO fault_events (time,scooter,kind,active,code,description)
+                        VALUES (%s,%s,%s,%s,%s,%s)
+                        """,
+                (
+                    data["timestamp"],
+                    data.get("scooter"),
+                    data["kind"],
+                    data["active"],
+                    data["code"],
+                    data["description"],
+                ),
+            )
+        conn.commit()
+    except Exception as e:
+        logging.error(f"Error executing fault_events query: {e}")
+        conn.rollback()
+
+
 def on_message(client, userdata, msg):
 
     logging.info("Message received!")
@@ -56,6 +82,14 @@ def on_message(client, userdata, msg):
 
     print(json.dumps(data))
 
+    if mqtt_client.topic_matches_sub(events_topic, msg.topic):
+        insert_fault_event(data)
+        return
+
+    if not mqtt_client.topic_matches_sub(topic, msg.topic):
+        logging.info(f"Skipping message from {msg.topic}")
+        return
+
     # Insert data into PostgreSQL
     try:
         with conn.cursor() as cur:
@@ -64,14 +98,15 @@ def on_message(client, userdata, msg):
             try:
                 cur.execute(
                     """
-                    INSERT INTO general_info (time,speed_kmh,trip_distance_m,uptime_sec,total_distance_m,est_distance_left_km,frame_temp)
-                            VALUES (%s,%s,%s,%s,%s,%s,%s)
+                    INSERT INTO general_info (time,scooter,speed_kmh,trip_distance_m,trip_time_sec,total_distance_m,est_distance_left_km,frame_temp)
+                            VALUES (%s,%s,%s,%s,%s,%s,%s,%s)
                             """,
                     (
                         data["timestamp"],
+                        data.get("scooter"),
                         data["speed_kmh"],
                         data["trip_distance_m"],
-                        data["uptime_sec"],
+                        data["trip_time_sec"],
                         data["total_distance_m"],
                         data["trip_distance_left_km"],
                         data["frame_temp"],
@@ -87,11 +122,12 @@ def on_message(client, userdata, msg):
             try:
                 cur.execute(
                     """
-                    INSERT INTO battery_info (time,capacity,percent,voltage,current,temp1,temp2)
-                            VALUES (%s,%s,%s,%s,%s,%s,%s)
+                    INSERT INTO battery_info (time,scooter,capacity,percent,voltage,current,temp1,temp2)
+                            VALUES (%s,%s,%s,%s,%s,%s,%s,%s)
                             """,
                     (
                         data["timestamp"],
+                        data.get("scooter"),
                         data["battery_info"]["capacity"],
                         data["battery_info"]["percent"],
                         data["battery_info"]["voltage"],
@@ -110,11 +146,12 @@ def on_message(client, userdata, msg):
             try:
                 cur.execute(
                     """
-                    INSERT INTO location_info (time,location,altitude,gps_speed)
-                            VALUES (%s,%s,%s,%s)
+                    INSERT INTO location_info (time,scooter,location,altitude,gps_speed)
+                            VALUES (%s,%s,%s,%s,%s)
                             """,
                     (
                         data["timestamp"],
+                        data.get("scooter"),
                         f"SRID=4326;POINT({data['gpsinfo']['longitude']} {data['gpsinfo']['latitude']})",
                         data["gpsinfo"]["altitude"],
                         data["gpsinfo"]["gps_speed"],
@@ -153,6 +190,7 @@ client.on_message = on_message
 client.connect(broker, mqtt_port)
 
 client.subscribe(topic, 0)
+client.subscribe(events_topic, 1)  # Same QoS the client publishes events with, they are not repeated
 
 # Start the MQTT client loop
 try:
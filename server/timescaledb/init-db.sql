@@ -6,9 +6,10 @@ CREATE EXTENSION IF NOT EXISTS postgis;
 --- Create general info table ---
 CREATE TABLE general_info (
     time timestamptz NOT NULL, --- Timestamp
+    scooter TEXT, --- Scooter name in the fleet
     speed_kmh DECIMAL(4,2) NOT NULL CHECK (speed_kmh >= 0), --- Speed km/h max: 99.99
     trip_distance_m INTEGER NOT NULL CHECK (trip_distance_m >= 0), --- Current trip distance (meters)
-    uptime_sec INTEGER NOT NULL CHECK (uptime_sec >= 0), --- Current uptime (seconds)
+    trip_time_sec INTEGER NOT NULL CHECK (trip_time_sec >= 0), --- Current trip riding time (seconds)
     total_distance_m INTEGER NOT NULL CHECK (total_distance_m >= 0), --- Lifetime distance (meters)
     est_distance_left_km DECIMAL(5,2) NOT NULL CHECK (est_distance_left_km >= 0),--- Estimated trip distance (kilometers)
     frame_temp DECIMAL(4,2) NOT NULL -- Frame temperature in celsius
@@ -18,10 +19,11 @@ SELECT create_hypertable('general_info',by_range('time'));
 
 -- Add comments to general_info columns
 COMMENT ON TABLE general_info IS 'Table storing scooter metrics';
+COMMENT ON COLUMN general_info.scooter IS 'Name of the scooter in the fleet';
 COMMENT ON COLUMN general_info.speed_kmh IS 'Speed of the scooter in km/h';
 COMMENT ON COLUMN general_info.total_distance_m IS 'Total distance covered by the scooter in m';
 COMMENT ON COLUMN general_info.trip_distance_m IS 'Distance covered during the current trip in m';
-COMMENT ON COLUMN general_info.uptime_sec IS 'Uptime of the scooter in seconds';
+COMMENT ON COLUMN general_info.trip_time_sec IS 'Riding time of the current trip in seconds';
 COMMENT ON COLUMN general_info.est_distance_left_km IS 'Estimated distance left in km';
 COMMENT ON COLUMN general_info.frame_temp IS 'Frame temperature in Celsius';
 
@@ -30,6 +32,7 @@ COMMENT ON COLUMN general_info.frame_temp IS 'Frame temperature in Celsius';
 --- Create battery info table ---
 CREATE TABLE battery_info (
     time timestamptz NOT NULL, --- Timestamp
+    scooter TEXT, --- Scooter name in the fleet
     capacity SMALLINT NOT NULL CHECK (capacity >=  0), --- Remaining mAh capacity
     percent SMALLINT NOT NULL CONSTRAINT valid_percentage CHECK (percent <=  100),
     voltage DECIMAL(4,2) NOT NULL CHECK (voltage >= 0), --- Voltage for all cells unified (Volts)
@@ -43,6 +46,7 @@ SELECT create_hypertable('battery_info',by_range('time'));
 
 -- Add comments to battery_info columns
 COMMENT ON TABLE battery_info IS 'Table storing battery metrics';
+COMMENT ON COLUMN battery_info.scooter IS 'Name of the scooter in the fleet';
 COMMENT ON COLUMN battery_info.capacity IS 'Battery capacity in mAh';
 COMMENT ON COLUMN battery_info.percent IS 'Battery charge percentage';
 COMMENT ON COLUMN battery_info.current IS 'current going through battery in Amps';
@@ -54,6 +58,7 @@ COMMENT ON COLUMN battery_info.temp2 IS 'Temperature sensor 2 reading in Celsius
 --- Create location info table ---
 CREATE TABLE location_info (
     time timestamptz NOT NULL, --- Timestamp
+    scooter TEXT, --- Scooter name in the fleet
     location geography(POINT,4326) NOT NULL, --- LON/LAT in PostGIS format
     altitude DECIMAL(6,2) NOT NULL, --Elevation above sea level in meters
     gps_speed DECIMAL(4,2) NOT NULL CHECK (gps_speed >= 0) -- GPS Measured Speed KM/h
@@ -63,7 +68,28 @@ SELECT create_hypertable('location_info',by_range('time'));
 
 -- Add comments to location_info columns
 COMMENT ON TABLE location_info IS 'Table storing GPS metrics';
+COMMENT ON COLUMN location_info.scooter IS 'Name of the scooter in the fleet';
 COMMENT ON COLUMN location_info.location IS 'Longitude Latitude in DD';
 COMMENT ON COLUMN location_info.altitude IS 'Altitude in meters';
 COMMENT ON COLUMN location_info.gps_speed IS 'GPS speed in km/h';
 
+
+--- Create fault events table ---
+CREATE TABLE fault_events (
+    time timestamptz NOT NULL, --- Timestamp
+    scooter TEXT, --- Scooter name in the fleet
+    kind TEXT NOT NULL CHECK (kind IN ('error', 'warning')),
+    active BOOLEAN NOT NULL, --- True when fault appeared, false when it was cleared
+    code INTEGER NOT NULL CHECK (code >= 0), --- Code shown on the dashboard
+    description TEXT NOT NULL
+);
+
+SELECT create_hypertable('fault_events',by_range('time'));
+
+-- Add comments to fault_events columns
+COMMENT ON TABLE fault_events IS 'Table storing errors and warnings appearing or clearing on the scooter dashboard';
+COMMENT ON COLUMN fault_events.scooter IS 'Name of the scooter in the fleet';
+COMMENT ON COLUMN fault_events.kind IS 'error or warning';
+COMMENT ON COLUMN fault_events.active IS 'True when fault appeared, false when it was cleared';
+COMMENT ON COLUMN fault_events.code IS 'Error or warning code shown on the dashboard';
+COMMENT ON COLUMN fault_events.description IS 'Description of the code';
@@ -0,0 +1,22 @@
+--- Add fault_events to a database created before it existed. Safe to run more than once ---
+CREATE TABLE IF NOT EXISTS fault_events (
+    time timestamptz NOT NULL, --- Timestamp
+    scooter TEXT, --- Scooter name in the fleet
+    kind TEXT NOT NULL CHECK (kind IN ('error', 'warning')),
+    active BOOLEAN NOT NULL, --- True when fault appeared, false when it was cleared
+    code INTEGER NOT NULL CHECK (code >= 0), --- Code shown on the dashboard
+    description TEXT NOT NULL
+);
+
+SELECT create_hypertable('fault_events',by_range('time'),if_not_exists => TRUE);
+
+COMMENT ON TABLE fault_events IS 'Table storing errors and warnings appearing or clearing on the scooter dashboard';
+COMMENT ON COLUMN fault_events.scooter IS 'Name of the scooter in the fleet';
+COMMENT ON COLUMN fault_events.kind IS 'error or warning';
+COMMENT ON COLUMN fault_events.active IS 'True when fault appeared, false when it was cleared';
+COMMENT ON COLUMN fault_events.code IS 'Error or warning code shown on the dashboard';
+COMMENT ON COLUMN fault_events.description IS 'Description of the code';
+
+GRANT INSERT ON fault_events TO bridge;
+GRANT SELECT ON fault_events TO grafana;
+GRANT SELECT ON fault_events TO api;
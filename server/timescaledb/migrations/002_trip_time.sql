@@ -0,0 +1,13 @@
+--- Client publishes trip_time_sec (riding time of the current trip) instead of uptime_sec. Safe to run more than once ---
+DO $$
+BEGIN
+    IF EXISTS (
+        SELECT 1 FROM information_schema.columns
+        WHERE table_name = 'general_info' AND column_name = 'uptime_sec'
+    ) THEN
+        ALTER TABLE general_info RENAME COLUMN uptime_sec TO trip_time_sec;
+    END IF;
+END
+$$;
+
+COMMENT ON COLUMN general_info.trip_time_sec IS 'Riding time of the current trip in seconds';
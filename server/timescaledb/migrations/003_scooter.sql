@@ -0,0 +1,8 @@
+--- Telemetry of several scooters is told apart by the scooter name. Safe to run more than once ---
+ALTER TABLE general_info ADD COLUMN IF NOT EXISTS scooter TEXT;
+ALTER TABLE battery_info ADD COLUMN IF NOT EXISTS scooter TEXT;
+ALTER TABLE location_info ADD COLUMN IF NOT EXISTS scooter TEXT;
+
+COMMENT ON COLUMN general_info.scooter IS 'Name of the scooter in the fleet';
+COMMENT ON COLUMN battery_info.scooter IS 'Name of the scooter in the fleet';
+COMMENT ON COLUMN location_info.scooter IS 'Name of the scooter in the fleet';
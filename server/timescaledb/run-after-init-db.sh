@@ -14,6 +14,7 @@ PGPASSWORD=${POSTGRES_PASSWORD} psql -v ON_ERROR_STOP=1 --username "$POSTGRES_US
     GRANT INSERT ON general_info TO bridge;
     GRANT INSERT ON battery_info TO bridge;
     GRANT INSERT ON location_info TO bridge;
+    GRANT INSERT ON fault_events TO bridge;
 
     --- Create read-only user for Grafana ---
     GRANT CONNECT ON DATABASE scooter_data TO grafana;
@@ -21,6 +22,7 @@ PGPASSWORD=${POSTGRES_PASSWORD} psql -v ON_ERROR_STOP=1 --username "$POSTGRES_US
     GRANT SELECT ON general_info TO grafana;
     GRANT SELECT ON battery_info TO grafana;
     GRANT SELECT ON location_info TO grafana;
+    GRANT SELECT ON fault_events TO grafana;
 
     --- Create read-only user for API ---
     GRANT CONNECT ON DATABASE scooter_data TO api;
@@ -28,5 +30,6 @@ PGPASSWORD=${POSTGRES_PASSWORD} psql -v ON_ERROR_STOP=1 --username "$POSTGRES_US
     GRANT SELECT ON general_info TO api;
     GRANT SELECT ON battery_info TO api;
     GRANT SELECT ON location_info TO api;
+    GRANT SELECT ON fault_events TO api;
 
 EOSQL